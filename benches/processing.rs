@@ -0,0 +1,180 @@
+//! Rough timings of whole runs over generated feeds, along with the peak
+//! memory of the worker models.
+//!
+//! Run with `cargo bench`.
+use std::env;
+use std::fs::{self, File};
+use std::process::Command;
+use std::time::{Duration, Instant};
+use solution::{
+    encode_transactions, execute_transactions, execute_transactions_mapped, execute_transactions_with_options,
+    ProcessingOptions, DEFAULT_READ_BUFFER_SIZE,
+};
+
+const ROWS: u32 = 1_000_000;
+const CLIENTS: u32 = 1_000;
+
+fn time<F: FnOnce()>(f: F) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+/// Every row is a withdrawal without funds, so every row is an error.
+fn every_row_errors() -> String {
+    let mut feed = String::from("type,client,tx,amount\n");
+    for tx in 0..ROWS {
+        feed.push_str(&format!("withdrawal,{},{},1.0\n", tx % CLIENTS, tx));
+    }
+    feed
+}
+
+/// Deposits spread over the clients, which every run accepts.
+fn deposits() -> String {
+    let mut feed = String::from("type,client,tx,amount\n");
+    for tx in 0..ROWS {
+        feed.push_str(&format!("deposit,{},{},1.5\n", tx % CLIENTS, tx));
+    }
+    feed
+}
+
+const MANY_CLIENTS: u32 = 100_000;
+
+/// A single deposit for each of a lot of clients.
+fn many_clients() -> String {
+    let mut feed = String::from("type,client,tx,amount\n");
+    for client in 0..MANY_CLIENTS {
+        feed.push_str(&format!("deposit,{},{},1.0\n", client, client));
+    }
+    feed
+}
+
+/// A withdrawal without funds for each of a lot of clients, so that every
+/// worker has an error to hand over.
+fn many_clients_error() -> String {
+    let mut feed = String::from("type,client,tx,amount\n");
+    for client in 0..MANY_CLIENTS {
+        feed.push_str(&format!("withdrawal,{},{},1.0\n", client, client));
+    }
+    feed
+}
+
+/// Lots of tiny deposits, all from the same client.
+fn micro_payments() -> String {
+    let mut feed = String::from("type,client,tx,amount\n");
+    for tx in 0..ROWS {
+        feed.push_str(&format!("deposit,1,{},0.01\n", tx));
+    }
+    feed
+}
+
+/// Peak resident memory of this process, in KiB. On Linux it is read from
+/// `VmHWM`, as `ru_maxrss` carries the peak of the parent over into the
+/// child across fork and exec.
+fn peak_memory() -> i64 {
+    let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let high_water_mark = status.lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|kib| kib.trim().trim_end_matches("kB").trim().parse().ok());
+    if let Some(kib) = high_water_mark {
+        return kib;
+    }
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) };
+    usage.ru_maxrss
+}
+
+/// Runs in its own process, as the peak memory of a process only grows.
+fn measure_memory(model: &str, threads: usize) {
+    let options = match model {
+        "sharded" => ProcessingOptions { max_workers: Some(threads), ..Default::default() },
+        _ => ProcessingOptions::default(),
+    };
+    let feed = many_clients();
+    let report = execute_transactions_with_options(feed.as_bytes(), threads, options);
+    assert_eq!(report.results.len(), MANY_CLIENTS as usize);
+    println!("{} workers ({} clients, {} threads): {} KiB peak memory", model, MANY_CLIENTS, threads, peak_memory());
+}
+
+/// Like [`measure_memory`], for micro-payments kept one by one or
+/// aggregated.
+fn measure_micro_payments(mode: &str, threads: usize) {
+    let options = match mode {
+        "aggregated" => ProcessingOptions { aggregate_deposits_below: Some(1.0), ..Default::default() },
+        _ => ProcessingOptions::default(),
+    };
+    let feed = micro_payments();
+    let report = execute_transactions_with_options(feed.as_bytes(), threads, options);
+    assert_eq!(report.transaction_counts.deposits, ROWS as u64);
+    println!("micro-payments {} ({} rows, {} threads): {} KiB peak memory", mode, ROWS, threads, peak_memory());
+}
+
+fn main() {
+    let threads = num_cpus::get();
+    if let Some(model) = env::args().find_map(|arg| arg.strip_prefix("--memory=").map(str::to_owned)) {
+        measure_memory(&model, threads);
+        return;
+    }
+    if let Some(mode) = env::args().find_map(|arg| arg.strip_prefix("--micro-payments=").map(str::to_owned)) {
+        measure_micro_payments(&mode, threads);
+        return;
+    }
+
+    // The memory is measured first, before this process grows the feeds.
+    let exe = env::current_exe().unwrap();
+    for model in ["per-client", "sharded"] {
+        let status = Command::new(&exe).arg(format!("--memory={}", model)).status().unwrap();
+        assert!(status.success());
+    }
+    for mode in ["kept", "aggregated"] {
+        let status = Command::new(&exe).arg(format!("--micro-payments={}", mode)).status().unwrap();
+        assert!(status.success());
+    }
+
+    let feed = every_row_errors();
+    let elapsed = time(|| {
+        let (_, errors) = execute_transactions(feed.as_bytes(), threads);
+        assert_eq!(errors.len(), ROWS as usize);
+    });
+    println!("every row errors ({} rows, {} threads): {:?}", ROWS, threads, elapsed);
+
+    let feed = many_clients_error();
+    let elapsed = time(|| {
+        let (_, errors) = execute_transactions(feed.as_bytes(), threads);
+        assert_eq!(errors.len(), MANY_CLIENTS as usize);
+    });
+    println!("every client errors ({} clients, {} threads): {:?}", MANY_CLIENTS, threads, elapsed);
+
+    let feed = deposits();
+    let mut encoded = vec![];
+    encode_transactions(feed.as_bytes(), &mut encoded).unwrap();
+    let csv = time(|| {
+        let report = execute_transactions_with_options(feed.as_bytes(), threads, ProcessingOptions::default());
+        assert_eq!(report.transaction_counts.deposits, ROWS as u64);
+    });
+    let binary = time(|| {
+        let options = ProcessingOptions { binary_input: true, ..Default::default() };
+        let report = execute_transactions_with_options(encoded.as_slice(), threads, options);
+        assert_eq!(report.transaction_counts.deposits, ROWS as u64);
+    });
+    println!("deposits as csv ({} rows, {} threads): {:?}", ROWS, threads, csv);
+    println!("deposits as binary ({} rows, {} threads): {:?}", ROWS, threads, binary);
+
+    let path = env::temp_dir().join("solution-bench-deposits.csv");
+    fs::write(&path, &feed).unwrap();
+    for buffer_size in [DEFAULT_READ_BUFFER_SIZE, 1024 * 1024] {
+        let elapsed = time(|| {
+            let options = ProcessingOptions { read_buffer_size: buffer_size, ..Default::default() };
+            let report = execute_transactions_with_options(File::open(&path).unwrap(), threads, options);
+            assert_eq!(report.transaction_counts.deposits, ROWS as u64);
+        });
+        println!("deposits from a file ({} rows, {} byte buffer): {:?}", ROWS, buffer_size, elapsed);
+    }
+    let elapsed = time(|| {
+        // Nothing else touches the file while it is mapped.
+        let report = unsafe { execute_transactions_mapped(&path, threads, ProcessingOptions::default()) }.unwrap();
+        assert_eq!(report.transaction_counts.deposits, ROWS as u64);
+    });
+    println!("deposits from a mapped file ({} rows): {:?}", ROWS, elapsed);
+    fs::remove_file(&path).unwrap();
+}
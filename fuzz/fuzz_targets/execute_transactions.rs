@@ -0,0 +1,23 @@
+//! Feeds arbitrary bytes to the whole pipeline, once as csv and once in the
+//! binary encoding.
+//!
+//! Run with `cargo fuzz run execute_transactions`.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use solution::{execute_transactions_with_options, ProcessingError, ProcessingOptions};
+
+fuzz_target!(|data: &[u8]| {
+    for binary_input in [false, true] {
+        let options = ProcessingOptions { binary_input, ..Default::default() };
+        let report = execute_transactions_with_options(data, 2, options);
+        // Workers catch their own panics, which would hide them from the
+        // fuzzer otherwise.
+        for e in &report.errors {
+            if let ProcessingError::WorkerPanicked { message, .. } = e {
+                panic!("{}", message);
+            }
+        }
+        report.settlement_summary();
+    }
+});
@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use crate::TransactionStatus;
+
+/// A transaction of an audited client changing status. `from` is empty
+/// when the transaction was first seen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatusTransition {
+    pub tx: u32,
+    pub from: Option<TransactionStatus>,
+    pub to: TransactionStatus,
+    /// Row of the input the change comes from.
+    pub row: usize,
+    /// The amount of the transaction as the input wrote it, with
+    /// `ProcessingOptions::retain_raw_amounts`, so a dispute can be checked
+    /// against the exact amount.
+    pub raw_amount: Option<String>,
+}
+
+/// The transitions of the clients in `ProcessingOptions::audit_clients`,
+/// in the order every client went through them. Shared with the workers,
+/// which fill it in as they go.
+#[derive(Clone, Debug, Default)]
+pub struct AuditTrail(Arc<Mutex<BTreeMap<u32, Vec<StatusTransition>>>>);
+
+impl AuditTrail {
+    pub fn transitions(&self) -> BTreeMap<u32, Vec<StatusTransition>> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub(crate) fn record<I: IntoIterator<Item = StatusTransition>>(&self, client: u32, transitions: I) {
+        let mut transitions = transitions.into_iter().peekable();
+        if transitions.peek().is_some() {
+            self.0.lock().unwrap().entry(client).or_default().extend(transitions);
+        }
+    }
+}
+
+/// The status every transaction ended in, by client, when given in
+/// `ProcessingOptions::final_statuses`. Shared with the workers, which
+/// fill it in once they are done with a client.
+#[derive(Clone, Debug, Default)]
+pub struct FinalStatuses(Arc<Mutex<BTreeMap<u32, BTreeMap<u32, TransactionStatus>>>>);
+
+impl FinalStatuses {
+    pub fn statuses(&self) -> BTreeMap<u32, BTreeMap<u32, TransactionStatus>> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub(crate) fn record<I: IntoIterator<Item = (u32, TransactionStatus)>>(&self, client: u32, statuses: I) {
+        self.0.lock().unwrap().entry(client).or_default().extend(statuses);
+    }
+}
@@ -0,0 +1,152 @@
+//! A compact binary encoding of the transactions, for feeds too large to
+//! parse as csv quickly.
+//!
+//! The input starts with [`MAGIC`], followed by fixed-width records of
+//! [`RECORD_LEN`] bytes: the type, the client and the tx as little-endian
+//! `u32`s and the amount as a little-endian `f32`, left at zero for the
+//! types without one. The bulk types leave the tx at zero as well.
+use std::io::{self, Read, Write};
+use crate::{ProcessingError, Transaction};
+use crate::transaction::RawTransaction;
+
+pub const MAGIC: &[u8; 4] = b"TXB1";
+pub const RECORD_LEN: usize = 13;
+
+const TYPES: [&str; 8] = ["deposit", "withdrawal", "fee", "dispute", "resolve", "chargeback", "resolve_all", "chargeback_all"];
+
+/// Encodes a single transaction as a record.
+pub fn encode_transaction<W: Write>(transaction: &Transaction, mut writer: W) -> io::Result<()> {
+    let (kind, client, tx, amount) = match transaction {
+        Transaction::Deposit { client, tx, amount, .. } => (0, client, tx, *amount),
+        Transaction::Withdrawal { client, tx, amount, .. } => (1, client, tx, *amount),
+        Transaction::Fee { client, tx, amount, .. } => (2, client, tx, *amount),
+        Transaction::Dispute { client, tx } => (3, client, tx, 0f32),
+        Transaction::Resolve { client, tx } => (4, client, tx, 0f32),
+        Transaction::Chargeback { client, tx } => (5, client, tx, 0f32),
+        Transaction::ResolveAll { client } => (6, client, &0, 0f32),
+        Transaction::ChargebackAll { client } => (7, client, &0, 0f32),
+    };
+    let mut record = [0u8; RECORD_LEN];
+    record[0] = kind;
+    record[1..5].copy_from_slice(&client.to_le_bytes());
+    record[5..9].copy_from_slice(&tx.to_le_bytes());
+    record[9..13].copy_from_slice(&amount.to_le_bytes());
+    writer.write_all(&record)
+}
+
+/// Converts a csv feed into the binary encoding. Stops at the first row
+/// that cannot be parsed. Returns how many transactions were written.
+///
+/// Amounts are written as read, so reading the encoding checks and rounds
+/// them like reading the csv would.
+pub fn encode_transactions<R: Read, W: Write>(reader: R, mut writer: W) -> Result<usize, ProcessingError> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+    writer.write_all(MAGIC)?;
+    let mut written = 0;
+    for raw_transaction in reader.deserialize::<RawTransaction>() {
+        let transaction: Transaction = raw_transaction?.try_into()?;
+        encode_transaction(&transaction, &mut writer)?;
+        written += 1;
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+/// Reads the records of a binary feed, once its header was checked.
+pub(crate) struct BinaryReader<R> {
+    reader: R,
+}
+
+impl<R: Read> BinaryReader<R> {
+    pub(crate) fn new(mut reader: R) -> Result<BinaryReader<R>, ProcessingError> {
+        let mut magic = [0u8; 4];
+        match reader.read_exact(&mut magic) {
+            Ok(()) if &magic == MAGIC => Ok(BinaryReader { reader }),
+            Ok(()) => Err(ProcessingError::NotBinaryInput),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(ProcessingError::NotBinaryInput),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The next record, decoded the same way as a csv row. `None` once the
+    /// input ends.
+    pub(crate) fn next_record(&mut self) -> Option<Result<Transaction, ProcessingError>> {
+        let mut record = [0u8; RECORD_LEN];
+        let mut filled = 0;
+        while filled < RECORD_LEN {
+            match self.reader.read(&mut record[filled..]) {
+                Ok(0) if filled == 0 => return None,
+                Ok(0) => return Some(Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())),
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+        Some(decode(&record))
+    }
+}
+
+fn decode(record: &[u8; RECORD_LEN]) -> Result<Transaction, ProcessingError> {
+    let kind = record[0] as usize;
+    let transaction_type = TYPES.get(kind).map_or_else(|| kind.to_string(), |name| (*name).to_owned());
+    let client = u32::from_le_bytes(record[1..5].try_into().unwrap());
+    let tx = u32::from_le_bytes(record[5..9].try_into().unwrap());
+    let amount = (kind < 3).then(|| f32::from_le_bytes(record[9..13].try_into().unwrap()));
+    Ok(RawTransaction::new(transaction_type, client, tx, amount).try_into()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::binary::{encode_transaction, encode_transactions, BinaryReader, MAGIC, RECORD_LEN};
+    use crate::{execute_transactions_with_options, ProcessingError, ProcessingOptions, ProcessingWarning, Transaction, TransactionParseError};
+
+    #[test]
+    fn test_csv_round_trips_through_the_binary_encoding() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.5\nwithdrawal,1,2,0.5\ndispute,1,1,\nchargeback,1,1,";
+        let mut encoded = vec![];
+        assert_eq!(encode_transactions(transactions.as_bytes(), &mut encoded).unwrap(), 4);
+        assert_eq!(encoded.len(), MAGIC.len() + 4 * RECORD_LEN);
+        let mut reader = BinaryReader::new(encoded.as_slice()).unwrap();
+        let decoded: Vec<Transaction> = std::iter::from_fn(|| reader.next_record()).map(Result::unwrap).collect();
+        assert_eq!(decoded, vec![
+            Transaction::deposit(1, 1, 1.5),
+            Transaction::withdrawal(1, 2, 0.5),
+            Transaction::dispute(1, 1),
+            Transaction::chargeback(1, 1),
+        ]);
+    }
+
+    #[test]
+    fn test_binary_input_is_checked_like_csv_input() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.23456\ndeposit,1,2,1.0";
+        let mut encoded = vec![];
+        encode_transactions(transactions.as_bytes(), &mut encoded).unwrap();
+        let options = ProcessingOptions {
+            binary_input: true,
+            reserved_tx_ids: HashSet::from([2]),
+            warn_on_precision_loss: true,
+            ..Default::default()
+        };
+        let report = execute_transactions_with_options(encoded.as_slice(), 1, options);
+        assert_eq!(report.results[0].total, 1.2346);
+        assert_eq!(report.warnings, vec![ProcessingWarning::PrecisionTruncated { tx: 1, original: 1.23456, rounded: 1.2346 }]);
+        assert!(matches!(report.errors[..], [ProcessingError::Parse(TransactionParseError::ReservedTxId(2))]));
+    }
+
+    #[test]
+    fn test_bad_records_are_reported() {
+        assert!(matches!(BinaryReader::new("type,client".as_bytes()), Err(ProcessingError::NotBinaryInput)));
+        let mut encoded = MAGIC.to_vec();
+        encode_transaction(&Transaction::deposit(1, 1, 1.0), &mut encoded).unwrap();
+        encoded[MAGIC.len()] = 9;
+        encoded.push(0);
+        let mut reader = BinaryReader::new(encoded.as_slice()).unwrap();
+        assert!(matches!(
+            reader.next_record(),
+            Some(Err(ProcessingError::Parse(TransactionParseError::InvalidTransactionType(kind)))) if kind == "9"
+        ));
+        assert!(matches!(reader.next_record(), Some(Err(ProcessingError::Io(_)))));
+        assert!(reader.next_record().is_none());
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::client_status::ClientAccount;
+use crate::engine::write_state;
+
+/// Receives the checkpoints asked for with
+/// `ProcessingOptions::checkpoint_every`, in the format of
+/// `Engine::save_state`, so `Engine::load_state` can resume from them.
+/// Workers call it as they go, so checkpoints may not arrive in order.
+#[derive(Clone)]
+pub struct CheckpointSink(Arc<dyn Fn(Vec<u8>) + Send + Sync>);
+
+impl CheckpointSink {
+    pub fn new<F: Fn(Vec<u8>) + Send + Sync + 'static>(f: F) -> CheckpointSink {
+        CheckpointSink(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for CheckpointSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CheckpointSink")
+    }
+}
+
+/// A checkpoint on its way through the workers. Each one adds its accounts
+/// when it gets to it, having applied every row before it and none after,
+/// and the last one hands the whole state over to the sink.
+#[derive(Debug)]
+pub(crate) struct Checkpoint {
+    rows: usize,
+    /// Workers yet to add their accounts.
+    pending: AtomicUsize,
+    accounts: Mutex<Vec<ClientAccount>>,
+    sink: CheckpointSink,
+}
+
+impl Checkpoint {
+    pub(crate) fn new(rows: usize, sink: CheckpointSink) -> Arc<Checkpoint> {
+        Arc::new(Checkpoint { rows, pending: AtomicUsize::new(0), accounts: Mutex::new(vec![]), sink })
+    }
+
+    /// Has to be called before the checkpoint is handed over to any of the
+    /// `workers`.
+    pub(crate) fn expect(&self, workers: usize) {
+        self.pending.store(workers, Ordering::SeqCst);
+        if workers == 0 {
+            self.finish();
+        }
+    }
+
+    pub(crate) fn add<'a, I: IntoIterator<Item = &'a ClientAccount>>(&self, accounts: I) {
+        self.accounts.lock().unwrap().extend(accounts.into_iter().cloned());
+        if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.finish();
+        }
+    }
+
+    fn finish(&self) {
+        let accounts = self.accounts.lock().unwrap();
+        let mut state = vec![];
+        // Writing to a vector does not fail.
+        write_state(&mut state, self.rows, accounts.iter()).unwrap();
+        (self.sink.0)(state);
+    }
+}
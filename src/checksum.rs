@@ -0,0 +1,164 @@
+use std::io::{self, Read};
+
+/// How `ProcessingOptions::checksum` sums up the input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChecksumAlgorithm {
+    /// The CRC-32 of zlib, gzip and zip files.
+    Crc32,
+    /// The Adler-32 of zlib streams, quicker but weaker on short inputs.
+    Adler32,
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const ADLER32_MODULUS: u32 = 65521;
+
+/// A checksum being computed over the bytes seen so far.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Checksum {
+    algorithm: ChecksumAlgorithm,
+    state: u32,
+    /// Whether the input was read to its end.
+    ended: bool,
+}
+
+impl Checksum {
+    pub(crate) fn new(algorithm: ChecksumAlgorithm) -> Checksum {
+        let state = match algorithm {
+            ChecksumAlgorithm::Crc32 => !0,
+            ChecksumAlgorithm::Adler32 => 1,
+        };
+        Checksum { algorithm, state, ended: false }
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        match self.algorithm {
+            ChecksumAlgorithm::Crc32 => {
+                for byte in bytes {
+                    self.state = CRC32_TABLE[((self.state ^ *byte as u32) & 0xFF) as usize] ^ (self.state >> 8);
+                }
+            }
+            ChecksumAlgorithm::Adler32 => {
+                let (mut a, mut b) = (self.state & 0xFFFF, self.state >> 16);
+                // 5552 bytes is the most that can be summed before b overflows.
+                for chunk in bytes.chunks(5552) {
+                    for byte in chunk {
+                        a += *byte as u32;
+                        b += a;
+                    }
+                    a %= ADLER32_MODULUS;
+                    b %= ADLER32_MODULUS;
+                }
+                self.state = (b << 16) | a;
+            }
+        }
+    }
+
+    pub(crate) fn value(&self) -> u32 {
+        match self.algorithm {
+            ChecksumAlgorithm::Crc32 => !self.state,
+            ChecksumAlgorithm::Adler32 => self.state,
+        }
+    }
+
+    /// The checksum of the whole input, unless reading stopped before its
+    /// end.
+    pub(crate) fn input_value(&self) -> Option<u32> {
+        self.ended.then(|| self.value())
+    }
+}
+
+/// Adds everything read through it to a checksum.
+pub(crate) struct ChecksumReader<'a, R> {
+    inner: R,
+    checksum: &'a mut Checksum,
+}
+
+impl<'a, R: Read> ChecksumReader<'a, R> {
+    pub(crate) fn new(inner: R, checksum: &'a mut Checksum) -> ChecksumReader<'a, R> {
+        ChecksumReader { inner, checksum }
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.checksum.update(&buf[..read]);
+        self.checksum.ended |= read == 0 && !buf.is_empty();
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checksum::{Checksum, ChecksumAlgorithm};
+    use crate::{execute_files, execute_source, execute_transactions_with_options, CsvSource, ErrorPolicy, ProcessingError, ProcessingOptions};
+
+    fn checksum(algorithm: ChecksumAlgorithm, bytes: &[u8]) -> u32 {
+        let mut checksum = Checksum::new(algorithm);
+        checksum.update(bytes);
+        checksum.value()
+    }
+
+    #[test]
+    fn test_checksums_match_the_reference_values() {
+        assert_eq!(checksum(ChecksumAlgorithm::Crc32, b"123456789"), 0xCBF4_3926);
+        assert_eq!(checksum(ChecksumAlgorithm::Adler32, b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(checksum(ChecksumAlgorithm::Crc32, b""), 0);
+        assert_eq!(checksum(ChecksumAlgorithm::Adler32, b""), 1);
+    }
+
+    #[test]
+    fn test_report_has_the_checksum_of_the_input() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,2,0.5\n";
+        for (algorithm, expected) in [(ChecksumAlgorithm::Crc32, 0x03DE_5E41), (ChecksumAlgorithm::Adler32, 0x4E83_1282)] {
+            // A tiny buffer makes the input go through in many reads.
+            let options = ProcessingOptions { checksum: Some(algorithm), read_buffer_size: 3, ..Default::default() };
+            let report = execute_transactions_with_options(transactions.as_bytes(), 2, options);
+            assert_eq!(report.input_checksum, Some(expected));
+        }
+        let report = execute_transactions_with_options(transactions.as_bytes(), 2, ProcessingOptions::default());
+        assert_eq!(report.input_checksum, None);
+    }
+
+    #[test]
+    fn test_input_read_in_part_has_no_checksum() {
+        let transactions = "type,client,tx,amount\nfoo,1,1,1.0\ndeposit,1,2,1.0\ndeposit,1,3,1.0\n";
+        let options = |error_policy| ProcessingOptions {
+            checksum: Some(ChecksumAlgorithm::Crc32),
+            error_policy,
+            read_buffer_size: 8,
+            ..Default::default()
+        };
+        let report = execute_transactions_with_options(transactions.as_bytes(), 1, options(ErrorPolicy::FailFast));
+        assert_eq!(report.input_checksum, None);
+        let report = execute_transactions_with_options(transactions.as_bytes(), 1, options(ErrorPolicy::ContinueAll));
+        assert_eq!(report.input_checksum, Some(checksum(ChecksumAlgorithm::Crc32, transactions.as_bytes())));
+    }
+
+    #[test]
+    fn test_checksum_of_a_source_is_rejected() {
+        let options = ProcessingOptions { checksum: Some(ChecksumAlgorithm::Crc32), ..Default::default() };
+        let report = execute_source(CsvSource::new("type,client,tx,amount\ndeposit,1,1,1.0".as_bytes()).unwrap(), 1, options.clone());
+        assert!(report.results.is_empty());
+        assert!(matches!(report.errors[..], [ProcessingError::ChecksumUnsupported]));
+        let report = execute_files(&[std::env::temp_dir().join("solution-no-such-file.csv")], 1, options);
+        assert!(matches!(report.errors[..], [ProcessingError::ChecksumUnsupported]));
+    }
+}
@@ -1,20 +1,77 @@
-use crossbeam_channel::Receiver;
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+#[cfg(feature = "parallel")]
+use crossbeam_channel::{Receiver, Sender};
+#[cfg(feature = "parallel")]
+use std::any::Any;
+#[cfg(feature = "parallel")]
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+#[cfg(feature = "parallel")]
+use std::collections::BTreeMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize, Serializer};
 use thiserror::Error;
-use crate::{Transaction, TransactionStatus};
-use crate::transaction::round;
+#[cfg(feature = "parallel")]
+use crate::ProcessingError;
+use crate::ProcessingWarning;
+use crate::audit::StatusTransition;
+use crate::checkpoint::Checkpoint;
+use crate::outcome::{record_outcome, Outcome, SkipReason, TransactionOutcome};
+use crate::{LockedDisputePolicy, NegativeTotalPolicy, ProcessingOptions, Transaction, TransactionKind, TransactionStatus};
+use crate::transaction::{from_minor_units, round, round_to, to_minor_units, MAX_AMOUNT};
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ClientStatus {
     pub(crate) id: u32,
     pub(crate) available: f32,
     pub(crate) held: f32,
+    /// The part of `held` that comes from disputed withdrawals.
+    pub(crate) held_from_withdrawals: f32,
     pub(crate) total: f32,
     pub(crate) locked: bool,
+    /// Most recent timestamp among the transactions of the client, when
+    /// the input has a timestamp column.
+    pub(crate) last_activity: Option<u64>,
 }
 
 impl ClientStatus {
+    pub fn held_from_deposits(&self) -> f32 {
+        round(self.held - self.held_from_withdrawals)
+    }
+
+    /// What the client can spend right now: `available`, plus the overdraft
+    /// it is allowed. A locked account can't spend anything, so it only
+    /// shows what it owes, if it is overdrawn.
+    pub fn effective_available(&self, options: &ProcessingOptions) -> f32 {
+        if self.locked {
+            self.available.min(0f32)
+        } else {
+            round(spendable(self.available, options))
+        }
+    }
+
+    pub fn held_from_withdrawals(&self) -> f32 {
+        self.held_from_withdrawals
+    }
+
+    pub fn last_activity(&self) -> Option<u64> {
+        self.last_activity
+    }
+
+    /// A copy with the balances rounded to `decimals` places, for
+    /// reporting.
+    pub fn rounded(&self, decimals: u32) -> ClientStatus {
+        ClientStatus {
+            available: round_to(self.available, decimals),
+            held: round_to(self.held, decimals),
+            held_from_withdrawals: round_to(self.held_from_withdrawals, decimals),
+            total: round_to(self.total, decimals),
+            ..self.clone()
+        }
+    }
+
     pub fn to_record(&self) -> Vec<String> {
         vec![
             self.id.to_string(),
@@ -24,6 +81,95 @@ impl ClientStatus {
             self.locked.to_string(),
         ]
     }
+
+    /// Reads a record written by [`ClientStatus::to_record`] back. Only the
+    /// output columns are known, so the rest is left empty.
+    pub fn from_record<S: AsRef<str>>(record: &[S]) -> Result<ClientStatus, ClientStatusParseError> {
+        let [id, available, held, total, locked] = record else {
+            return Err(ClientStatusParseError::WrongLength(record.len()));
+        };
+        Ok(ClientStatus {
+            id: parse_column("client", id)?,
+            available: parse_column("available", available)?,
+            held: parse_column("held", held)?,
+            held_from_withdrawals: 0f32,
+            total: parse_column("total", total)?,
+            locked: parse_column("locked", locked)?,
+            last_activity: None,
+        })
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ClientStatusParseError {
+    #[error("A client status has 5 columns, but the record has {0}")]
+    WrongLength(usize),
+    #[error("Invalid {column} {value:?}")]
+    InvalidColumn { column: &'static str, value: String },
+}
+
+fn parse_column<T: std::str::FromStr, S: AsRef<str>>(column: &'static str, value: &S) -> Result<T, ClientStatusParseError> {
+    let value = value.as_ref().trim();
+    value.parse().map_err(|_| ClientStatusParseError::InvalidColumn { column, value: value.to_owned() })
+}
+
+/// A client status with typed fields, named after the output columns, so
+/// it can be handed straight to a serializer. Amounts are written the way
+/// [`ClientStatus::to_record`] writes them, `2` rather than `2.0`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ClientStatusRecord {
+    pub client: u32,
+    #[serde(serialize_with = "serialize_amount")]
+    pub available: f32,
+    #[serde(serialize_with = "serialize_amount")]
+    pub held: f32,
+    #[serde(serialize_with = "serialize_amount")]
+    pub total: f32,
+    pub locked: bool,
+}
+
+impl ClientStatusRecord {
+    /// The header of the output, written even when there are no clients.
+    pub const COLUMNS: [&'static str; 5] = ["client", "available", "held", "total", "locked"];
+}
+
+fn serialize_amount<S: Serializer>(amount: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(amount)
+}
+
+/// Like [`ClientStatusRecord`], with the amounts as integer counts of
+/// ten-thousandths, the precision they are rounded to. `1.5` is `15000`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct MinorUnitsRecord {
+    pub client: u32,
+    pub available: i64,
+    pub held: i64,
+    pub total: i64,
+    pub locked: bool,
+}
+
+impl From<&ClientStatus> for MinorUnitsRecord {
+    fn from(status: &ClientStatus) -> MinorUnitsRecord {
+        MinorUnitsRecord {
+            client: status.id,
+            available: to_minor_units(status.available),
+            held: to_minor_units(status.held),
+            total: to_minor_units(status.total),
+            locked: status.locked,
+        }
+    }
+}
+
+impl From<&ClientStatus> for ClientStatusRecord {
+    fn from(status: &ClientStatus) -> ClientStatusRecord {
+        ClientStatusRecord {
+            client: status.id,
+            available: status.available,
+            held: status.held,
+            total: status.total,
+            locked: status.locked,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -32,6 +178,8 @@ pub enum ClientStatusError {
     WrongClientId(u32, u32),
     #[error("Transaction ID {0} not unique")]
     DuplicatedTransaction(u32),
+    #[error("Transaction ID {0} was reused after being disputed")]
+    TxIdReusedAfterDispute(u32),
     #[error("Negative amount {0} in transaction {1}")]
     NegativeAmount(f32, u32),
     #[error("Not enough founds to withdraw {0} during transaction {1}, with available founds {2}")]
@@ -40,257 +188,1078 @@ pub enum ClientStatusError {
     CustomerFrozen(u32, u32),
     #[error("Transaction {0} could not complete")]
     NonExistingTransaction(u32),
-    #[error("Cannot start a dispute on transaction {0} while being on status {:?}", 1)]
+    #[error("Cannot start a dispute on transaction {0} while being on status {1}")]
     InvalidStatusToStartDispute(u32, TransactionStatus),
-    #[error("Cannot resolve a dispute on transaction {0} while being on status {:?}", 1)]
+    #[error("Cannot resolve a dispute on transaction {0} while being on status {1}")]
     InvalidStatusToResolve(u32, TransactionStatus),
-    #[error("Cannot chargeback a dispute on transaction {0} while being on status {:?}", 1)]
+    #[error("Cannot chargeback a dispute on transaction {0} while being on status {1}")]
     InvalidStatusToChargeback(u32, TransactionStatus),
+    #[error("Transaction {0} is a dispute, resolve or chargeback, which cannot be disputed")]
+    DisputeOnNonMonetaryTransaction(u32),
+    #[error("Transaction {0} was already charged back and cannot be disputed again")]
+    CannotDisputeChargedBack(u32),
+    #[error("Transaction {0} is a withdrawal, and disputing withdrawals is disabled")]
+    CannotDisputeWithdrawal(u32),
+    #[error("Amount of transaction {0} is too small to be represented")]
+    SubPrecisionAmount(u32),
+    #[error("Transaction {tx} failed validation: {reason}")]
+    CustomValidationFailed { tx: u32, reason: String },
+    #[error("Dispute on transaction {0} is frozen along with its locked account")]
+    DisputeFrozen(u32),
+    #[error("Transaction {0} cannot be charged back this soon after its dispute started")]
+    ChargebackTooSoon(u32),
+    #[error("Transaction {0} is too old to be disputed")]
+    DisputeOutsideWindow(u32),
+    #[error("Amount {amount} of transaction {tx} exceeds the limit of {limit}")]
+    AmountLimitExceeded { tx: u32, amount: f32, limit: f32 },
+    #[error("Disputing transaction {tx} would hold more than the limit of {limit} for client {client}")]
+    HeldLimitExceeded { client: u32, tx: u32, limit: f32 },
+    #[error("Client {0} ended up with a negative total")]
+    NegativeTotal(u32),
+    #[error("Client {0} submitted more transactions than allowed in a run")]
+    RateLimitExceeded(u32),
+    #[error("Deposit {tx} would take the balance of client {client} above {ceiling}")]
+    BalanceCeilingExceeded { client: u32, tx: u32, ceiling: f32 },
 }
 
-pub fn build(
+impl ClientStatusError {
+    /// The client the error is about, when the error knows it.
+    pub fn client(&self) -> Option<u32> {
+        match self {
+            ClientStatusError::WrongClientId(_, client)
+            | ClientStatusError::CustomerFrozen(client, _)
+            | ClientStatusError::NegativeTotal(client)
+            | ClientStatusError::RateLimitExceeded(client)
+            | ClientStatusError::HeldLimitExceeded { client, .. }
+            | ClientStatusError::BalanceCeilingExceeded { client, .. } => Some(*client),
+            _ => None,
+        }
+    }
+
+    /// The transaction the error is about, when there is one.
+    pub fn tx(&self) -> Option<u32> {
+        match self {
+            ClientStatusError::WrongClientId(..) | ClientStatusError::NegativeTotal(_) | ClientStatusError::RateLimitExceeded(_) => None,
+            ClientStatusError::DuplicatedTransaction(tx)
+            | ClientStatusError::TxIdReusedAfterDispute(tx)
+            | ClientStatusError::NegativeAmount(_, tx)
+            | ClientStatusError::InsufficientFounds(_, tx, _)
+            | ClientStatusError::CustomerFrozen(_, tx)
+            | ClientStatusError::NonExistingTransaction(tx)
+            | ClientStatusError::InvalidStatusToStartDispute(tx, _)
+            | ClientStatusError::InvalidStatusToResolve(tx, _)
+            | ClientStatusError::InvalidStatusToChargeback(tx, _)
+            | ClientStatusError::CannotDisputeChargedBack(tx)
+            | ClientStatusError::CannotDisputeWithdrawal(tx)
+            | ClientStatusError::SubPrecisionAmount(tx)
+            | ClientStatusError::CustomValidationFailed { tx, .. }
+            | ClientStatusError::DisputeOnNonMonetaryTransaction(tx)
+            | ClientStatusError::DisputeFrozen(tx)
+            | ClientStatusError::ChargebackTooSoon(tx)
+            | ClientStatusError::DisputeOutsideWindow(tx)
+            | ClientStatusError::AmountLimitExceeded { tx, .. }
+            | ClientStatusError::HeldLimitExceeded { tx, .. }
+            | ClientStatusError::BalanceCeilingExceeded { tx, .. } => Some(*tx),
+        }
+    }
+}
+
+/// What the builder remembers about a deposit or withdrawal it has seen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransactionRecord {
+    pub(crate) status: TransactionStatus,
+    /// The amount in minor units, so disputes can move it without drifting.
+    pub(crate) amount: i64,
+    /// The part of the amount currently held by a dispute, in minor units.
+    pub(crate) held: i64,
+    /// The amount as it appeared in the input, when retained.
+    pub(crate) raw_amount: Option<String>,
+    /// Row of the input at which the current dispute started.
+    pub(crate) disputed_at: Option<usize>,
+    /// Row of the input the transaction was read from.
+    pub(crate) row: usize,
+}
+
+impl TransactionRecord {
+    /// Withdrawals are the only records taking funds away.
+    fn is_withdrawal(&self) -> bool {
+        self.amount < 0
+    }
+
+    fn is_disputed(&self) -> bool {
+        matches!(self.status, TransactionStatus::OnDispute | TransactionStatus::PartialHold)
+    }
+
+    /// Whether it is or was disputed, however the dispute ended.
+    fn was_disputed(&self) -> bool {
+        self.is_disputed() || matches!(self.status, TransactionStatus::Resolved | TransactionStatus::Chargeback)
+    }
+
+    fn new(row: usize, status: TransactionStatus, amount: i64, raw_amount: Option<String>) -> TransactionRecord {
+        TransactionRecord { status, amount, held: 0, raw_amount, disputed_at: None, row }
+    }
+}
+
+/// With an error limit, errors are reported in batches of this size, so
+/// the reader finds out about them while workers rarely need the shared
+/// error lock.
+#[cfg(feature = "parallel")]
+const ERROR_BATCH_SIZE: usize = 64;
+
+/// Buffers the errors of a single worker, which hands them back along
+/// with its statuses. With an error limit the reader needs to see them
+/// sooner, so they go to the shared list in batches.
+#[cfg(feature = "parallel")]
+struct ErrorBuffer {
+    shared: Arc<Mutex<Vec<ProcessingError>>>,
+    buffer: Vec<ProcessingError>,
+    batch_size: Option<usize>,
+}
+
+#[cfg(feature = "parallel")]
+impl ErrorBuffer {
+    fn new(shared: Arc<Mutex<Vec<ProcessingError>>>, options: &ProcessingOptions) -> ErrorBuffer {
+        let batch_size = options.error_policy.error_limit().map(|_| ERROR_BATCH_SIZE);
+        ErrorBuffer { shared, buffer: vec![], batch_size }
+    }
+
+    fn push(&mut self, e: ProcessingError) {
+        self.buffer.push(e);
+        if self.batch_size.is_some_and(|size| self.buffer.len() >= size) {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        self.shared.lock().unwrap().append(&mut self.buffer);
+    }
+
+    /// The errors that were not handed over to the shared list.
+    fn into_errors(self) -> Vec<ProcessingError> {
+        self.buffer
+    }
+}
+
+/// What a worker hands back once its channel closes: the statuses of its
+/// clients and its errors. They are merged with those of the other workers
+/// after the pool joins, so workers never wait on each other to report.
+#[cfg(feature = "parallel")]
+#[derive(Debug, Default)]
+pub struct WorkerOutput {
+    pub(crate) statuses: Vec<ClientStatus>,
+    pub(crate) errors: Vec<ProcessingError>,
+}
+
+/// Releases the funds held by a disputed record.
+fn resolve(available: &mut f32, held: &mut f32, record: &mut TransactionRecord) {
+    *held = from_minor_units(to_minor_units(*held).saturating_sub(record.held));
+    // A resolved withdrawal stands, so its funds stay gone.
+    if !record.is_withdrawal() {
+        *available = from_minor_units(to_minor_units(*available).saturating_add(record.held));
+    }
+    record.held = 0;
+    record.status = TransactionStatus::Resolved;
+}
+
+/// Takes away the funds held by a disputed record.
+fn charge_back(available: &mut f32, held: &mut f32, record: &mut TransactionRecord) {
+    *held = from_minor_units(to_minor_units(*held).saturating_sub(record.held));
+    // Charging back a withdrawal gives the funds back.
+    if record.is_withdrawal() {
+        *available = from_minor_units(to_minor_units(*available).saturating_add(record.held));
+    }
+    record.held = 0;
+    record.status = TransactionStatus::Chargeback;
+}
+
+/// Remembers the id of a resolve or chargeback that refers to no deposit or
+/// withdrawal. A dispute of a missing transaction is not remembered, so
+/// disputing it again is still about a missing transaction.
+fn note_operation(operation_ids: &mut HashSet<u32>, tx: u32, options: &ProcessingOptions) {
+    if options.track_operation_ids {
+        operation_ids.insert(tx);
+    }
+}
+
+/// Rows before the dispute, as adjustments and restored state can have, say
+/// nothing about its age, so they never count as too soon.
+fn is_chargeback_too_soon(record: &TransactionRecord, row: usize, options: &ProcessingOptions) -> bool {
+    match (options.min_dispute_age, record.disputed_at) {
+        (Some(min_age), Some(disputed_at)) => row.checked_sub(disputed_at).is_some_and(|age| age < min_age),
+        _ => false,
+    }
+}
+
+/// A transaction on its way to the account of its client.
+#[derive(Debug)]
+pub(crate) struct Routed {
+    /// Row of the input it was read from. Adjustments carry on counting
+    /// past the end of the input.
+    pub(crate) row: usize,
+    /// Line of the csv input the row starts on, when it comes from csv.
+    pub(crate) line: Option<u64>,
+    pub(crate) timestamp: Option<u64>,
+    /// Submitted after the input instead of read from it.
+    pub(crate) adjustment: bool,
+    pub(crate) transaction: Transaction,
+}
+
+/// What the reader hands over to the workers.
+#[derive(Debug)]
+pub(crate) enum Message {
+    Transaction(Routed),
+    /// Every row before the checkpoint was handed over already.
+    Checkpoint(Arc<Checkpoint>),
+}
+
+/// The balance of a client right after one of its transactions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountSnapshot {
+    pub client: u32,
+    /// Row of the input the transaction was read from.
+    pub row: usize,
+    pub available: f32,
+    pub held: f32,
+    pub locked: bool,
+}
+
+/// Receives the snapshots of `ProcessingOptions::debug_client`.
+#[derive(Clone)]
+pub struct SnapshotSink(Arc<dyn Fn(AccountSnapshot) + Send + Sync>);
+
+impl SnapshotSink {
+    pub fn new<F: Fn(AccountSnapshot) + Send + Sync + 'static>(f: F) -> SnapshotSink {
+        SnapshotSink(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for SnapshotSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SnapshotSink")
+    }
+}
+
+/// Receives the status of every client as soon as its worker is done with
+/// it, see `ProcessingOptions::status_sink`.
+#[derive(Clone)]
+pub struct StatusSink(Arc<dyn Fn(ClientStatus) + Send + Sync>);
+
+impl StatusSink {
+    pub fn new<F: Fn(ClientStatus) + Send + Sync + 'static>(f: F) -> StatusSink {
+        StatusSink(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for StatusSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StatusSink")
+    }
+}
+
+/// Checks a transaction before it is applied, see
+/// `ProcessingOptions::validators`. The error is the reason it was rejected.
+#[derive(Clone)]
+pub struct Validator(Arc<ValidatorFn>);
+
+type ValidatorFn = dyn Fn(&Transaction) -> Result<(), String> + Send + Sync;
+
+impl Validator {
+    pub fn new<F: Fn(&Transaction) -> Result<(), String> + Send + Sync + 'static>(f: F) -> Validator {
+        Validator(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for Validator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Validator")
+    }
+}
+
+/// The held limit, if holding `hold` more minor units on top of `held`
+/// goes over it.
+fn exceeded_held_limit(held: f32, hold: i64, options: &ProcessingOptions) -> Option<f32> {
+    options.max_held_per_client.filter(|limit| to_minor_units(held).saturating_add(hold) > to_minor_units(*limit))
+}
+
+/// The most the available funds can reach, if anything caps them.
+fn balance_limit(options: &ProcessingOptions) -> Option<f32> {
+    options.max_balance.or(options.saturating_arithmetic.then_some(MAX_AMOUNT))
+}
+
+/// What can be spent out of `available`, counting the overdraft.
+fn spendable(available: f32, options: &ProcessingOptions) -> f32 {
+    available + options.overdraft_limit.unwrap_or(0f32)
+}
+
+fn is_over_limit(amount: f32, options: &ProcessingOptions) -> bool {
+    options.max_transaction_amount.is_some_and(|limit| amount > limit)
+}
+
+/// The running balance of a single client, along with everything it needs to
+/// remember about its past transactions.
+#[derive(Clone, Debug)]
+pub(crate) struct ClientAccount {
     id: u32,
-    receiver: Receiver<Transaction>,
-    result: Arc<Mutex<Vec<ClientStatus>>>,
-    errors: Arc<Mutex<Vec<Box<dyn std::error::Error + Send>>>>,
-) {
-    let mut available = 0f32;
-    let mut held = 0f32;
-    let mut locked = false;
-    let mut transaction_statuses = HashMap::new();
+    available: f32,
+    held: f32,
+    locked: bool,
+    /// Whether any deposit went through, regardless of what happened to it.
+    deposited: bool,
+    last_activity: Option<u64>,
+    transaction_statuses: HashMap<u32, TransactionRecord>,
+    /// Transactions the client submitted in this run, which is not part of
+    /// the saved state.
+    submitted: u64,
+    /// Ids that only showed up on disputes, resolves and chargebacks, when
+    /// tracked. Not part of the saved state either.
+    operation_ids: HashSet<u32>,
+    /// Ids of the deposits added to the balance without being kept, so
+    /// that reusing one is still reported.
+    aggregated_ids: HashSet<u32>,
+    testing: TestingPattern,
+}
+
+/// Keeps count of the deposits taken straight back out by a withdrawal of
+/// the same amount, in a row, which is how stolen cards are tried out.
+/// Disputes and the like in between don't break a pair, and a deposit made
+/// while another one is pending leaves the earliest one pending.
+#[derive(Clone, Debug, Default)]
+struct TestingPattern {
+    /// Amount of the earliest deposit not withdrawn yet, in minor units.
+    deposit: Option<i64>,
+    pairs: usize,
+    suspicious: bool,
+}
+
+impl TestingPattern {
+    fn observe(&mut self, t: &Transaction, threshold: usize) {
+        match t {
+            Transaction::Deposit { amount, .. } => {
+                self.deposit.get_or_insert(to_minor_units(*amount));
+            }
+            Transaction::Withdrawal { amount, .. } if self.deposit == Some(to_minor_units(*amount)) => {
+                self.deposit = None;
+                self.pairs += 1;
+                self.suspicious |= self.pairs >= threshold;
+            }
+            Transaction::Dispute { .. } | Transaction::Resolve { .. } | Transaction::Chargeback { .. }
+            | Transaction::ResolveAll { .. } | Transaction::ChargebackAll { .. } => {}
+            _ => {
+                self.deposit = None;
+                self.pairs = 0;
+            }
+        }
+    }
+}
+
+impl ClientAccount {
+    pub(crate) fn new(id: u32) -> ClientAccount {
+        ClientAccount { id, available: 0f32, held: 0f32, locked: false, deposited: false, last_activity: None, transaction_statuses: HashMap::new(), submitted: 0, operation_ids: HashSet::new(), aggregated_ids: HashSet::new(), testing: TestingPattern::default() }
+    }
+
+    /// Rows are not necessarily in time order, so the latest timestamp
+    /// wins rather than the last one seen.
+    pub(crate) fn record_activity(&mut self, timestamp: u64) {
+        self.last_activity = self.last_activity.max(Some(timestamp));
+    }
+
+    pub(crate) fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The transactions currently on dispute, by tx id.
+    fn disputed(&self) -> Vec<u32> {
+        let mut disputed: Vec<u32> = self.transaction_statuses.iter()
+            .filter(|(_, record)| record.is_disputed())
+            .map(|(tx, _)| *tx)
+            .collect();
+        disputed.sort_unstable();
+        disputed
+    }
+
+    /// The current status of every transaction the given one may change:
+    /// its own, plus whichever disputes a chargeback may settle along with
+    /// it.
+    fn statuses_affected_by(&self, t: &Transaction) -> Vec<(u32, Option<TransactionStatus>)> {
+        let mut txs = match t {
+            Transaction::ResolveAll { .. } | Transaction::ChargebackAll { .. } => vec![],
+            t => vec![t.get_tx()],
+        };
+        if matches!(t, Transaction::Chargeback { .. } | Transaction::ResolveAll { .. } | Transaction::ChargebackAll { .. }) {
+            txs.extend(self.disputed().into_iter().filter(|tx| *tx != t.get_tx()));
+        }
+        txs.into_iter().map(|tx| (tx, self.transaction_statuses.get(&tx).map(|record| record.status))).collect()
+    }
+
+    /// Whether the client never had a deposit or withdrawal of its own,
+    /// only operations on transactions it never made.
+    pub(crate) fn is_phantom(&self) -> bool {
+        self.transaction_statuses.is_empty() && self.available == 0f32 && self.held == 0f32 && !self.locked
+    }
+
+    /// The status of a client nothing else will happen to, after handing
+    /// it over to whoever wants to know as soon as possible. Nothing, for a
+    /// phantom client that should not be reported.
+    pub(crate) fn finish(&self, options: &ProcessingOptions, warnings: &Mutex<Vec<ProcessingWarning>>) -> Option<ClientStatus> {
+        if !options.include_phantom_clients && self.is_phantom() {
+            return None;
+        }
+        if self.testing.suspicious {
+            warnings.lock().unwrap().push(ProcessingWarning::SuspiciousActivity(self.id));
+        }
+        if let Some(statuses) = &options.final_statuses {
+            statuses.record(self.id, self.transaction_statuses.iter().map(|(tx, record)| (*tx, record.status)));
+        }
+        let mut status = self.status();
+        if options.on_negative_total == NegativeTotalPolicy::Clamp && status.total < 0f32 {
+            warnings.lock().unwrap().push(ProcessingWarning::NegativeTotalClamped { client: status.id, total: status.total });
+            // What the client owes comes off the available funds, so the
+            // total still adds up.
+            status.available = round(status.available - status.total);
+            status.total = 0f32;
+        }
+        if let Some(StatusSink(sink)) = &options.status_sink {
+            sink(options.output_precision.map_or_else(|| status.clone(), |decimals| status.rounded(decimals)));
+        }
+        Some(status)
+    }
 
-    for t in receiver {
+    /// Applies a transaction read at `row` of the input. A failed transaction
+    /// may still be remembered, so later disputes can tell it apart from an
+    /// unknown one.
+    pub(crate) fn apply(&mut self, row: usize, t: Transaction, options: &ProcessingOptions) -> Result<(), ClientStatusError> {
+        let id = self.id;
         match t {
+            // A clean feed never reuses an id, let alone one that went
+            // through a dispute, which points at rows out of order.
             Transaction::Deposit { tx, client, .. }
-            | Transaction::Withdrawal { tx, client, ..} if client == id && transaction_statuses.contains_key(&tx) => {
-                errors.lock().unwrap().push(Box::new(ClientStatusError::DuplicatedTransaction(tx)));
+            | Transaction::Withdrawal { tx, client, ..} | Transaction::Fee { tx, client, .. }
+                if client == id && (self.transaction_statuses.contains_key(&tx) || self.aggregated_ids.contains(&tx)) => {
+                if self.transaction_statuses.get(&tx).is_some_and(TransactionRecord::was_disputed) {
+                    Err(ClientStatusError::TxIdReusedAfterDispute(tx))
+                } else {
+                    Err(ClientStatusError::DuplicatedTransaction(tx))
+                }
+            }
+            Transaction::Deposit { tx, amount, client, raw_amount } if client == id && is_over_limit(amount, options) => {
+                self.transaction_statuses.insert(tx, TransactionRecord::new(row, TransactionStatus::FailedDeposit, 0, raw_amount));
+                Err(ClientStatusError::AmountLimitExceeded { tx, amount, limit: options.max_transaction_amount.unwrap_or_default() })
             }
-            Transaction::Deposit { tx, amount, client } if client == id && (amount > 0f32 || amount.abs() < f32::EPSILON) => {
-                available += amount;
-                transaction_statuses.insert(tx, (TransactionStatus::Deposited, amount));
+            Transaction::Withdrawal { tx, amount, client, raw_amount } | Transaction::Fee { tx, amount, client, raw_amount } if client == id && is_over_limit(amount, options) => {
+                self.transaction_statuses.insert(tx, TransactionRecord::new(row, TransactionStatus::FailedWithdrawal, 0, raw_amount));
+                Err(ClientStatusError::AmountLimitExceeded { tx, amount, limit: options.max_transaction_amount.unwrap_or_default() })
             }
-            Transaction::Deposit { tx, client, amount } if client == id => {
-                errors.lock().unwrap().push(Box::new(ClientStatusError::NegativeAmount(amount, tx)));
-                transaction_statuses.insert(tx, (TransactionStatus::FailedDeposit, 0f32));
+            // Held funds are still the client's, so they count towards the
+            // balance as well.
+            Transaction::Deposit { tx, amount, client, raw_amount } if client == id && amount > 0f32
+                && balance_limit(options).is_some_and(|limit| self.available + self.held + amount > limit) => {
+                let limit = balance_limit(options).unwrap_or_default();
+                if options.saturating_arithmetic {
+                    // Only what was credited can be disputed later on.
+                    let credited = (limit - self.available - self.held).max(0f32);
+                    self.available += credited;
+                    self.deposited = true;
+                    self.transaction_statuses.insert(tx, TransactionRecord::new(row, TransactionStatus::Deposited, to_minor_units(credited), raw_amount));
+                    Ok(())
+                } else {
+                    self.transaction_statuses.insert(tx, TransactionRecord::new(row, TransactionStatus::FailedDeposit, 0, raw_amount));
+                    Err(ClientStatusError::BalanceCeilingExceeded { client, tx, ceiling: limit })
+                }
+            }
+            Transaction::Deposit { tx, amount, client, .. } if client == id && amount >= 0f32
+                && options.aggregate_deposits_below.is_some_and(|limit| amount < limit) => {
+                self.available += amount;
+                self.deposited = true;
+                self.aggregated_ids.insert(tx);
+                Ok(())
+            }
+            Transaction::Deposit { tx, amount, client, raw_amount } if client == id && (amount > 0f32 || amount.abs() < f32::EPSILON) => {
+                self.available += amount;
+                self.deposited = true;
+                self.transaction_statuses.insert(tx, TransactionRecord::new(row, TransactionStatus::Deposited, to_minor_units(amount), raw_amount));
+                Ok(())
+            }
+            Transaction::Deposit { tx, client, amount, raw_amount } if client == id => {
+                self.transaction_statuses.insert(tx, TransactionRecord::new(row, TransactionStatus::FailedDeposit, 0, raw_amount));
+                Err(ClientStatusError::NegativeAmount(amount, tx))
             }
-            Transaction::Withdrawal { tx, amount, client }
-                if client == id && !locked && (amount < available || (amount - available).abs() < f32::EPSILON) && (amount > 0f32 || amount.abs() < f32::EPSILON) => {
-                available -= amount;
-                transaction_statuses.insert(tx, (TransactionStatus::Withdrew, -amount));
+            Transaction::Withdrawal { tx, amount, client, raw_amount } | Transaction::Fee { tx, amount, client, raw_amount }
+                if client == id && !self.locked && (amount < spendable(self.available, options) || (amount - spendable(self.available, options)).abs() < f32::EPSILON)
+                    && (amount > 0f32 || amount.abs() < f32::EPSILON) => {
+                self.available -= amount;
+                self.transaction_statuses.insert(tx, TransactionRecord::new(row, TransactionStatus::Withdrew, -to_minor_units(amount), raw_amount));
+                Ok(())
             }
-            Transaction::Withdrawal { tx, client, amount } if client == id && !locked && amount < 0f32 => {
-                errors.lock().unwrap().push(Box::new(ClientStatusError::NegativeAmount(amount, tx)));
-                transaction_statuses.insert(tx, (TransactionStatus::FailedWithdrawal, 0f32));
+            Transaction::Withdrawal { tx, client, amount, raw_amount } | Transaction::Fee { tx, client, amount, raw_amount } if client == id && !self.locked && amount < 0f32 => {
+                self.transaction_statuses.insert(tx, TransactionRecord::new(row, TransactionStatus::FailedWithdrawal, 0, raw_amount));
+                Err(ClientStatusError::NegativeAmount(amount, tx))
             }
-            Transaction::Withdrawal { tx, client, amount } if client == id && !locked => {
-                errors.lock().unwrap().push(Box::new(ClientStatusError::InsufficientFounds(amount, tx, available)));
-                transaction_statuses.insert(tx, (TransactionStatus::FailedWithdrawal, 0f32));
+            Transaction::Withdrawal { tx, client, raw_amount, .. } | Transaction::Fee { tx, client, raw_amount, .. }
+                if client == id && !self.locked && options.ignore_withdrawal_without_balance && !self.deposited && self.available.abs() < f32::EPSILON => {
+                self.transaction_statuses.insert(tx, TransactionRecord::new(row, TransactionStatus::FailedWithdrawal, 0, raw_amount));
+                Ok(())
             }
-            Transaction::Withdrawal { tx, client, .. } if client == id => {
-                errors.lock().unwrap().push(Box::new(ClientStatusError::CustomerFrozen(client, tx)));
-                transaction_statuses.insert(tx, (TransactionStatus::FailedWithdrawal, 0f32));
+            Transaction::Withdrawal { tx, client, amount, raw_amount } | Transaction::Fee { tx, client, amount, raw_amount } if client == id && !self.locked => {
+                self.transaction_statuses.insert(tx, TransactionRecord::new(row, TransactionStatus::FailedWithdrawal, 0, raw_amount));
+                Err(ClientStatusError::InsufficientFounds(amount, tx, self.available))
+            }
+            Transaction::Withdrawal { tx, client, raw_amount, .. } | Transaction::Fee { tx, client, raw_amount, .. } if client == id => {
+                self.transaction_statuses.insert(tx, TransactionRecord::new(row, TransactionStatus::FailedWithdrawal, 0, raw_amount));
+                Err(ClientStatusError::CustomerFrozen(client, tx))
             }
             Transaction::Dispute { tx, client } if client == id => {
-                match transaction_statuses.get(&tx).cloned() {
-                    Some((TransactionStatus::Deposited, amount)) | Some((TransactionStatus::Resolved, amount)) => {
-                        held += amount;
-                        available -= amount;
-                        transaction_statuses.insert(tx, (TransactionStatus::OnDispute, amount));
+                match self.transaction_statuses.get_mut(&tx) {
+                    Some(record) if !options.dispute_withdrawals && record.is_withdrawal() => Err(ClientStatusError::CannotDisputeWithdrawal(tx)),
+                    Some(record) if options.dispute_lookback.is_some_and(|window| row.saturating_sub(record.row) > window) => {
+                        Err(ClientStatusError::DisputeOutsideWindow(tx))
                     }
-                    Some((status, _)) => {
-                        errors.lock().unwrap().push(Box::new(ClientStatusError::InvalidStatusToStartDispute(tx, status)));
+                    // Without capping, the whole amount is held even if part of
+                    // it was already withdrawn, which leaves available negative:
+                    // the client owes the difference.
+                    // The withdrawn funds are already gone, so holding them
+                    // leaves the available funds alone.
+                    Some(record) if options.dispute_withdrawals && record.is_withdrawal()
+                        && matches!(record.status, TransactionStatus::Withdrew | TransactionStatus::Resolved) => {
+                        if let Some(limit) = exceeded_held_limit(self.held, -record.amount, options) {
+                            return Err(ClientStatusError::HeldLimitExceeded { client, tx, limit });
+                        }
+                        self.held = from_minor_units(to_minor_units(self.held).saturating_sub(record.amount));
+                        record.held = -record.amount;
+                        record.disputed_at = Some(row);
+                        record.status = TransactionStatus::OnDispute;
+                        Ok(())
                     }
-                    None => {
-                        errors.lock().unwrap().push(Box::new(ClientStatusError::NonExistingTransaction(tx)));
+                    Some(record) if !record.is_withdrawal() && matches!(record.status, TransactionStatus::Deposited | TransactionStatus::Resolved) => {
+                        let available_units = to_minor_units(self.available);
+                        let hold = if options.cap_dispute_holds {
+                            record.amount.min(available_units).max(0)
+                        } else {
+                            record.amount
+                        };
+                        if let Some(limit) = exceeded_held_limit(self.held, hold, options) {
+                            return Err(ClientStatusError::HeldLimitExceeded { client, tx, limit });
+                        }
+                        self.held = from_minor_units(to_minor_units(self.held).saturating_add(hold));
+                        self.available = from_minor_units(available_units.saturating_sub(hold));
+                        record.held = hold;
+                        record.disputed_at = Some(row);
+                        record.status = if hold < record.amount {
+                            TransactionStatus::PartialHold
+                        } else {
+                            TransactionStatus::OnDispute
+                        };
+                        Ok(())
                     }
+                    Some(record) if record.status == TransactionStatus::Chargeback => Err(ClientStatusError::CannotDisputeChargedBack(tx)),
+                    Some(record) => Err(ClientStatusError::InvalidStatusToStartDispute(tx, record.status)),
+                    None if self.operation_ids.contains(&tx) => Err(ClientStatusError::DisputeOnNonMonetaryTransaction(tx)),
+                    None => Err(ClientStatusError::NonExistingTransaction(tx)),
                 }
             }
             Transaction::Resolve { tx, client } if client == id => {
-                match transaction_statuses.get(&tx).cloned() {
-                    Some((TransactionStatus::OnDispute, amount)) => {
-                        held -= amount;
-                        available += amount;
-                        transaction_statuses.insert(tx, (TransactionStatus::Resolved, amount));
-                    }
-                    Some((status, _)) => {
-                        errors.lock().unwrap().push(Box::new(ClientStatusError::InvalidStatusToResolve(tx, status)));
+                let frozen = self.locked && options.locked_disputes == LockedDisputePolicy::Frozen;
+                match self.transaction_statuses.get_mut(&tx) {
+                    Some(record) if frozen && record.is_disputed() => Err(ClientStatusError::DisputeFrozen(tx)),
+                    Some(record) if record.is_disputed() => {
+                        resolve(&mut self.available, &mut self.held, record);
+                        Ok(())
                     }
+                    Some(record) if record.status == TransactionStatus::Resolved && options.tolerate_redundant_resolves => Ok(()),
+                    Some(record) => Err(ClientStatusError::InvalidStatusToResolve(tx, record.status)),
                     None => {
-                        errors.lock().unwrap().push(Box::new(ClientStatusError::NonExistingTransaction(tx)));
+                        note_operation(&mut self.operation_ids, tx, options);
+                        Err(ClientStatusError::NonExistingTransaction(tx))
                     }
                 }
             }
             Transaction::Chargeback { tx, client } if client == id => {
-                match transaction_statuses.get(&tx).cloned() {
-                    Some((TransactionStatus::OnDispute, amount)) => {
-                        held -= amount;
-                        locked = true;
-                        transaction_statuses.insert(tx, (TransactionStatus::Chargeback, amount));
+                let frozen = self.locked && options.locked_disputes == LockedDisputePolicy::Frozen;
+                let charged_back = match self.transaction_statuses.get_mut(&tx) {
+                    Some(record) if frozen && record.is_disputed() => Err(ClientStatusError::DisputeFrozen(tx)),
+                    Some(record) if record.is_disputed() && is_chargeback_too_soon(record, row, options) => {
+                        Err(ClientStatusError::ChargebackTooSoon(tx))
                     }
-                    Some((status, _)) => {
-                        errors.lock().unwrap().push(Box::new(ClientStatusError::InvalidStatusToChargeback(tx, status)));
+                    Some(record) if record.is_disputed() => {
+                        charge_back(&mut self.available, &mut self.held, record);
+                        self.locked = true;
+                        Ok(())
                     }
+                    Some(record) => Err(ClientStatusError::InvalidStatusToChargeback(tx, record.status)),
                     None => {
-                        errors.lock().unwrap().push(Box::new(ClientStatusError::NonExistingTransaction(tx)));
+                        note_operation(&mut self.operation_ids, tx, options);
+                        Err(ClientStatusError::NonExistingTransaction(tx))
+                    }
+                };
+                if charged_back.is_ok() && options.locked_disputes == LockedDisputePolicy::AutoResolve {
+                    for record in self.transaction_statuses.values_mut().filter(|record| record.is_disputed()) {
+                        resolve(&mut self.available, &mut self.held, record);
                     }
                 }
+                charged_back
             }
-            Transaction::Deposit { client, .. } | Transaction::Withdrawal { client, ..} |
+            Transaction::ResolveAll { client } if client == id => {
+                let frozen = self.locked && options.locked_disputes == LockedDisputePolicy::Frozen;
+                if let Some(tx) = self.disputed().first().filter(|_| frozen) {
+                    return Err(ClientStatusError::DisputeFrozen(*tx));
+                }
+                for record in self.transaction_statuses.values_mut().filter(|record| record.is_disputed()) {
+                    resolve(&mut self.available, &mut self.held, record);
+                }
+                Ok(())
+            }
+            // Every dispute is charged back before the account gets locked, so
+            // none of them is held up by the policy for locked accounts. The
+            // ones too recent to be charged back stay on dispute, and the first
+            // of them is reported.
+            Transaction::ChargebackAll { client } if client == id => {
+                let frozen = self.locked && options.locked_disputes == LockedDisputePolicy::Frozen;
+                let disputed = self.disputed();
+                if let Some(tx) = disputed.first().filter(|_| frozen) {
+                    return Err(ClientStatusError::DisputeFrozen(*tx));
+                }
+                let mut too_soon = None;
+                for tx in disputed {
+                    let record = self.transaction_statuses.get_mut(&tx).unwrap();
+                    if is_chargeback_too_soon(record, row, options) {
+                        too_soon = too_soon.or(Some(tx));
+                    } else {
+                        charge_back(&mut self.available, &mut self.held, record);
+                        self.locked = true;
+                    }
+                }
+                if self.locked && options.locked_disputes == LockedDisputePolicy::AutoResolve {
+                    for record in self.transaction_statuses.values_mut().filter(|record| record.is_disputed()) {
+                        resolve(&mut self.available, &mut self.held, record);
+                    }
+                }
+                too_soon.map_or(Ok(()), |tx| Err(ClientStatusError::ChargebackTooSoon(tx)))
+            }
+            Transaction::Deposit { client, .. } | Transaction::Withdrawal { client, ..} | Transaction::Fee { client, .. } |
                 Transaction::Dispute { client, .. } | Transaction::Resolve { client, .. } |
-                Transaction::Chargeback { client, .. } => {
-                errors.lock().unwrap().push(Box::new(ClientStatusError::WrongClientId(id, client)));
+                Transaction::Chargeback { client, .. } | Transaction::ResolveAll { client } |
+                Transaction::ChargebackAll { client } => {
+                Err(ClientStatusError::WrongClientId(id, client))
             },
         }
     }
 
-    let mut result = result.lock().unwrap();
-    result.push(
-        ClientStatus { id, available: round(available), held: round(held), locked, total: round(held + available) }
-    );
+    /// Applies a transaction along with everything that goes with it: the
+    /// activity timestamp and the debug snapshot.
+    pub(crate) fn process(&mut self, routed: Routed, options: &ProcessingOptions) -> Result<(), ClientStatusError> {
+        let Routed { row, timestamp, adjustment, transaction: t, .. } = routed;
+        if let Some(timestamp) = timestamp {
+            self.record_activity(timestamp);
+        }
+        let outcome = options.outcome_sink.is_some().then(|| (Some(t.get_tx()).filter(|_| !t.kind().is_bulk()), t.kind()));
+        // Adjustments come from the operator, so they do not count.
+        if !adjustment {
+            self.submitted += 1;
+        }
+        let audit = options.audit_trail.as_ref()
+            .filter(|_| options.audit_clients.contains(&self.id))
+            .map(|trail| (trail, self.statuses_affected_by(&t)));
+        let observed = options.suspicious_pairs.map(|threshold| (threshold, t.clone()));
+        let skipped = if options.max_transactions_per_client.is_some_and(|limit| self.submitted > limit) {
+            Some(SkipReason::RateLimited)
+        } else {
+            (t.get_client() != self.id).then_some(SkipReason::WrongClient)
+        };
+        if let (Some(skipped), Some(reason)) = (&options.skipped, skipped) {
+            skipped.record(t.clone(), reason);
+        }
+        let rejection = options.validators.get(&t.kind()).and_then(|Validator(validate)| validate(&t).err());
+        let applied = if skipped == Some(SkipReason::RateLimited) {
+            Err(ClientStatusError::RateLimitExceeded(self.id))
+        } else if let Some(reason) = rejection {
+            Err(ClientStatusError::CustomValidationFailed { tx: t.get_tx(), reason })
+        } else {
+            self.apply(row, t, options)
+        };
+        if let Some((threshold, t)) = observed.filter(|_| applied.is_ok()) {
+            self.testing.observe(&t, threshold);
+        }
+        if let Some((trail, before)) = audit {
+            trail.record(self.id, before.into_iter().filter_map(|(tx, from)| {
+                let record = self.transaction_statuses.get(&tx)?;
+                (from != Some(record.status)).then(|| StatusTransition { tx, from, to: record.status, row, raw_amount: record.raw_amount.clone() })
+            }));
+        }
+        self.trace(row, options);
+        if let Some((tx, kind)) = outcome {
+            // Only a withdrawal skipped for lack of any deposit fails
+            // without an error.
+            let ignored = applied.is_ok() && matches!(kind, TransactionKind::Withdrawal | TransactionKind::Fee)
+                && tx.and_then(|tx| self.transaction_statuses.get(&tx)).is_some_and(|record| record.status == TransactionStatus::FailedWithdrawal);
+            let (outcome, reason) = match &applied {
+                Ok(()) if ignored => (Outcome::Ignored, Some("nothing deposited".to_owned())),
+                Ok(()) => (Outcome::Applied, None),
+                Err(e) => (Outcome::Rejected, Some(e.to_string())),
+            };
+            record_outcome(options, TransactionOutcome { row, tx, client: Some(self.id), kind: Some(kind), outcome, reason, adjustment });
+        }
+        applied
+    }
+
+    /// Hands the current balance over to the snapshot sink, if this is the
+    /// client being debugged.
+    pub(crate) fn trace(&self, row: usize, options: &ProcessingOptions) {
+        if options.debug_client != Some(self.id) {
+            return;
+        }
+        if let Some(SnapshotSink(sink)) = &options.snapshot_sink {
+            sink(AccountSnapshot {
+                client: self.id,
+                row,
+                available: round(self.available),
+                held: round(self.held),
+                locked: self.locked,
+            });
+        }
+    }
+
+    pub(crate) fn status(&self) -> ClientStatus {
+        let held_from_withdrawals = self.transaction_statuses.values()
+            .filter(|record| record.is_withdrawal())
+            .fold(0i64, |held, record| held.saturating_add(record.held));
+        ClientStatus {
+            id: self.id,
+            available: round(self.available),
+            held: round(self.held),
+            held_from_withdrawals: from_minor_units(held_from_withdrawals),
+            locked: self.locked,
+            last_activity: self.last_activity,
+            total: round(self.held + self.available),
+        }
+    }
+
+    /// Writes the account as an `account` line, followed by a `tx` line
+    /// for every transaction it remembers and, if any deposit was
+    /// aggregated, an `aggregated` line with their ids. Fields are
+    /// separated by tabs.
+    pub(crate) fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(
+            writer,
+            "account\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.id, self.available, self.held, self.locked, self.deposited, optional(self.last_activity),
+        )?;
+        let mut txs: Vec<&u32> = self.transaction_statuses.keys().collect();
+        txs.sort();
+        for tx in txs {
+            let record = &self.transaction_statuses[tx];
+            writeln!(
+                writer,
+                "tx\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                tx, record.status, record.amount, record.held, optional(record.disputed_at), optional(record.raw_amount.as_ref()), record.row,
+            )?;
+        }
+        if !self.aggregated_ids.is_empty() {
+            let mut aggregated: Vec<&u32> = self.aggregated_ids.iter().collect();
+            aggregated.sort();
+            let aggregated: Vec<String> = aggregated.into_iter().map(u32::to_string).collect();
+            writeln!(writer, "aggregated\t{}", aggregated.join(","))?;
+        }
+        Ok(())
+    }
+
+    /// Reads the fields of an `account` line written by `save`.
+    pub(crate) fn load(fields: &[&str]) -> Option<ClientAccount> {
+        match fields {
+            [id, available, held, locked, deposited, last_activity] => Some(ClientAccount {
+                id: id.parse().ok()?,
+                available: available.parse().ok()?,
+                held: held.parse().ok()?,
+                locked: locked.parse().ok()?,
+                deposited: deposited.parse().ok()?,
+                last_activity: parse_optional(last_activity)?,
+                transaction_statuses: HashMap::new(),
+                submitted: 0,
+                operation_ids: HashSet::new(),
+                aggregated_ids: HashSet::new(),
+                testing: TestingPattern::default(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Reads the ids of an `aggregated` line written by `save`.
+    pub(crate) fn load_aggregated(&mut self, ids: &str) -> Option<()> {
+        for id in ids.split(',') {
+            self.aggregated_ids.insert(id.parse().ok()?);
+        }
+        Some(())
+    }
+
+    /// Reads the fields of a `tx` line written by `save`. Lines saved
+    /// before the row was, count as read from the first row.
+    pub(crate) fn load_record(&mut self, fields: &[&str]) -> Option<()> {
+        let (fields, row) = match fields {
+            [fields @ .., row] if fields.len() == 6 => (fields, row.parse().ok()?),
+            _ => (fields, 0),
+        };
+        match fields {
+            [tx, status, amount, held, disputed_at, raw_amount] => {
+                let record = TransactionRecord {
+                    status: TransactionStatus::from_name(status)?,
+                    amount: amount.parse().ok()?,
+                    held: held.parse().ok()?,
+                    raw_amount: parse_optional(raw_amount)?,
+                    disputed_at: parse_optional(disputed_at)?,
+                    row,
+                };
+                self.transaction_statuses.insert(tx.parse().ok()?, record);
+                Some(())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Missing values are saved as `-`.
+fn optional<T: fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "-".to_owned(), |value| value.to_string())
+}
+
+fn parse_optional<T: std::str::FromStr>(field: &str) -> Option<Option<T>> {
+    match field {
+        "-" => Some(None),
+        field => field.parse().ok().map(Some),
+    }
+}
+
+#[cfg(feature = "parallel")]
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload.downcast_ref::<&str>().map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_owned())
+}
+
+#[cfg(feature = "parallel")]
+pub fn build(
+    id: u32,
+    receiver: Receiver<Message>,
+    result: Sender<WorkerOutput>,
+    errors: Arc<Mutex<Vec<ProcessingError>>>,
+    warnings: Arc<Mutex<Vec<ProcessingWarning>>>,
+    options: Arc<ProcessingOptions>,
+) {
+    let mut account = ClientAccount::new(id);
+    let mut errors = ErrorBuffer::new(errors, &options);
+
+    // A panic would otherwise kill the worker silently and lose the client
+    // altogether. The account is left as the failing transaction found it,
+    // which is still worth reporting alongside the error.
+    let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+        for message in receiver {
+            match message {
+                Message::Transaction(routed) => {
+                    let (row, line) = (routed.row, routed.line);
+                    if let Err(e) = account.process(routed, &options) {
+                        errors.push(ProcessingError::ClientAt { client: id, row, line, source: e });
+                    }
+                }
+                Message::Checkpoint(checkpoint) => checkpoint.add([&account]),
+            }
+        }
+    }));
+    if let Err(payload) = outcome {
+        errors.push(ProcessingError::WorkerPanicked { client: id, message: panic_message(payload.as_ref()) });
+    }
+
+    let statuses = account.finish(&options, &warnings).into_iter().collect();
+    // The receiving end only goes away when nobody is interested in the
+    // results anymore, so there is no one left to report a failure to.
+    let _ = result.send(WorkerOutput { statuses, errors: errors.into_errors() });
+}
+
+/// Like [`build`], but for every client routed to this worker, with the
+/// statuses in client id order once the channel closes.
+#[cfg(feature = "parallel")]
+pub fn build_shard(
+    receiver: Receiver<Message>,
+    result: Sender<WorkerOutput>,
+    errors: Arc<Mutex<Vec<ProcessingError>>>,
+    warnings: Arc<Mutex<Vec<ProcessingWarning>>>,
+    options: Arc<ProcessingOptions>,
+) {
+    let mut accounts: BTreeMap<u32, ClientAccount> = BTreeMap::new();
+    let mut errors = ErrorBuffer::new(errors, &options);
+
+    for message in receiver {
+        let routed = match message {
+            Message::Transaction(routed) => routed,
+            Message::Checkpoint(checkpoint) => {
+                checkpoint.add(accounts.values());
+                continue;
+            }
+        };
+        let (client, row, line) = (routed.transaction.get_client(), routed.row, routed.line);
+        let account = accounts.entry(client).or_insert_with(|| ClientAccount::new(client));
+        // Catching every transaction keeps a panic from taking the other
+        // clients of the shard down with it.
+        match panic::catch_unwind(AssertUnwindSafe(|| account.process(routed, &options))) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => errors.push(ProcessingError::ClientAt { client, row, line, source: e }),
+            Err(payload) => errors.push(ProcessingError::WorkerPanicked { client, message: panic_message(payload.as_ref()) }),
+        }
+    }
+
+    let statuses = accounts.values().filter_map(|account| account.finish(&options, &warnings)).collect();
+    let _ = result.send(WorkerOutput { statuses, errors: errors.into_errors() });
 }
 
 #[cfg(test)]
 mod tests {
-    use std::error::Error;
-    use std::sync::{Arc, Mutex};
-    use std::thread;
-    use std::thread::JoinHandle;
-    use crossbeam_channel::unbounded;
-    use crate::client_status::{build, ClientStatusError};
-    use crate::{ClientStatus, Transaction, TransactionStatus};
+    use crate::client_status::{ClientAccount, ClientStatusError, ClientStatusParseError, ClientStatusRecord, MinorUnitsRecord};
+    use crate::{ClientStatus, LockedDisputePolicy, ProcessingOptions, Transaction, TransactionStatus};
 
     #[test]
     fn four_point_precision() {
         let transactions = vec![
-            Transaction::Deposit { client: 1, tx: 1, amount: 1.123123f32 },
-            Transaction::Deposit { client: 1, tx: 3, amount: 2.111111f32 },
-            Transaction::Withdrawal { client: 1, tx: 4, amount: 1.222222f32 },
+            Transaction::Deposit { client: 1, tx: 1, amount: 1.123123f32, raw_amount: None },
+            Transaction::Deposit { client: 1, tx: 3, amount: 2.111111f32, raw_amount: None },
+            Transaction::Withdrawal { client: 1, tx: 4, amount: 1.222222f32, raw_amount: None },
         ];
         test_successful_transaction(1, transactions, ClientStatus {
             id: 1,
             available: 2.012f32,
             held: 0f32,
+            held_from_withdrawals: 0f32,
             total: 2.012f32,
             locked: false,
+            last_activity: None,
         });
     }
 
     #[test]
     fn test_deposit_and_withdrawal_without_failed_withdrawal() {
         let transactions = vec![
-            Transaction::Deposit { client: 1, tx: 1, amount: 1f32 },
-            Transaction::Deposit { client: 1, tx: 3, amount: 2f32 },
-            Transaction::Withdrawal { client: 1, tx: 4, amount: 1.5f32 },
+            Transaction::Deposit { client: 1, tx: 1, amount: 1f32, raw_amount: None },
+            Transaction::Deposit { client: 1, tx: 3, amount: 2f32, raw_amount: None },
+            Transaction::Withdrawal { client: 1, tx: 4, amount: 1.5f32, raw_amount: None },
         ];
         test_successful_transaction(1, transactions, ClientStatus {
             id: 1,
             available: 1.5f32,
             held: 0f32,
+            held_from_withdrawals: 0f32,
             total: 1.5f32,
             locked: false,
+            last_activity: None,
         });
     }
 
     #[test]
     fn test_deposit_and_withdrawal_with_failed_withdrawal() {
         let transactions = vec![
-            Transaction::Deposit { client: 2, tx: 2, amount: 2f32 },
-            Transaction::Withdrawal { client: 2, tx: 5, amount: 3f32 },
+            Transaction::Deposit { client: 2, tx: 2, amount: 2f32, raw_amount: None },
+            Transaction::Withdrawal { client: 2, tx: 5, amount: 3f32, raw_amount: None },
         ];
         test_transaction_with_errors(2, transactions, ClientStatus {
             id: 2,
             available: 2f32,
             held: 0f32,
+            held_from_withdrawals: 0f32,
             total: 2f32,
             locked: false,
+            last_activity: None,
         }, vec![ClientStatusError::InsufficientFounds(3f32, 5, 2f32)]);
     }
 
     #[test]
     fn test_negative_deposit() {
         let transactions = vec![
-            Transaction::Deposit { client: 2, tx: 2, amount: 2f32 },
-            Transaction::Deposit { client: 2, tx: 5, amount: -3f32 },
+            Transaction::Deposit { client: 2, tx: 2, amount: 2f32, raw_amount: None },
+            Transaction::Deposit { client: 2, tx: 5, amount: -3f32, raw_amount: None },
         ];
         test_transaction_with_errors(2, transactions, ClientStatus {
             id: 2,
             available: 2f32,
             held: 0f32,
+            held_from_withdrawals: 0f32,
             total: 2f32,
             locked: false,
+            last_activity: None,
         }, vec![ClientStatusError::NegativeAmount(-3f32, 5)]);
     }
 
     #[test]
     fn test_negative_withdrawal() {
         let transactions = vec![
-            Transaction::Deposit { client: 2, tx: 2, amount: 2f32 },
-            Transaction::Withdrawal { client: 2, tx: 5, amount: -3f32 },
+            Transaction::Deposit { client: 2, tx: 2, amount: 2f32, raw_amount: None },
+            Transaction::Withdrawal { client: 2, tx: 5, amount: -3f32, raw_amount: None },
         ];
         test_transaction_with_errors(2, transactions, ClientStatus {
             id: 2,
             available: 2f32,
             held: 0f32,
+            held_from_withdrawals: 0f32,
             total: 2f32,
             locked: false,
+            last_activity: None,
         }, vec![ClientStatusError::NegativeAmount(-3f32, 5)]);
     }
 
     #[test]
     fn test_it_only_process_relevant_client() {
         let transactions = vec![
-            Transaction::Deposit { client: 2, tx: 2, amount: 2f32 },
-            Transaction::Withdrawal { client: 2, tx: 5, amount: 3f32 },
+            Transaction::Deposit { client: 2, tx: 2, amount: 2f32, raw_amount: None },
+            Transaction::Withdrawal { client: 2, tx: 5, amount: 3f32, raw_amount: None },
         ];
         test_transaction_with_errors(1, transactions, ClientStatus {
             id: 1,
             available: 0.0,
             held: 0.0,
+            held_from_withdrawals: 0f32,
             total: 0.0,
-            locked: false
+            locked: false,
+            last_activity: None,
         }, vec![ClientStatusError::WrongClientId(1, 2), ClientStatusError::WrongClientId(1, 2)]);
     }
 
     #[test]
     fn test_transactions_are_unique() {
         let transactions = vec![
-            Transaction::Deposit { client: 2, tx: 2, amount: 2f32 },
-            Transaction::Withdrawal { client: 2, tx: 5, amount: 1f32 },
-            Transaction::Deposit { client: 2, tx: 2, amount: 2f32 },
-            Transaction::Withdrawal { client: 2, tx: 5, amount: 3f32 },
+            Transaction::Deposit { client: 2, tx: 2, amount: 2f32, raw_amount: None },
+            Transaction::Withdrawal { client: 2, tx: 5, amount: 1f32, raw_amount: None },
+            Transaction::Deposit { client: 2, tx: 2, amount: 2f32, raw_amount: None },
+            Transaction::Withdrawal { client: 2, tx: 5, amount: 3f32, raw_amount: None },
         ];
         test_transaction_with_errors(2, transactions, ClientStatus {
             id: 2,
             available: 1.0,
             held: 0.0,
+            held_from_withdrawals: 0f32,
             total: 1.0,
-            locked: false
+            locked: false,
+            last_activity: None,
         }, vec![ClientStatusError::DuplicatedTransaction(2), ClientStatusError::DuplicatedTransaction(5)]);
     }
 
     #[test]
     fn test_dispute_puts_funds_on_hold() {
         let transactions = vec![
-            Transaction::Deposit { client: 1, tx: 1, amount: 1f32 },
-            Transaction::Deposit { client: 1, tx: 3, amount: 2f32 },
-            Transaction::Withdrawal { client: 1, tx: 4, amount: 1.5f32 },
+            Transaction::Deposit { client: 1, tx: 1, amount: 1f32, raw_amount: None },
+            Transaction::Deposit { client: 1, tx: 3, amount: 2f32, raw_amount: None },
+            Transaction::Withdrawal { client: 1, tx: 4, amount: 1.5f32, raw_amount: None },
             Transaction::Dispute { client: 1, tx: 1, },
         ];
         test_successful_transaction(1, transactions, ClientStatus {
             id: 1,
             available: 0.5f32,
             held: 1f32,
+            held_from_withdrawals: 0f32,
             total: 1.5f32,
             locked: false,
+            last_activity: None,
         });
     }
 
     #[test]
     fn test_dispute_resolve_and_chargeback_on_non_dispute_tx_do_nothing() {
         let transactions = vec![
-            Transaction::Deposit { client: 1, tx: 1, amount: 1f32 },
-            Transaction::Deposit { client: 1, tx: 3, amount: 2f32 },
-            Transaction::Withdrawal { client: 1, tx: 4, amount: 1.5f32 },
+            Transaction::Deposit { client: 1, tx: 1, amount: 1f32, raw_amount: None },
+            Transaction::Deposit { client: 1, tx: 3, amount: 2f32, raw_amount: None },
+            Transaction::Withdrawal { client: 1, tx: 4, amount: 1.5f32, raw_amount: None },
             Transaction::Chargeback { client: 1, tx: 1, },
             Transaction::Resolve { client: 1, tx: 1, },
         ];
@@ -298,35 +1267,67 @@ mod tests {
             id: 1,
             available: 1.5f32,
             held: 0f32,
+            held_from_withdrawals: 0f32,
             total: 1.5f32,
             locked: false,
+            last_activity: None,
         }, vec![ClientStatusError::InvalidStatusToChargeback(1, TransactionStatus::Deposited), ClientStatusError::InvalidStatusToResolve(1, TransactionStatus::Deposited)]);
     }
 
+    #[test]
+    fn test_record_serializes_with_output_columns() {
+        let status = ClientStatus { id: 1, available: 1.5, held: 0.5, held_from_withdrawals: 0.0, total: 2.0, locked: false, last_activity: Some(3) };
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        wtr.serialize(ClientStatusRecord::from(&status)).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(data, "client,available,held,total,locked\n1,1.5,0.5,2,false\n");
+        assert_eq!(data.lines().nth(1).unwrap(), status.to_record().join(","));
+        assert_eq!(data.lines().next().unwrap(), ClientStatusRecord::COLUMNS.join(","));
+    }
+
+    #[test]
+    fn test_minor_units_record_has_integer_amounts() {
+        let status = ClientStatus { id: 1, available: 1.5, held: 0.0003, held_from_withdrawals: 0.0, total: 1.5003, locked: false, last_activity: None };
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        wtr.serialize(MinorUnitsRecord::from(&status)).unwrap();
+        let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(data, "client,available,held,total,locked\n1,15000,3,15003,false\n");
+    }
+
+    #[test]
+    fn test_invalid_status_errors_name_the_status() {
+        assert_eq!(
+            ClientStatusError::InvalidStatusToResolve(1, TransactionStatus::Deposited).to_string(),
+            "Cannot resolve a dispute on transaction 1 while being on status deposited"
+        );
+    }
+
     #[test]
     fn test_dispute_resolve_makes_funds_available() {
         let transactions = vec![
-            Transaction::Deposit { client: 1, tx: 1, amount: 1f32 },
-            Transaction::Deposit { client: 1, tx: 3, amount: 2f32 },
-            Transaction::Withdrawal { client: 1, tx: 4, amount: 1.5f32 },
-            Transaction::Dispute { client: 1, tx: 1, },
-            Transaction::Resolve { client: 1, tx: 1, },
+            Transaction::deposit(1, 1, 1f32),
+            Transaction::deposit(1, 3, 2f32),
+            Transaction::withdrawal(1, 4, 1.5f32),
+            Transaction::dispute(1, 1),
+            Transaction::resolve(1, 1),
         ];
         test_successful_transaction(1, transactions, ClientStatus {
             id: 1,
             available: 1.5f32,
             held: 0f32,
+            held_from_withdrawals: 0f32,
             total: 1.5f32,
             locked: false,
+            last_activity: None,
         });
     }
 
     #[test]
     fn test_dispute_resolve_makes_funds_available_with_duplicate_resolves() {
         let transactions = vec![
-            Transaction::Deposit { client: 1, tx: 1, amount: 1f32 },
-            Transaction::Deposit { client: 1, tx: 3, amount: 2f32 },
-            Transaction::Withdrawal { client: 1, tx: 4, amount: 1.5f32 },
+            Transaction::Deposit { client: 1, tx: 1, amount: 1f32, raw_amount: None },
+            Transaction::Deposit { client: 1, tx: 3, amount: 2f32, raw_amount: None },
+            Transaction::Withdrawal { client: 1, tx: 4, amount: 1.5f32, raw_amount: None },
             Transaction::Dispute { client: 1, tx: 1, },
             Transaction::Resolve { client: 1, tx: 1, },
             Transaction::Dispute { client: 1, tx: 1, },
@@ -336,62 +1337,948 @@ mod tests {
             id: 1,
             available: 1.5f32,
             held: 0f32,
+            held_from_withdrawals: 0f32,
             total: 1.5f32,
             locked: false,
+            last_activity: None,
         });
     }
 
     #[test]
-    fn test_dispute_chargeback_freezes_and_removes_funds() {
-        let transactions = vec![
-            Transaction::Deposit { client: 1, tx: 1, amount: 1f32 },
-            Transaction::Deposit { client: 1, tx: 3, amount: 2f32 },
-            Transaction::Withdrawal { client: 1, tx: 4, amount: 1.5f32 },
-            Transaction::Dispute { client: 1, tx: 1, },
-            Transaction::Chargeback { client: 1, tx: 1, },
+    fn test_repeated_dispute_resolve_cycles_do_not_drift() {
+        let mut transactions = vec![
+            Transaction::Deposit { client: 1, tx: 1, amount: 1234.5678f32, raw_amount: None },
+            Transaction::Deposit { client: 1, tx: 2, amount: 0.0001f32, raw_amount: None },
         ];
+        for _ in 0..1000 {
+            transactions.push(Transaction::Dispute { client: 1, tx: 1 });
+            transactions.push(Transaction::Resolve { client: 1, tx: 1 });
+        }
         test_successful_transaction(1, transactions, ClientStatus {
             id: 1,
-            available: 0.5f32,
+            available: 1234.5679f32,
             held: 0f32,
-            total: 0.5f32,
-            locked: true,
+            held_from_withdrawals: 0f32,
+            total: 1234.5679f32,
+            locked: false,
+            last_activity: None,
         });
     }
 
     #[test]
-    fn test_frozen_account_can_deposit_and_not_withdraw() {
+    fn test_dispute_after_withdrawal_leaves_available_negative() {
         let transactions = vec![
-            Transaction::Deposit { client: 1, tx: 1, amount: 1f32 },
-            Transaction::Deposit { client: 1, tx: 3, amount: 2f32 },
-            Transaction::Withdrawal { client: 1, tx: 4, amount: 1.5f32 },
+            Transaction::Deposit { client: 1, tx: 1, amount: 100f32, raw_amount: None },
+            Transaction::Withdrawal { client: 1, tx: 2, amount: 100f32, raw_amount: None },
             Transaction::Dispute { client: 1, tx: 1, },
-            Transaction::Chargeback { client: 1, tx: 1, },
-            Transaction::Withdrawal { client: 1, tx: 5, amount: 0.5f32 },
-            Transaction::Deposit { client: 1, tx: 6, amount: 2f32 },
         ];
-        test_transaction_with_errors(1, transactions, ClientStatus {
+        test_successful_transaction(1, transactions, ClientStatus {
             id: 1,
-            available: 2.5f32,
-            held: 0f32,
-            total: 2.5f32,
-            locked: true,
-        }, vec![ClientStatusError::CustomerFrozen(1, 5)]);
-    }
+            available: -100f32,
+            held: 100f32,
+            held_from_withdrawals: 0f32,
+            total: 0f32,
+            locked: false,
+            last_activity: None,
+        });
+    }
+
+    #[test]
+    fn test_capped_dispute_only_holds_available_funds() {
+        let transactions = vec![
+            Transaction::Deposit { client: 1, tx: 1, amount: 100f32, raw_amount: None },
+            Transaction::Withdrawal { client: 1, tx: 2, amount: 60f32, raw_amount: None },
+            Transaction::Dispute { client: 1, tx: 1, },
+        ];
+        test_transaction_with_options(1, ProcessingOptions { cap_dispute_holds: true, ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 0f32,
+            held: 40f32,
+            held_from_withdrawals: 0f32,
+            total: 40f32,
+            locked: false,
+            last_activity: None,
+        }, vec![]);
+    }
+
+    #[test]
+    fn test_capped_dispute_releases_partial_hold_on_resolve() {
+        let transactions = vec![
+            Transaction::Deposit { client: 1, tx: 1, amount: 100f32, raw_amount: None },
+            Transaction::Withdrawal { client: 1, tx: 2, amount: 60f32, raw_amount: None },
+            Transaction::Dispute { client: 1, tx: 1, },
+            Transaction::Resolve { client: 1, tx: 1, },
+            Transaction::Dispute { client: 1, tx: 1, },
+            Transaction::Chargeback { client: 1, tx: 1, },
+        ];
+        test_transaction_with_options(1, ProcessingOptions { cap_dispute_holds: true, ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 0f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 0f32,
+            locked: true,
+            last_activity: None,
+        }, vec![]);
+    }
+
+    #[test]
+    fn test_chargeback_too_soon_after_dispute_is_rejected() {
+        let transactions = vec![
+            Transaction::Deposit { client: 1, tx: 1, amount: 1f32, raw_amount: None },
+            Transaction::Dispute { client: 1, tx: 1, },
+            Transaction::Deposit { client: 1, tx: 2, amount: 2f32, raw_amount: None },
+            Transaction::Chargeback { client: 1, tx: 1, },
+        ];
+        test_transaction_with_options(1, ProcessingOptions { min_dispute_age: Some(3), ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 2f32,
+            held: 1f32,
+            held_from_withdrawals: 0f32,
+            total: 3f32,
+            locked: false,
+            last_activity: None,
+        }, vec![ClientStatusError::ChargebackTooSoon(1)]);
+    }
+
+    #[test]
+    fn test_chargeback_on_an_earlier_row_than_its_dispute() {
+        let options = ProcessingOptions { min_dispute_age: Some(3), ..Default::default() };
+        let mut account = ClientAccount::new(1);
+        account.apply(0, Transaction::Deposit { client: 1, tx: 1, amount: 2f32, raw_amount: None }, &options).unwrap();
+        account.apply(10, Transaction::Dispute { client: 1, tx: 1 }, &options).unwrap();
+        account.apply(2, Transaction::Chargeback { client: 1, tx: 1 }, &options).unwrap();
+        assert!(account.status().locked);
+    }
+
+    #[test]
+    fn test_chargeback_after_min_dispute_age_is_allowed() {
+        let transactions = vec![
+            Transaction::Deposit { client: 1, tx: 1, amount: 1f32, raw_amount: None },
+            Transaction::Dispute { client: 1, tx: 1, },
+            Transaction::Deposit { client: 1, tx: 2, amount: 2f32, raw_amount: None },
+            Transaction::Deposit { client: 1, tx: 3, amount: 3f32, raw_amount: None },
+            Transaction::Chargeback { client: 1, tx: 1, },
+        ];
+        test_transaction_with_options(1, ProcessingOptions { min_dispute_age: Some(3), ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 5f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 5f32,
+            locked: true,
+            last_activity: None,
+        }, vec![]);
+    }
+
+    #[test]
+    fn test_dispute_within_the_lookback_is_applied() {
+        let transactions = vec![
+            Transaction::Deposit { client: 1, tx: 1, amount: 1f32, raw_amount: None },
+            Transaction::Deposit { client: 1, tx: 2, amount: 2f32, raw_amount: None },
+            Transaction::Dispute { client: 1, tx: 1, },
+        ];
+        test_transaction_with_options(1, ProcessingOptions { dispute_lookback: Some(2), ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 2f32,
+            held: 1f32,
+            held_from_withdrawals: 0f32,
+            total: 3f32,
+            locked: false,
+            last_activity: None,
+        }, vec![]);
+    }
+
+    #[test]
+    fn test_dispute_beyond_the_lookback_is_rejected() {
+        let transactions = vec![
+            Transaction::Deposit { client: 1, tx: 1, amount: 1f32, raw_amount: None },
+            Transaction::Deposit { client: 1, tx: 2, amount: 2f32, raw_amount: None },
+            Transaction::Deposit { client: 1, tx: 3, amount: 3f32, raw_amount: None },
+            Transaction::Dispute { client: 1, tx: 1, },
+        ];
+        test_transaction_with_options(1, ProcessingOptions { dispute_lookback: Some(2), ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 6f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 6f32,
+            locked: false,
+            last_activity: None,
+        }, vec![ClientStatusError::DisputeOutsideWindow(1)]);
+    }
+
+    #[test]
+    fn test_redundant_resolve_is_an_error_by_default() {
+        let transactions = vec![
+            Transaction::Deposit { client: 1, tx: 1, amount: 1f32, raw_amount: None },
+            Transaction::Dispute { client: 1, tx: 1, },
+            Transaction::Resolve { client: 1, tx: 1, },
+            Transaction::Resolve { client: 1, tx: 1, },
+        ];
+        test_transaction_with_errors(1, transactions, ClientStatus {
+            id: 1,
+            available: 1f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 1f32,
+            locked: false,
+            last_activity: None,
+        }, vec![ClientStatusError::InvalidStatusToResolve(1, TransactionStatus::Resolved)]);
+    }
+
+    #[test]
+    fn test_redundant_resolve_can_be_tolerated() {
+        let transactions = vec![
+            Transaction::Deposit { client: 1, tx: 1, amount: 1f32, raw_amount: None },
+            Transaction::Dispute { client: 1, tx: 1, },
+            Transaction::Resolve { client: 1, tx: 1, },
+            Transaction::Resolve { client: 1, tx: 1, },
+        ];
+        test_transaction_with_options(1, ProcessingOptions { tolerate_redundant_resolves: true, ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 1f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 1f32,
+            locked: false,
+            last_activity: None,
+        }, vec![]);
+    }
+
+    #[test]
+    fn test_transactions_up_to_the_limit_are_applied() {
+        let transactions = vec![
+            Transaction::Deposit { client: 1, tx: 1, amount: 100f32, raw_amount: None },
+            Transaction::Deposit { client: 1, tx: 2, amount: 50f32, raw_amount: None },
+            Transaction::Withdrawal { client: 1, tx: 3, amount: 100f32, raw_amount: None },
+        ];
+        test_transaction_with_options(1, ProcessingOptions { max_transaction_amount: Some(100f32), ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 50f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 50f32,
+            locked: false,
+            last_activity: None,
+        }, vec![]);
+    }
+
+    #[test]
+    fn test_transactions_above_the_limit_are_rejected() {
+        let transactions = vec![
+            Transaction::Deposit { client: 1, tx: 1, amount: 100f32, raw_amount: None },
+            Transaction::Deposit { client: 1, tx: 2, amount: 100.5f32, raw_amount: None },
+            Transaction::Withdrawal { client: 1, tx: 3, amount: 100.5f32, raw_amount: None },
+            Transaction::Dispute { client: 1, tx: 2 },
+        ];
+        test_transaction_with_options(1, ProcessingOptions { max_transaction_amount: Some(100f32), ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 100f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 100f32,
+            locked: false,
+            last_activity: None,
+        }, vec![
+            ClientStatusError::AmountLimitExceeded { tx: 2, amount: 100.5f32, limit: 100f32 },
+            ClientStatusError::AmountLimitExceeded { tx: 3, amount: 100.5f32, limit: 100f32 },
+            ClientStatusError::InvalidStatusToStartDispute(2, TransactionStatus::FailedDeposit),
+        ]);
+    }
+
+    #[test]
+    fn test_status_goes_through_a_record_and_back() {
+        let status = ClientStatus { id: 7, available: 1.2345, held: 0.5, held_from_withdrawals: 0f32, total: 1.7345, locked: true, last_activity: None };
+        let read = ClientStatus::from_record(&status.to_record()).unwrap();
+        assert_eq!(read.id, status.id);
+        assert!((read.available - status.available).abs() < f32::EPSILON);
+        assert!((read.held - status.held).abs() < f32::EPSILON);
+        assert!((read.total - status.total).abs() < f32::EPSILON);
+        assert_eq!(read.locked, status.locked);
+    }
+
+    #[test]
+    fn test_malformed_records_are_rejected() {
+        assert_eq!(ClientStatus::from_record(&["1", "1.0", "0"]), Err(ClientStatusParseError::WrongLength(3)));
+        assert_eq!(
+            ClientStatus::from_record(&["1", "1.0", "0", "1.0", "yes"]),
+            Err(ClientStatusParseError::InvalidColumn { column: "locked", value: "yes".to_owned() }),
+        );
+        assert_eq!(
+            ClientStatus::from_record(&["one", "1.0", "0", "1.0", "false"]),
+            Err(ClientStatusParseError::InvalidColumn { column: "client", value: "one".to_owned() }),
+        );
+    }
+
+    #[test]
+    fn test_withdrawals_can_use_the_overdraft() {
+        let options = ProcessingOptions { overdraft_limit: Some(10f32), ..Default::default() };
+        let transactions = vec![
+            Transaction::deposit(1, 1, 5f32),
+            Transaction::withdrawal(1, 2, 8f32),
+            Transaction::withdrawal(1, 3, 8f32),
+        ];
+        test_transaction_with_options(1, options, transactions, ClientStatus {
+            id: 1,
+            available: -3f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: -3f32,
+            locked: false,
+            last_activity: None,
+        }, vec![ClientStatusError::InsufficientFounds(8f32, 3, -3f32)]);
+    }
+
+    #[test]
+    fn test_locked_accounts_have_nothing_to_spend() {
+        let options = ProcessingOptions { overdraft_limit: Some(10f32), ..Default::default() };
+        let mut account = ClientAccount::new(1);
+        account.apply(1, Transaction::deposit(1, 1, 5f32), &options).unwrap();
+        account.apply(2, Transaction::deposit(1, 2, 2f32), &options).unwrap();
+        assert_eq!(account.status().effective_available(&options), 17f32);
+        account.apply(3, Transaction::withdrawal(1, 3, 9f32), &options).unwrap();
+        account.apply(4, Transaction::dispute(1, 1), &options).unwrap();
+        account.apply(5, Transaction::chargeback(1, 1), &options).unwrap();
+        let status = account.status();
+        assert!(status.locked);
+        assert_eq!(status.available, -7f32);
+        assert_eq!(status.effective_available(&options), -7f32);
+        assert!(account.apply(6, Transaction::withdrawal(1, 4, 1f32), &options).is_err());
+        let status = ClientStatus { available: 3f32, total: 3f32, ..status };
+        assert_eq!(status.effective_available(&options), 0f32);
+    }
+
+    #[test]
+    fn test_small_deposits_are_aggregated() {
+        let options = ProcessingOptions { aggregate_deposits_below: Some(1f32), ..Default::default() };
+        let mut account = ClientAccount::new(1);
+        for tx in 1..=4 {
+            account.apply(tx as usize, Transaction::Deposit { client: 1, tx, amount: 0.25f32, raw_amount: None }, &options).unwrap();
+        }
+        account.apply(5, Transaction::Deposit { client: 1, tx: 5, amount: 2f32, raw_amount: None }, &options).unwrap();
+        assert!(matches!(account.apply(6, Transaction::Dispute { client: 1, tx: 1 }, &options), Err(ClientStatusError::NonExistingTransaction(1))));
+        account.apply(7, Transaction::Dispute { client: 1, tx: 5 }, &options).unwrap();
+        let replayed = account.apply(8, Transaction::Deposit { client: 1, tx: 2, amount: 0.25f32, raw_amount: None }, &options);
+        assert!(matches!(replayed, Err(ClientStatusError::DuplicatedTransaction(2))));
+        assert_eq!(account.transaction_statuses.len(), 1);
+        assert_eq!(account.status(), ClientStatus {
+            id: 1,
+            available: 1f32,
+            held: 2f32,
+            held_from_withdrawals: 0f32,
+            total: 3f32,
+            locked: false,
+            last_activity: None,
+        });
+    }
+
+    #[test]
+    fn test_withdrawal_without_deposits_can_be_ignored() {
+        let transactions = vec![
+            Transaction::Withdrawal { client: 1, tx: 1, amount: 5f32, raw_amount: None },
+            Transaction::Dispute { client: 1, tx: 1 },
+        ];
+        test_transaction_with_options(1, ProcessingOptions { ignore_withdrawal_without_balance: true, ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 0f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 0f32,
+            locked: false,
+            last_activity: None,
+        }, vec![ClientStatusError::InvalidStatusToStartDispute(1, TransactionStatus::FailedWithdrawal)]);
+    }
+
+    #[test]
+    fn test_overdraft_is_reported_even_when_ignoring_withdrawals_without_deposits() {
+        let transactions = vec![
+            Transaction::Deposit { client: 1, tx: 1, amount: 2f32, raw_amount: None },
+            Transaction::Withdrawal { client: 1, tx: 2, amount: 2f32, raw_amount: None },
+            Transaction::Withdrawal { client: 1, tx: 3, amount: 5f32, raw_amount: None },
+        ];
+        test_transaction_with_options(1, ProcessingOptions { ignore_withdrawal_without_balance: true, ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 0f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 0f32,
+            locked: false,
+            last_activity: None,
+        }, vec![ClientStatusError::InsufficientFounds(5f32, 3, 0f32)]);
+    }
+
+    #[test]
+    fn test_disputing_a_withdrawal_is_an_error_by_default() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 5f32),
+            Transaction::withdrawal(1, 2, 2f32),
+            Transaction::dispute(1, 2),
+        ];
+        test_transaction_with_errors(1, transactions, ClientStatus {
+            id: 1,
+            available: 3f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 3f32,
+            locked: false,
+            last_activity: None,
+        }, vec![ClientStatusError::CannotDisputeWithdrawal(2)]);
+    }
+
+    #[test]
+    fn test_disputing_a_disputed_withdrawal_is_a_status_error() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 5f32),
+            Transaction::withdrawal(1, 2, 2f32),
+            Transaction::dispute(1, 2),
+            Transaction::dispute(1, 2),
+        ];
+        test_transaction_with_options(1, ProcessingOptions { dispute_withdrawals: true, ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 3f32,
+            held: 2f32,
+            held_from_withdrawals: 2f32,
+            total: 5f32,
+            locked: false,
+            last_activity: None,
+        }, vec![ClientStatusError::InvalidStatusToStartDispute(2, TransactionStatus::OnDispute)]);
+    }
+
+    #[test]
+    fn test_held_funds_are_split_by_source() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 5f32),
+            Transaction::withdrawal(1, 2, 2f32),
+            Transaction::dispute(1, 1),
+            Transaction::dispute(1, 2),
+        ];
+        let status = ClientStatus {
+            id: 1,
+            available: -2f32,
+            held: 7f32,
+            held_from_withdrawals: 2f32,
+            total: 5f32,
+            locked: false,
+            last_activity: None,
+        };
+        assert_eq!(status.held_from_deposits(), 5f32);
+        test_transaction_with_options(1, ProcessingOptions { dispute_withdrawals: true, ..Default::default() }, transactions, status, vec![]);
+    }
+
+    #[test]
+    fn test_resolved_withdrawal_stands() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 5f32),
+            Transaction::withdrawal(1, 2, 2f32),
+            Transaction::dispute(1, 2),
+            Transaction::resolve(1, 2),
+        ];
+        test_transaction_with_options(1, ProcessingOptions { dispute_withdrawals: true, ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 3f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 3f32,
+            locked: false,
+            last_activity: None,
+        }, vec![]);
+    }
+
+    #[test]
+    fn test_charged_back_withdrawal_gives_funds_back() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 5f32),
+            Transaction::withdrawal(1, 2, 2f32),
+            Transaction::dispute(1, 2),
+            Transaction::chargeback(1, 2),
+        ];
+        test_transaction_with_options(1, ProcessingOptions { dispute_withdrawals: true, ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 5f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 5f32,
+            locked: true,
+            last_activity: None,
+        }, vec![]);
+    }
+
+    #[test]
+    fn test_fee_can_be_disputed_apart_from_its_withdrawal() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 10f32),
+            Transaction::withdrawal(1, 2, 4f32),
+            Transaction::fee(1, 3, 0.5f32),
+            Transaction::dispute(1, 3),
+            Transaction::chargeback(1, 3),
+        ];
+        test_transaction_with_options(1, ProcessingOptions { dispute_withdrawals: true, ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 6f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 6f32,
+            locked: true,
+            last_activity: None,
+        }, vec![]);
+    }
+
+    #[test]
+    fn test_withdrawal_and_its_fee_can_both_be_disputed() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 10f32),
+            Transaction::withdrawal(1, 2, 4f32),
+            Transaction::fee(1, 3, 0.5f32),
+            Transaction::dispute(1, 2),
+            Transaction::dispute(1, 3),
+            Transaction::resolve(1, 2),
+        ];
+        test_transaction_with_options(1, ProcessingOptions { dispute_withdrawals: true, ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 5.5f32,
+            held: 0.5f32,
+            held_from_withdrawals: 0.5f32,
+            total: 6f32,
+            locked: false,
+            last_activity: None,
+        }, vec![]);
+    }
+
+    #[test]
+    fn test_dispute_within_the_held_limit_is_applied() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 3f32),
+            Transaction::deposit(1, 2, 2f32),
+            Transaction::dispute(1, 1),
+            Transaction::dispute(1, 2),
+        ];
+        test_transaction_with_options(1, ProcessingOptions { max_held_per_client: Some(5f32), ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 0f32,
+            held: 5f32,
+            held_from_withdrawals: 0f32,
+            total: 5f32,
+            locked: false,
+            last_activity: None,
+        }, vec![]);
+    }
+
+    #[test]
+    fn test_dispute_beyond_the_held_limit_is_rejected() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 3f32),
+            Transaction::deposit(1, 2, 2f32),
+            Transaction::dispute(1, 1),
+            Transaction::dispute(1, 2),
+            Transaction::chargeback(1, 2),
+        ];
+        test_transaction_with_options(1, ProcessingOptions { max_held_per_client: Some(4f32), ..Default::default() }, transactions, ClientStatus {
+            id: 1,
+            available: 2f32,
+            held: 3f32,
+            held_from_withdrawals: 0f32,
+            total: 5f32,
+            locked: false,
+            last_activity: None,
+        }, vec![
+            ClientStatusError::HeldLimitExceeded { client: 1, tx: 2, limit: 4f32 },
+            ClientStatusError::InvalidStatusToChargeback(2, TransactionStatus::Deposited),
+        ]);
+    }
+
+    #[test]
+    fn test_dispute_chargeback_freezes_and_removes_funds() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 1f32),
+            Transaction::deposit(1, 3, 2f32),
+            Transaction::withdrawal(1, 4, 1.5f32),
+            Transaction::dispute(1, 1),
+            Transaction::chargeback(1, 1),
+        ];
+        test_successful_transaction(1, transactions, ClientStatus {
+            id: 1,
+            available: 0.5f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 0.5f32,
+            locked: true,
+            last_activity: None,
+        });
+    }
+
+    #[test]
+    fn test_frozen_account_can_deposit_and_not_withdraw() {
+        let transactions = vec![
+            Transaction::Deposit { client: 1, tx: 1, amount: 1f32, raw_amount: None },
+            Transaction::Deposit { client: 1, tx: 3, amount: 2f32, raw_amount: None },
+            Transaction::Withdrawal { client: 1, tx: 4, amount: 1.5f32, raw_amount: None },
+            Transaction::Dispute { client: 1, tx: 1, },
+            Transaction::Chargeback { client: 1, tx: 1, },
+            Transaction::Withdrawal { client: 1, tx: 5, amount: 0.5f32, raw_amount: None },
+            Transaction::Deposit { client: 1, tx: 6, amount: 2f32, raw_amount: None },
+        ];
+        test_transaction_with_errors(1, transactions, ClientStatus {
+            id: 1,
+            available: 2.5f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 2.5f32,
+            locked: true,
+            last_activity: None,
+        }, vec![ClientStatusError::CustomerFrozen(1, 5)]);
+    }
+
+    #[test]
+    fn test_charged_back_transaction_cannot_be_disputed_again() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 1f32),
+            Transaction::deposit(1, 2, 2f32),
+            Transaction::dispute(1, 1),
+            Transaction::chargeback(1, 1),
+            Transaction::dispute(1, 1),
+        ];
+        test_transaction_with_errors(1, transactions, ClientStatus {
+            id: 1,
+            available: 2f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 2f32,
+            locked: true,
+            last_activity: None,
+        }, vec![ClientStatusError::CannotDisputeChargedBack(1)]);
+    }
+
+    #[test]
+    fn test_concurrent_disputes_hold_the_sum_of_their_amounts() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 50f32),
+            Transaction::deposit(1, 2, 30f32),
+            Transaction::deposit(1, 3, 20f32),
+            Transaction::dispute(1, 1),
+            Transaction::dispute(1, 2),
+            Transaction::withdrawal(1, 4, 30f32),
+            Transaction::withdrawal(1, 5, 20f32),
+        ];
+        test_transaction_with_errors(1, transactions, ClientStatus {
+            id: 1,
+            available: 0f32,
+            held: 80f32,
+            held_from_withdrawals: 0f32,
+            total: 80f32,
+            locked: false,
+            last_activity: None,
+        }, vec![ClientStatusError::InsufficientFounds(30f32, 4, 20f32)]);
+    }
+
+    #[test]
+    fn test_resolving_one_of_two_disputes_only_releases_its_amount() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 50f32),
+            Transaction::deposit(1, 2, 30f32),
+            Transaction::dispute(1, 1),
+            Transaction::dispute(1, 2),
+            Transaction::resolve(1, 1),
+        ];
+        test_successful_transaction(1, transactions, ClientStatus {
+            id: 1,
+            available: 50f32,
+            held: 30f32,
+            held_from_withdrawals: 0f32,
+            total: 80f32,
+            locked: false,
+            last_activity: None,
+        });
+    }
+
+    #[test]
+    fn test_deposits_above_the_max_balance_are_rejected() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 60f32),
+            Transaction::deposit(1, 2, 50f32),
+        ];
+        let options = ProcessingOptions { max_balance: Some(100f32), ..Default::default() };
+        test_transaction_with_options(1, options, transactions, ClientStatus {
+            id: 1,
+            available: 60f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 60f32,
+            locked: false,
+            last_activity: None,
+        }, vec![ClientStatusError::BalanceCeilingExceeded { client: 1, tx: 2, ceiling: 100f32 }]);
+    }
+
+    #[test]
+    fn test_held_funds_count_towards_the_max_balance() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 60f32),
+            Transaction::dispute(1, 1),
+            Transaction::deposit(1, 2, 40f32),
+            Transaction::deposit(1, 3, 0.5f32),
+        ];
+        let options = ProcessingOptions { max_balance: Some(100f32), ..Default::default() };
+        test_transaction_with_options(1, options, transactions, ClientStatus {
+            id: 1,
+            available: 40f32,
+            held: 60f32,
+            held_from_withdrawals: 0f32,
+            total: 100f32,
+            locked: false,
+            last_activity: None,
+        }, vec![ClientStatusError::BalanceCeilingExceeded { client: 1, tx: 3, ceiling: 100f32 }]);
+    }
+
+    #[test]
+    fn test_saturating_deposit_stops_at_the_max_balance() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 60f32),
+            Transaction::deposit(1, 2, 50f32),
+            Transaction::dispute(1, 2),
+        ];
+        let options = ProcessingOptions { max_balance: Some(100f32), saturating_arithmetic: true, ..Default::default() };
+        test_transaction_with_options(1, options, transactions, ClientStatus {
+            id: 1,
+            available: 60f32,
+            held: 40f32,
+            held_from_withdrawals: 0f32,
+            total: 100f32,
+            locked: false,
+            last_activity: None,
+        }, vec![]);
+    }
+
+    #[test]
+    fn test_saturating_deposit_never_reaches_infinity() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, f32::MAX / 2f32),
+            Transaction::deposit(1, 2, f32::MAX / 2f32),
+        ];
+        let mut account = ClientAccount::new(1);
+        let options = ProcessingOptions { saturating_arithmetic: true, ..Default::default() };
+        for (row, t) in transactions.into_iter().enumerate() {
+            account.apply(row, t, &options).unwrap();
+        }
+        let status = account.status();
+        assert!(status.available.is_finite() && status.total.is_finite());
+    }
+
+    #[test]
+    fn test_disputes_of_huge_deposits_do_not_overflow() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 1e30f32),
+            Transaction::deposit(1, 2, 1e30f32),
+            Transaction::dispute(1, 1),
+            Transaction::dispute(1, 2),
+            Transaction::resolve(1, 1),
+        ];
+        let mut account = ClientAccount::new(1);
+        let options = ProcessingOptions::default();
+        for (row, t) in transactions.into_iter().enumerate() {
+            account.apply(row, t, &options).unwrap();
+        }
+        assert!(account.status().total.is_finite());
+    }
+
+    /// Locks the account while the dispute on tx 2 is still open.
+    fn locked_with_pending_dispute() -> Vec<Transaction> {
+        vec![
+            Transaction::deposit(1, 1, 1f32),
+            Transaction::deposit(1, 2, 2f32),
+            Transaction::dispute(1, 1),
+            Transaction::dispute(1, 2),
+            Transaction::chargeback(1, 1),
+        ]
+    }
+
+    #[test]
+    fn test_pending_dispute_stays_processable_after_locking() {
+        let mut transactions = locked_with_pending_dispute();
+        transactions.push(Transaction::resolve(1, 2));
+        test_successful_transaction(1, transactions, ClientStatus {
+            id: 1,
+            available: 2f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 2f32,
+            locked: true,
+            last_activity: None,
+        });
+    }
+
+    #[test]
+    fn test_pending_dispute_can_be_frozen_by_locking() {
+        let mut transactions = locked_with_pending_dispute();
+        transactions.push(Transaction::resolve(1, 2));
+        transactions.push(Transaction::chargeback(1, 2));
+        let options = ProcessingOptions { locked_disputes: LockedDisputePolicy::Frozen, ..Default::default() };
+        test_transaction_with_options(1, options, transactions, ClientStatus {
+            id: 1,
+            available: 0f32,
+            held: 2f32,
+            held_from_withdrawals: 0f32,
+            total: 2f32,
+            locked: true,
+            last_activity: None,
+        }, vec![ClientStatusError::DisputeFrozen(2), ClientStatusError::DisputeFrozen(2)]);
+    }
+
+    #[test]
+    fn test_pending_dispute_can_be_resolved_by_locking() {
+        let options = ProcessingOptions { locked_disputes: LockedDisputePolicy::AutoResolve, ..Default::default() };
+        test_transaction_with_options(1, options, locked_with_pending_dispute(), ClientStatus {
+            id: 1,
+            available: 2f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 2f32,
+            locked: true,
+            last_activity: None,
+        }, vec![]);
+    }
+
+    #[test]
+    fn test_tx_id_reused_after_dispute_is_told_apart() {
+        let transactions = vec![
+            Transaction::deposit(1, 5, 1f32),
+            Transaction::deposit(1, 6, 2f32),
+            Transaction::dispute(1, 5),
+            Transaction::deposit(1, 5, 3f32),
+            Transaction::dispute(1, 6),
+            Transaction::resolve(1, 6),
+            Transaction::withdrawal(1, 6, 1f32),
+            Transaction::deposit(1, 7, 1f32),
+            Transaction::deposit(1, 7, 1f32),
+        ];
+        test_transaction_with_errors(1, transactions, ClientStatus {
+            id: 1,
+            available: 3f32,
+            held: 1f32,
+            held_from_withdrawals: 0f32,
+            total: 4f32,
+            locked: false,
+            last_activity: None,
+        }, vec![
+            ClientStatusError::TxIdReusedAfterDispute(5),
+            ClientStatusError::TxIdReusedAfterDispute(6),
+            ClientStatusError::DuplicatedTransaction(7),
+        ]);
+    }
+
+    #[test]
+    fn test_disputing_a_dispute_is_reported() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, 1f32),
+            Transaction::dispute(1, 8),
+            Transaction::dispute(1, 8),
+            Transaction::resolve(1, 9),
+            Transaction::dispute(1, 9),
+            Transaction::chargeback(1, 10),
+            Transaction::dispute(1, 10),
+            Transaction::dispute(1, 11),
+        ];
+        let options = ProcessingOptions { track_operation_ids: true, ..Default::default() };
+        test_transaction_with_options(1, options, transactions, ClientStatus {
+            id: 1,
+            available: 1f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 1f32,
+            locked: false,
+            last_activity: None,
+        }, vec![
+            ClientStatusError::NonExistingTransaction(8),
+            ClientStatusError::NonExistingTransaction(8),
+            ClientStatusError::NonExistingTransaction(9),
+            ClientStatusError::DisputeOnNonMonetaryTransaction(9),
+            ClientStatusError::NonExistingTransaction(10),
+            ClientStatusError::DisputeOnNonMonetaryTransaction(10),
+            ClientStatusError::NonExistingTransaction(11),
+        ]);
+    }
+
+    /// Leaves tx 1 and 3 on dispute, and tx 2 resolved.
+    fn several_disputes() -> Vec<Transaction> {
+        vec![
+            Transaction::deposit(1, 1, 1f32),
+            Transaction::deposit(1, 2, 2f32),
+            Transaction::deposit(1, 3, 4f32),
+            Transaction::dispute(1, 1),
+            Transaction::dispute(1, 2),
+            Transaction::dispute(1, 3),
+            Transaction::resolve(1, 2),
+        ]
+    }
+
+    #[test]
+    fn test_resolve_all_releases_every_dispute() {
+        let mut transactions = several_disputes();
+        transactions.push(Transaction::resolve_all(1));
+        transactions.push(Transaction::resolve(1, 3));
+        test_transaction_with_errors(1, transactions, ClientStatus {
+            id: 1,
+            available: 7f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 7f32,
+            locked: false,
+            last_activity: None,
+        }, vec![ClientStatusError::InvalidStatusToResolve(3, TransactionStatus::Resolved)]);
+    }
+
+    #[test]
+    fn test_chargeback_all_locks_after_every_dispute() {
+        let mut transactions = several_disputes();
+        transactions.push(Transaction::chargeback_all(1));
+        let options = ProcessingOptions { locked_disputes: LockedDisputePolicy::Frozen, ..Default::default() };
+        test_transaction_with_options(1, options, transactions, ClientStatus {
+            id: 1,
+            available: 2f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 2f32,
+            locked: true,
+            last_activity: None,
+        }, vec![]);
+    }
+
+    #[test]
+    fn test_chargeback_all_on_frozen_account_is_rejected() {
+        let mut transactions = locked_with_pending_dispute();
+        transactions.push(Transaction::chargeback_all(1));
+        transactions.push(Transaction::resolve_all(1));
+        let options = ProcessingOptions { locked_disputes: LockedDisputePolicy::Frozen, ..Default::default() };
+        test_transaction_with_options(1, options, transactions, ClientStatus {
+            id: 1,
+            available: 0f32,
+            held: 2f32,
+            held_from_withdrawals: 0f32,
+            total: 2f32,
+            locked: true,
+            last_activity: None,
+        }, vec![ClientStatusError::DisputeFrozen(2), ClientStatusError::DisputeFrozen(2)]);
+    }
+
+    #[test]
+    fn test_chargeback_all_without_disputes_does_not_lock() {
+        let transactions = vec![Transaction::deposit(1, 1, 1f32), Transaction::chargeback_all(1)];
+        test_successful_transaction(1, transactions, ClientStatus {
+            id: 1,
+            available: 1f32,
+            held: 0f32,
+            held_from_withdrawals: 0f32,
+            total: 1f32,
+            locked: false,
+            last_activity: None,
+        });
+    }
 
     fn test_successful_transaction(
         client_id: u32,
         transactions: Vec<Transaction>,
         client_status: ClientStatus,
     ) {
-        let result = Arc::new(Mutex::new(vec![]));
-        let errors: Arc<Mutex<Vec<Box<dyn Error + Send>>>> = Arc::new(Mutex::new(vec![]));
-        run_build(client_id, transactions, result.clone(), errors.clone()).join().unwrap();
-        let errors = Arc::try_unwrap(errors).unwrap().into_inner().unwrap();
-        let result = Arc::try_unwrap(result).unwrap().into_inner().unwrap();
-        assert!(errors.is_empty());
-        assert_eq!(result.len(), 1);
-        assert_eq!(result[0], client_status);
+        test_transaction_with_errors(client_id, transactions, client_status, vec![]);
     }
 
     fn test_transaction_with_errors(
@@ -400,30 +2287,104 @@ mod tests {
         client_status: ClientStatus,
         expected_errors: Vec<ClientStatusError>,
     ) {
-        let result = Arc::new(Mutex::new(vec![]));
-        let errors: Arc<Mutex<Vec<Box<dyn Error + Send>>>> = Arc::new(Mutex::new(vec![]));
-        run_build(client_id, transactions, result.clone(), errors.clone()).join().unwrap();
-        let errors = Arc::try_unwrap(errors).unwrap().into_inner().unwrap();
-        let result = Arc::try_unwrap(result).unwrap().into_inner().unwrap();
-        assert_eq!(result.len(), 1);
-        assert_eq!(result[0], client_status);
+        test_transaction_with_options(client_id, ProcessingOptions::default(), transactions, client_status, expected_errors);
+    }
+
+    fn test_transaction_with_options(
+        client_id: u32,
+        options: ProcessingOptions,
+        transactions: Vec<Transaction>,
+        client_status: ClientStatus,
+        expected_errors: Vec<ClientStatusError>,
+    ) {
+        let mut account = ClientAccount::new(client_id);
+        let errors: Vec<ClientStatusError> = transactions.into_iter()
+            .enumerate()
+            .filter_map(|(row, t)| account.apply(row, t, &options).err())
+            .collect();
+        assert_eq!(account.status(), client_status);
         assert_eq!(errors.len(), expected_errors.len());
         for (e1, e2) in errors.iter().zip(expected_errors.iter()) {
             assert_eq!(e1.to_string(), e2.to_string());
         }
     }
 
-    fn run_build(
-        client_id: u32,
-        transactions: Vec<Transaction>,
-        result: Arc<Mutex<Vec<ClientStatus>>>,
-        errors: Arc<Mutex<Vec<Box<dyn Error + Send>>>>
-    ) -> JoinHandle<()> {
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_build_sends_status_and_errors() {
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+        use crossbeam_channel::unbounded;
+        use crate::client_status::{build, Message, Routed, WorkerOutput};
+        use crate::ProcessingError;
+
         let (sender, receiver) = unbounded();
-        let j = thread::spawn(move || build(client_id, receiver, result, errors));
-        for t in transactions {
-            sender.send(t).unwrap();
+        let (result, result_receiver) = unbounded();
+        let errors: Arc<Mutex<Vec<ProcessingError>>> = Arc::new(Mutex::new(vec![]));
+        let worker_errors = errors.clone();
+        let j = thread::spawn(move || build(2, receiver, result, worker_errors, Arc::default(), Arc::new(ProcessingOptions::default())));
+        sender.send(Message::Transaction(Routed { line: None, row: 0, timestamp: Some(10), adjustment: false, transaction: Transaction::Deposit { client: 2, tx: 2, amount: 2f32, raw_amount: None } })).unwrap();
+        sender.send(Message::Transaction(Routed { line: None, row: 1, timestamp: None, adjustment: false, transaction: Transaction::Withdrawal { client: 2, tx: 5, amount: 3f32, raw_amount: None } })).unwrap();
+        drop(sender);
+        j.join().unwrap();
+
+        let output: Vec<WorkerOutput> = result_receiver.iter().collect();
+        assert_eq!(output.len(), 1);
+        assert_eq!(output[0].statuses, vec![ClientStatus { id: 2, available: 2f32, held: 0f32, held_from_withdrawals: 0.0, total: 2f32, locked: false, last_activity: Some(10) }]);
+        assert_eq!(output[0].errors.len(), 1);
+        assert_eq!(output[0].errors[0].to_string(), ClientStatusError::InsufficientFounds(3f32, 5, 2f32).to_string());
+        // Without an error limit, nothing goes through the shared list.
+        assert!(errors.lock().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_errors_are_only_batched_with_an_error_limit() {
+        use std::sync::{Arc, Mutex};
+        use crate::client_status::{ErrorBuffer, ERROR_BATCH_SIZE};
+        use crate::{ErrorPolicy, ProcessingError};
+
+        let shared: Arc<Mutex<Vec<ProcessingError>>> = Arc::new(Mutex::new(vec![]));
+        let mut unlimited = ErrorBuffer::new(shared.clone(), &ProcessingOptions::default());
+        let limit = ProcessingOptions { error_policy: ErrorPolicy::ContinueUntil(1), ..Default::default() };
+        let mut limited = ErrorBuffer::new(shared.clone(), &limit);
+        for tx in 0..ERROR_BATCH_SIZE as u32 {
+            unlimited.push(ClientStatusError::NonExistingTransaction(tx).into());
+        }
+        assert!(shared.lock().unwrap().is_empty());
+        for tx in 0..ERROR_BATCH_SIZE as u32 {
+            limited.push(ClientStatusError::NonExistingTransaction(tx).into());
         }
-        j
+        assert_eq!(shared.lock().unwrap().len(), ERROR_BATCH_SIZE);
+        assert_eq!(unlimited.into_errors().len(), ERROR_BATCH_SIZE);
+        assert!(limited.into_errors().is_empty());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_build_reports_a_panicking_worker() {
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+        use crossbeam_channel::unbounded;
+        use crate::client_status::{build, Message, Routed, Validator, WorkerOutput};
+        use crate::{ProcessingError, TransactionKind};
+
+        let (sender, receiver) = unbounded();
+        let (result, result_receiver) = unbounded();
+        let errors: Arc<Mutex<Vec<ProcessingError>>> = Arc::new(Mutex::new(vec![]));
+        let worker_errors = errors.clone();
+        let mut options = ProcessingOptions::default();
+        options.validators.insert(TransactionKind::Chargeback, Validator::new(|_| panic!("chargebacks are not supported")));
+        let j = thread::spawn(move || build(1, receiver, result, worker_errors, Arc::default(), Arc::new(options)));
+        sender.send(Message::Transaction(Routed { line: None, row: 0, timestamp: None, adjustment: false, transaction: Transaction::Deposit { client: 1, tx: 1, amount: 2f32, raw_amount: None } })).unwrap();
+        sender.send(Message::Transaction(Routed { line: None, row: 1, timestamp: None, adjustment: false, transaction: Transaction::Dispute { client: 1, tx: 1 } })).unwrap();
+        sender.send(Message::Transaction(Routed { line: None, row: 2, timestamp: None, adjustment: false, transaction: Transaction::Chargeback { client: 1, tx: 1 } })).unwrap();
+        drop(sender);
+        j.join().unwrap();
+
+        let output: Vec<WorkerOutput> = result_receiver.iter().collect();
+        assert_eq!(output[0].statuses, vec![ClientStatus { id: 1, available: 0f32, held: 2f32, held_from_withdrawals: 0.0, total: 2f32, locked: false, last_activity: None }]);
+        assert!(matches!(output[0].errors.as_slice(), [ProcessingError::WorkerPanicked { client: 1, message }] if message == "chargebacks are not supported"));
+        assert!(errors.lock().unwrap().is_empty());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,46 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Tells the current time, in seconds since the Unix epoch, to every
+/// decision that depends on it. Tests inject a [`FixedClock`] instead of
+/// the [`SystemClock`].
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> u64;
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        // A system clock set before 1970 is as good as no clock at all.
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+    }
+}
+
+/// A clock stopped at a given time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FixedClock(pub u64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{Clock, FixedClock, SystemClock};
+
+    #[test]
+    fn test_fixed_clock_does_not_move() {
+        let clock = FixedClock(42);
+        assert_eq!(clock.now(), 42);
+        assert_eq!(clock.now(), 42);
+    }
+
+    #[test]
+    fn test_system_clock_is_past_the_epoch() {
+        assert!(SystemClock.now() > 0);
+    }
+}
@@ -0,0 +1,69 @@
+//! Column layouts of feeds without a header row, read from a schema file
+//! like:
+//!
+//! ```toml
+//! # Layout of the nightly feed.
+//! columns = ["type", "client", "tx", "amount"]
+//! ```
+use std::io::{BufRead, BufReader, Read};
+use crate::ProcessingError;
+
+/// Columns every layout needs. The amount can be left out by feeds with
+/// only disputes, resolves and chargebacks.
+const REQUIRED_COLUMNS: [&str; 3] = ["type", "client", "tx"];
+
+/// Reads the `columns` of a schema file, checking they cover every
+/// required field.
+pub fn read_columns<R: Read>(reader: R) -> Result<Vec<String>, ProcessingError> {
+    for line in BufReader::new(reader).lines() {
+        let line = line?;
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        if key.trim() != "columns" {
+            continue;
+        }
+        let list = value.trim()
+            .strip_prefix('[')
+            .and_then(|value| value.strip_suffix(']'))
+            .ok_or_else(|| ProcessingError::InvalidColumns("columns is not a list".to_owned()))?;
+        let columns = list.split(',')
+            .map(str::trim)
+            .filter(|column| !column.is_empty())
+            .map(|column| {
+                column.strip_prefix('"').and_then(|column| column.strip_suffix('"'))
+                    .map(str::to_owned)
+                    .ok_or_else(|| ProcessingError::InvalidColumns(format!("{} is not a quoted name", column)))
+            })
+            .collect::<Result<Vec<String>, ProcessingError>>()?;
+        check_columns(&columns)?;
+        return Ok(columns);
+    }
+    Err(ProcessingError::InvalidColumns("no columns are listed".to_owned()))
+}
+
+pub(crate) fn check_columns(columns: &[String]) -> Result<(), ProcessingError> {
+    match REQUIRED_COLUMNS.iter().find(|required| !columns.iter().any(|column| column == *required)) {
+        Some(missing) => Err(ProcessingError::InvalidColumns(format!("{} is missing", missing))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{read_columns, ProcessingError};
+
+    #[test]
+    fn test_columns_are_read_from_the_schema() {
+        let schema = "# Nightly feed.\ncolumns = [\"type\", \"client\", \"tx\", \"amount\"]\n";
+        assert_eq!(read_columns(schema.as_bytes()).unwrap(), vec!["type", "client", "tx", "amount"]);
+    }
+
+    #[test]
+    fn test_incomplete_columns_are_rejected() {
+        let error = read_columns("columns = [\"type\", \"tx\", \"amount\"]".as_bytes()).unwrap_err();
+        assert!(matches!(&error, ProcessingError::InvalidColumns(reason) if reason == "client is missing"));
+        assert!(matches!(read_columns("columns = type".as_bytes()), Err(ProcessingError::InvalidColumns(_))));
+        assert!(matches!(read_columns("".as_bytes()), Err(ProcessingError::InvalidColumns(_))));
+    }
+}
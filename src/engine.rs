@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::io;
+use crate::client_status::{ClientAccount, Routed};
+use crate::{ClientStatus, ClientStatusError, ProcessingError, ProcessingOptions, Transaction};
+
+const STATE_HEADER: &str = "engine\t1";
+
+/// Processes transactions handed over one at a time on the calling thread,
+/// keeping every account between calls, so a feed can be processed as it
+/// arrives and the state saved in between.
+#[derive(Debug)]
+pub struct Engine {
+    options: ProcessingOptions,
+    accounts: HashMap<u32, ClientAccount>,
+    /// Rows processed so far, which disputes are aged by.
+    rows: usize,
+}
+
+impl Engine {
+    pub fn new(options: ProcessingOptions) -> Engine {
+        Engine { options, accounts: HashMap::new(), rows: 0 }
+    }
+
+    pub fn process(&mut self, transaction: Transaction) -> Result<(), ClientStatusError> {
+        self.apply(transaction, false)
+    }
+
+    /// Applies a manual adjustment, like an operator's correction once the
+    /// feed is over and its results were looked at. It is processed like
+    /// any other transaction, but told apart from the feed in the outcomes.
+    pub fn adjust(&mut self, transaction: Transaction) -> Result<(), ClientStatusError> {
+        self.apply(transaction, true)
+    }
+
+    fn apply(&mut self, transaction: Transaction, adjustment: bool) -> Result<(), ClientStatusError> {
+        let client = transaction.get_client();
+        let account = self.accounts.entry(client).or_insert_with(|| ClientAccount::new(client));
+        let row = self.rows;
+        self.rows += 1;
+        account.process(Routed { row, line: None, timestamp: None, adjustment, transaction }, &self.options)
+    }
+
+    /// The balance of every client so far, sorted by id.
+    pub fn results(&self) -> Vec<ClientStatus> {
+        let mut results: Vec<ClientStatus> = self.accounts.values()
+            .filter(|account| self.options.include_phantom_clients || !account.is_phantom())
+            .map(ClientAccount::status)
+            .collect();
+        results.sort_by_key(|status| status.id);
+        results
+    }
+
+    /// Writes every account, along with the transactions it remembers, so
+    /// `load_state` can pick up where this engine is. The options are not
+    /// part of the state.
+    pub fn save_state<W: Write>(&self, writer: W) -> io::Result<()> {
+        write_state(writer, self.rows, self.accounts.values())
+    }
+
+    /// Restores an engine saved by `save_state`, with the default options.
+    pub fn load_state<R: Read>(reader: R) -> Result<Engine, ProcessingError> {
+        let mut engine = Engine::new(ProcessingOptions::default());
+        let mut current: Option<ClientAccount> = None;
+        for (index, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let invalid = || ProcessingError::InvalidState { line: index as u64 + 1 };
+            let fields: Vec<&str> = line.split('\t').collect();
+            match fields.as_slice() {
+                ["engine", "1", rows] if index == 0 => engine.rows = rows.parse().map_err(|_| invalid())?,
+                _ if index == 0 => return Err(invalid()),
+                ["account", fields @ ..] => {
+                    let account = ClientAccount::load(fields).ok_or_else(invalid)?;
+                    if let Some(previous) = current.replace(account) {
+                        engine.accounts.insert(previous.id(), previous);
+                    }
+                }
+                ["tx", fields @ ..] => current.as_mut().and_then(|account| account.load_record(fields)).ok_or_else(invalid)?,
+                ["aggregated", ids] => current.as_mut().and_then(|account| account.load_aggregated(ids)).ok_or_else(invalid)?,
+                _ => return Err(invalid()),
+            }
+        }
+        if let Some(account) = current {
+            engine.accounts.insert(account.id(), account);
+        }
+        Ok(engine)
+    }
+
+    pub fn set_options(&mut self, options: ProcessingOptions) {
+        self.options = options;
+    }
+
+    /// Rows processed so far, including the ones of the saved state it was
+    /// loaded from. A feed resumes from the row after them.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+}
+
+/// Writes the state of an engine which processed `rows` so far, with the
+/// accounts in client id order.
+pub(crate) fn write_state<'a, W: Write, I: IntoIterator<Item = &'a ClientAccount>>(mut writer: W, rows: usize, accounts: I) -> io::Result<()> {
+    writeln!(writer, "{}\t{}", STATE_HEADER, rows)?;
+    let mut accounts: Vec<&ClientAccount> = accounts.into_iter().collect();
+    accounts.sort_by_key(|account| account.id());
+    for account in accounts {
+        account.save(&mut writer)?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use crate::{ClientStatus, ClientStatusError, Engine, OutcomeSink, ProcessingError, ProcessingOptions, Transaction};
+
+    #[test]
+    fn test_saved_engine_resumes_with_its_disputes() {
+        let mut engine = Engine::new(ProcessingOptions::default());
+        engine.process(Transaction::deposit(1, 1, 1.5)).unwrap();
+        engine.process(Transaction::deposit(1, 2, 2.0)).unwrap();
+        engine.process(Transaction::dispute(1, 2)).unwrap();
+        engine.process(Transaction::deposit(2, 3, 0.25)).unwrap();
+        let mut state = vec![];
+        engine.save_state(&mut state).unwrap();
+
+        let mut restored = Engine::load_state(state.as_slice()).unwrap();
+        assert_eq!(restored.results(), engine.results());
+        restored.process(Transaction::dispute(1, 1)).unwrap();
+        restored.process(Transaction::chargeback(1, 2)).unwrap();
+        assert!(matches!(restored.process(Transaction::deposit(2, 3, 1.0)), Err(ClientStatusError::DuplicatedTransaction(3))));
+        assert_eq!(restored.results(), vec![
+            ClientStatus { id: 1, available: 0.0, held: 1.5, held_from_withdrawals: 0.0, total: 1.5, locked: true, last_activity: None },
+            ClientStatus { id: 2, available: 0.25, held: 0.0, held_from_withdrawals: 0.0, total: 0.25, locked: false, last_activity: None },
+        ]);
+    }
+
+    #[test]
+    fn test_saved_engine_remembers_aggregated_ids() {
+        let options = ProcessingOptions { aggregate_deposits_below: Some(1.0), ..Default::default() };
+        let mut engine = Engine::new(options.clone());
+        engine.process(Transaction::deposit(1, 1, 0.25)).unwrap();
+        let mut state = vec![];
+        engine.save_state(&mut state).unwrap();
+
+        let mut restored = Engine::load_state(state.as_slice()).unwrap();
+        restored.set_options(options);
+        assert!(matches!(restored.process(Transaction::deposit(1, 1, 0.25)), Err(ClientStatusError::DuplicatedTransaction(1))));
+        assert_eq!(restored.results()[0].total, 0.25);
+    }
+
+    #[test]
+    fn test_constructed_amounts_are_rounded_like_parsed_ones() {
+        let mut engine = Engine::new(ProcessingOptions::default());
+        engine.process(Transaction::deposit(1, 1, 1.0)).unwrap();
+        engine.process(Transaction::withdrawal(1, 2, 1.00004)).unwrap();
+        assert_eq!(engine.results()[0].available, 0.0);
+    }
+
+    #[test]
+    fn test_broken_state_is_rejected() {
+        assert!(matches!(Engine::load_state("engine\t1\t0\ntx\t1".as_bytes()), Err(ProcessingError::InvalidState { line: 2 })));
+        assert!(matches!(Engine::load_state("account\t1".as_bytes()), Err(ProcessingError::InvalidState { line: 1 })));
+    }
+
+    #[test]
+    fn test_adjustments_follow_what_was_read() {
+        let outcomes = Arc::new(Mutex::new(vec![]));
+        let sink_outcomes = outcomes.clone();
+        let options = ProcessingOptions {
+            outcome_sink: Some(OutcomeSink::new(move |outcome| sink_outcomes.lock().unwrap().push(outcome))),
+            ..Default::default()
+        };
+        let mut engine = Engine::new(options);
+        engine.process(Transaction::deposit(1, 1, 1.0)).unwrap();
+        assert!(engine.process(Transaction::withdrawal(1, 2, 3.0)).is_err());
+        // The operator tops up whoever ended up short of their withdrawal.
+        let short = engine.results()[0].clone();
+        engine.adjust(Transaction::deposit(short.id, 3, 3.0 - short.available)).unwrap();
+        engine.process(Transaction::withdrawal(1, 4, 3.0)).unwrap();
+        assert_eq!(engine.results(), vec![
+            ClientStatus { id: 1, available: 0.0, held: 0.0, held_from_withdrawals: 0.0, total: 0.0, locked: false, last_activity: None },
+        ]);
+        let adjustments: Vec<bool> = outcomes.lock().unwrap().iter().map(|outcome| outcome.adjustment).collect();
+        assert_eq!(adjustments, vec![false, false, true, false]);
+    }
+}
@@ -0,0 +1,109 @@
+use std::io;
+use thiserror::Error;
+use crate::{ClientStatusError, TransactionParseError};
+
+/// Every error a run can report, so callers can tell them apart without
+/// downcasting.
+#[derive(Debug, Error)]
+pub enum ProcessingError {
+    #[error("Client {0} produced more than one result")]
+    DuplicateClientResult(u32),
+    #[error(transparent)]
+    Parse(#[from] TransactionParseError),
+    #[error(transparent)]
+    Client(#[from] ClientStatusError),
+    /// A client error, along with the client and the row of the input
+    /// whose transaction caused it.
+    #[error("{source}")]
+    ClientAt { client: u32, row: usize, line: Option<u64>, source: ClientStatusError },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    /// A csv error on a given row, located even when the csv layer itself
+    /// did not record where it happened.
+    #[error("{source} (line {line}, byte {byte})")]
+    CsvAt { line: u64, byte: u64, source: csv::Error },
+    #[error("Could not hand transaction {0} over to its worker")]
+    Channel(u32),
+    #[error("Transaction {0} belongs to client {1}, which is reserved for quarantined rows")]
+    QuarantineClientInUse(u32, u32),
+    #[error("{error} ({count} times)")]
+    Repeated { error: Box<ProcessingError>, count: usize },
+    #[error("Worker of client {client} panicked: {message}")]
+    WorkerPanicked { client: u32, message: String },
+    #[error("Expected schema {expected}, but the input has {}", found.as_deref().unwrap_or("none"))]
+    SchemaMismatch { expected: String, found: Option<String> },
+    #[error("Input does not start with the binary transaction header")]
+    NotBinaryInput,
+    #[error("Line {line} of the saved engine state is invalid")]
+    InvalidState { line: u64 },
+    #[error("Column layout is invalid: {0}")]
+    InvalidColumns(String),
+    #[error("Last row, on line {line}, was cut short")]
+    PartialRow { line: u64 },
+    #[error("A checksum can only be computed over csv or binary input, not over a transaction source")]
+    ChecksumUnsupported,
+    /// An error reading one of several files, along with its path.
+    #[error("{path}: {source}")]
+    InFile { path: String, source: Box<ProcessingError> },
+}
+
+impl ProcessingError {
+    /// A short name for the kind of error, stable enough for tools to
+    /// match on.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ProcessingError::DuplicateClientResult(_) => "duplicate_client_result",
+            ProcessingError::Parse(_) => "parse",
+            ProcessingError::Client(_) | ProcessingError::ClientAt { .. } => "client",
+            ProcessingError::Io(_) => "io",
+            ProcessingError::Csv(_) | ProcessingError::CsvAt { .. } => "csv",
+            ProcessingError::Channel(_) => "channel",
+            ProcessingError::QuarantineClientInUse(..) => "quarantine_client_in_use",
+            ProcessingError::Repeated { error, .. } | ProcessingError::InFile { source: error, .. } => error.kind(),
+            ProcessingError::WorkerPanicked { .. } => "worker_panicked",
+            ProcessingError::SchemaMismatch { .. } => "schema_mismatch",
+            ProcessingError::NotBinaryInput => "not_binary_input",
+            ProcessingError::InvalidState { .. } => "invalid_state",
+            ProcessingError::InvalidColumns(_) => "invalid_columns",
+            ProcessingError::PartialRow { .. } => "partial_row",
+            ProcessingError::ChecksumUnsupported => "checksum_unsupported",
+        }
+    }
+
+    pub fn client(&self) -> Option<u32> {
+        match self {
+            ProcessingError::DuplicateClientResult(client)
+            | ProcessingError::QuarantineClientInUse(_, client)
+            | ProcessingError::WorkerPanicked { client, .. }
+            | ProcessingError::ClientAt { client, .. } => Some(*client),
+            ProcessingError::Client(e) => e.client(),
+            ProcessingError::Repeated { error, .. } | ProcessingError::InFile { source: error, .. } => error.client(),
+            _ => None,
+        }
+    }
+
+    pub fn tx(&self) -> Option<u32> {
+        match self {
+            ProcessingError::Parse(TransactionParseError::ReservedTxId(tx))
+            | ProcessingError::Channel(tx)
+            | ProcessingError::QuarantineClientInUse(tx, _) => Some(*tx),
+            ProcessingError::Client(e) | ProcessingError::ClientAt { source: e, .. } => e.tx(),
+            ProcessingError::Repeated { error, .. } | ProcessingError::InFile { source: error, .. } => error.tx(),
+            _ => None,
+        }
+    }
+
+    /// The line of the input the error comes from, when it is known.
+    pub fn line(&self) -> Option<u64> {
+        match self {
+            ProcessingError::CsvAt { line, .. } | ProcessingError::InvalidState { line } | ProcessingError::PartialRow { line } => Some(*line),
+            ProcessingError::Parse(TransactionParseError::TooFewColumns { line, .. }) => Some(*line),
+            ProcessingError::Csv(e) => e.position().map(|position| position.line()),
+            ProcessingError::ClientAt { line, .. } => *line,
+            ProcessingError::Repeated { error, .. } | ProcessingError::InFile { source: error, .. } => error.line(),
+            _ => None,
+        }
+    }
+}
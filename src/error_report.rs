@@ -0,0 +1,99 @@
+use std::io::{self, Write};
+use crate::ProcessingError;
+
+/// Writes `errors` as a JSON array, one object per error with its kind,
+/// message and whatever is known about where it comes from. Unknown
+/// fields are `null`, like the line of a transaction that wasn't read
+/// from csv.
+pub fn write_error_report<W: Write>(errors: &[ProcessingError], mut writer: W) -> io::Result<()> {
+    writer.write_all(b"[")?;
+    for (i, error) in errors.iter().enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        let count = match error {
+            ProcessingError::Repeated { count, .. } => *count,
+            _ => 1,
+        };
+        write!(
+            writer,
+            "\n  {{\"type\": {}, \"message\": {}, \"client\": {}, \"tx\": {}, \"line\": {}, \"count\": {}}}",
+            json_string(error.kind()),
+            json_string(&error.to_string()),
+            json_number(error.client()),
+            json_number(error.tx()),
+            json_number(error.line()),
+            count,
+        )?;
+    }
+    if !errors.is_empty() {
+        writer.write_all(b"\n")?;
+    }
+    writer.write_all(b"]\n")
+}
+
+fn json_number<N: ToString>(n: Option<N>) -> String {
+    n.map_or_else(|| "null".to_owned(), |n| n.to_string())
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error_report::write_error_report;
+    use crate::{execute_transactions_with_options, ClientStatusError, ProcessingError, ProcessingOptions, TransactionParseError};
+
+    fn report(errors: &[ProcessingError]) -> String {
+        let mut out = vec![];
+        write_error_report(errors, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_no_errors_is_an_empty_array() {
+        assert_eq!(report(&[]), "[]\n");
+    }
+
+    #[test]
+    fn test_errors_are_written_with_what_is_known_about_them() {
+        let errors = vec![
+            ClientStatusError::InsufficientFounds(3f32, 5, 2f32).into(),
+            ProcessingError::Repeated { error: Box::new(TransactionParseError::InvalidTransactionType("f\"oo".to_owned()).into()), count: 3 },
+        ];
+        assert_eq!(report(&errors), concat!(
+            "[\n",
+            "  {\"type\": \"client\", \"message\": \"Not enough founds to withdraw 3 during transaction 5, with available founds 2\", \"client\": null, \"tx\": 5, \"line\": null, \"count\": 1},\n",
+            "  {\"type\": \"parse\", \"message\": \"Transaction type f\\\"oo is invalid (3 times)\", \"client\": null, \"tx\": null, \"line\": null, \"count\": 3}\n",
+            "]\n",
+        ));
+    }
+
+    #[test]
+    fn test_errors_of_a_run_know_their_client_and_line() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,2,5.0\ndispute,2,9,";
+        let mut errors = execute_transactions_with_options(transactions.as_bytes(), 2, ProcessingOptions::default()).errors;
+        errors.sort_by_key(|e| e.line());
+        assert_eq!(report(&errors), concat!(
+            "[\n",
+            "  {\"type\": \"client\", \"message\": \"Not enough founds to withdraw 5 during transaction 2, with available founds 1\", \"client\": 1, \"tx\": 2, \"line\": 3, \"count\": 1},\n",
+            "  {\"type\": \"client\", \"message\": \"Transaction 9 could not complete\", \"client\": 2, \"tx\": 9, \"line\": 4, \"count\": 1}\n",
+            "]\n",
+        ));
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::mem::size_of;
+use std::sync::Mutex;
+
+use crate::client_status::{ClientAccount, Routed, TransactionRecord};
+use crate::{check_schema, csv_reader, parse_record, read_headers, ProcessingError, ProcessingOptions, Transaction};
+
+/// What processing a feed is expected to take, as found by `estimate_memory`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryEstimate {
+    pub rows: usize,
+    /// Rows that don't parse, which are left out of the estimate.
+    pub skipped_rows: usize,
+    pub clients: usize,
+    /// Deposits, withdrawals and fees of the busiest client, which are all kept
+    /// around in case they are disputed.
+    pub max_transactions_per_client: usize,
+    /// Bytes of the accounts and the transactions they remember.
+    pub status_bytes: usize,
+    /// Bytes of the channels if the readers get ahead of every worker.
+    pub channel_bytes: usize,
+}
+
+impl MemoryEstimate {
+    pub fn total_bytes(&self) -> usize {
+        self.status_bytes + self.channel_bytes
+    }
+}
+
+/// Hash maps keep about an eighth of their slots free, plus a control byte per slot.
+fn map_bytes<K, V>(entries: usize) -> usize {
+    entries * 8 / 7 * (size_of::<K>() + size_of::<V>() + 1)
+}
+
+/// Goes through the feed without processing it, to estimate the memory the
+/// processing would need. Rows that don't parse are counted and left out,
+/// and a schema marker is skipped like processing does. The reader is left
+/// where it was, ready for the real pass.
+pub fn estimate_memory<R: Read + Seek>(mut reader: R) -> Result<MemoryEstimate, ProcessingError> {
+    let start = reader.stream_position()?;
+    let options = ProcessingOptions::default();
+    let warnings = Mutex::new(vec![]);
+    let mut transactions: HashMap<u32, usize> = HashMap::new();
+    let mut rows = 0;
+    let mut skipped_rows = 0;
+    {
+        let mut input = BufReader::new(&mut reader);
+        check_schema(&mut input, &options)?;
+        let mut csv = csv_reader(input, &options);
+        let headers = read_headers(&mut csv, &options)?;
+        for record in csv.records() {
+            rows += 1;
+            let Ok(transaction) = record.map_err(ProcessingError::from).and_then(|record| parse_record(&record, &headers, &options, &warnings)) else {
+                skipped_rows += 1;
+                continue;
+            };
+            let stored = transactions.entry(transaction.get_client()).or_default();
+            if matches!(transaction, Transaction::Deposit { .. } | Transaction::Withdrawal { .. } | Transaction::Fee { .. }) {
+                *stored += 1;
+            }
+        }
+    }
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(MemoryEstimate {
+        rows,
+        skipped_rows,
+        clients: transactions.len(),
+        max_transactions_per_client: transactions.values().copied().max().unwrap_or(0),
+        status_bytes: map_bytes::<u32, ClientAccount>(transactions.len())
+            + transactions.values().map(|count| map_bytes::<u32, TransactionRecord>(*count)).sum::<usize>(),
+        channel_bytes: rows * size_of::<Routed>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+    use std::mem::size_of;
+    use crate::client_status::{ClientAccount, Routed, TransactionRecord};
+    use crate::estimate_memory;
+
+    #[test]
+    fn test_memory_is_estimated_from_clients_and_transactions() {
+        let mut input = Cursor::new("type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\nwithdrawal,1,3,0.5\ndispute,1,1,\ndeposit,2,4,1.0\nbanana,3,5,1.0\ndeposit,1\n");
+        let estimate = estimate_memory(&mut input).unwrap();
+        assert_eq!(estimate.rows, 7);
+        assert_eq!(estimate.skipped_rows, 2);
+        assert_eq!(estimate.clients, 2);
+        assert_eq!(estimate.max_transactions_per_client, 3);
+        // Two accounts, with three and one transactions, take a slot each
+        // in maps too small to keep any free.
+        let account = 4 + size_of::<ClientAccount>() + 1;
+        let transaction = 4 + size_of::<TransactionRecord>() + 1;
+        assert_eq!(estimate.status_bytes, 2 * account + 3 * transaction + transaction);
+        assert_eq!(estimate.channel_bytes, 7 * size_of::<Routed>());
+        assert_eq!(estimate.total_bytes(), estimate.status_bytes + estimate.channel_bytes);
+        let mut header = String::new();
+        input.read_to_string(&mut header).unwrap();
+        assert!(header.starts_with("type,client"));
+    }
+
+    #[test]
+    fn test_schema_marker_is_skipped() {
+        let input = "# schema: v2\ntype,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,1.0";
+        let estimate = estimate_memory(Cursor::new(input)).unwrap();
+        assert_eq!((estimate.rows, estimate.skipped_rows, estimate.clients), (2, 0, 2));
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::path::Path;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::thread::{self, JoinHandle};
+use crate::{execute_source, CsvSource, ProcessingError, ProcessingOptions, ProcessingReport, ProcessingWarning, Transaction, TransactionSource};
+
+/// Transactions a file is parsed ahead of the ones being processed.
+const READ_AHEAD: usize = 1024;
+
+/// The transactions of several files, each one parsed on a thread of its
+/// own, handed over file after file.
+struct Files {
+    parsed: VecDeque<(Receiver<Parsed>, JoinHandle<()>)>,
+    warnings: Vec<ProcessingWarning>,
+    timestamp: Option<u64>,
+    line: Option<u64>,
+}
+
+/// A transaction as parsed, with the warnings raised while parsing it.
+struct Parsed {
+    read: Result<Transaction, ProcessingError>,
+    warnings: Vec<ProcessingWarning>,
+    timestamp: Option<u64>,
+    line: Option<u64>,
+}
+
+impl TransactionSource for Files {
+    fn next(&mut self) -> Option<Result<Transaction, ProcessingError>> {
+        while let Some((parsed, _)) = self.parsed.front() {
+            match parsed.recv() {
+                Ok(Parsed { read, warnings, timestamp, line }) => {
+                    (self.warnings, self.timestamp, self.line) = (warnings, timestamp, line);
+                    return Some(read);
+                }
+                Err(_) => {
+                    let (_, handle) = self.parsed.pop_front().unwrap();
+                    if let Err(panic) = handle.join() {
+                        std::panic::resume_unwind(panic);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn take_warnings(&mut self) -> Vec<ProcessingWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    /// The line within the file the transaction was read from.
+    fn line(&self) -> Option<u64> {
+        self.line
+    }
+}
+
+impl Drop for Files {
+    /// Lets the threads of the files that were not read to the end give
+    /// up, and waits for them.
+    fn drop(&mut self) {
+        let handles: Vec<JoinHandle<()>> = self.parsed.drain(..).map(|(_, handle)| handle).collect();
+        for handle in handles {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Whether nothing more can be read once the error happened.
+fn is_io_error(e: &ProcessingError) -> bool {
+    match e {
+        ProcessingError::Io(_) => true,
+        ProcessingError::Csv(source) | ProcessingError::CsvAt { source, .. } => source.is_io_error(),
+        _ => false,
+    }
+}
+
+fn parse(path: &Path, options: ProcessingOptions) -> (Receiver<Parsed>, JoinHandle<()>) {
+    let (sender, receiver) = sync_channel(READ_AHEAD);
+    let path = path.to_owned();
+    let handle = thread::spawn(move || {
+        let in_file = |e: ProcessingError| Parsed {
+            read: Err(ProcessingError::InFile { path: path.display().to_string(), source: Box::new(e) }),
+            warnings: vec![],
+            timestamp: None,
+            line: None,
+        };
+        let source = File::open(&path).map_err(ProcessingError::from).and_then(|file| CsvSource::with_options(file, options));
+        let mut source = match source {
+            Ok(source) => source,
+            Err(e) => {
+                let _ = sender.send(in_file(e));
+                return;
+            }
+        };
+        while let Some(read) = source.next() {
+            let stop = read.as_ref().is_err_and(is_io_error);
+            let parsed = match read {
+                Ok(transaction) => Parsed {
+                    read: Ok(transaction),
+                    warnings: source.take_warnings(),
+                    timestamp: source.timestamp(),
+                    line: source.line(),
+                },
+                Err(e) => Parsed { warnings: source.take_warnings(), ..in_file(e) },
+            };
+            // Sending only fails once the run stopped reading.
+            if sender.send(parsed).is_err() || stop {
+                return;
+            }
+        }
+    });
+    (receiver, handle)
+}
+
+/// Processes the csv files at `paths` as a single input, with every client
+/// shared between them. The files are parsed at the same time, but the
+/// transactions of each one are processed after those of the files before
+/// it, as if they were concatenated in that order, so a dispute can refer
+/// to a transaction of an earlier file.
+///
+/// A file that cannot be opened or read is reported with its path, along
+/// with the errors of its rows, and the other files are still processed.
+pub fn execute_files<P: AsRef<Path>>(paths: &[P], threads: usize, options: ProcessingOptions) -> ProcessingReport {
+    let parsed = paths.iter().map(|path| parse(path.as_ref(), options.clone())).collect();
+    execute_source(Files { parsed, warnings: vec![], timestamp: None, line: None }, threads, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+    use crate::{execute_files, ClientStatus, ProcessingError, ProcessingOptions};
+
+    #[test]
+    fn test_disputes_can_refer_to_earlier_files() {
+        let months = [
+            "type,client,tx,amount\ndeposit,1,1,5.0\ndeposit,2,2,3.0\n",
+            "type,client,tx,amount\nwithdrawal,2,3,1.0\ndispute,1,1,\nwithdrawal,2,5,9.0\n",
+            "type,client,tx,amount\nchargeback,1,1,\ndeposit,2,4,0.5\n",
+        ];
+        let paths: Vec<_> = months.iter().enumerate().map(|(i, month)| {
+            let path = env::temp_dir().join(format!("solution-month-{}-{}.csv", std::process::id(), i));
+            fs::write(&path, month).unwrap();
+            path
+        }).collect();
+        for threads in [1, 2] {
+            let mut report = execute_files(&paths, threads, ProcessingOptions::default());
+            report.results.sort_by_key(|status| status.id);
+            // The line is the one within the file the row was read from.
+            assert!(matches!(report.errors[..], [ProcessingError::ClientAt { client: 2, row: 4, line: Some(4), .. }]));
+            assert_eq!(report.results, vec![
+                ClientStatus { id: 1, available: 0.0, held: 0.0, held_from_withdrawals: 0.0, total: 0.0, locked: true, last_activity: None },
+                ClientStatus { id: 2, available: 2.5, held: 0.0, held_from_withdrawals: 0.0, total: 2.5, locked: false, last_activity: None },
+            ]);
+        }
+        for path in paths {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_missing_file_is_an_error_of_its_own() {
+        let missing = env::temp_dir().join("solution-no-such-month.csv");
+        let present = env::temp_dir().join(format!("solution-present-month-{}.csv", std::process::id()));
+        fs::write(&present, "type,client,tx,amount\ndeposit,1,1,5.0\nfoo,1,2,1.0\n").unwrap();
+        let report = execute_files(&[&missing, &present], 1, ProcessingOptions::default());
+        fs::remove_file(&present).unwrap();
+        assert_eq!(report.results.iter().map(|status| status.total).collect::<Vec<f32>>(), vec![5.0]);
+        assert!(matches!(
+            &report.errors[..],
+            [ProcessingError::InFile { path, source }, ProcessingError::InFile { .. }]
+                if *path == missing.display().to_string() && matches!(**source, ProcessingError::Io(_))
+        ));
+    }
+}
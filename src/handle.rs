@@ -0,0 +1,108 @@
+use std::io::Read;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use crate::{execute_transactions_with_options, ProcessingOptions, ProcessingReport};
+
+/// Lets another thread hold the reader loop between two rows.
+#[derive(Debug, Default)]
+pub struct PauseSwitch {
+    // Checked on every row, so the mutex is only taken while paused.
+    paused: AtomicBool,
+    lock: Mutex<()>,
+    resumed: Condvar,
+}
+
+impl PauseSwitch {
+    pub fn pause(&self) {
+        let _guard = self.lock.lock().unwrap();
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        let _guard = self.lock.lock().unwrap();
+        self.paused.store(false, Ordering::SeqCst);
+        self.resumed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn wait_while_paused(&self) {
+        if !self.is_paused() {
+            return;
+        }
+        let guard = self.lock.lock().unwrap();
+        let _guard = self.resumed.wait_while(guard, |_| self.is_paused()).unwrap();
+    }
+}
+
+/// A run happening in the background, which can be paused and resumed.
+pub struct ProcessingHandle {
+    pause: Arc<PauseSwitch>,
+    thread: JoinHandle<ProcessingReport>,
+}
+
+/// Starts processing the transactions in a background thread and returns
+/// straight away.
+pub fn execute_transactions_handle<R: Read + Send + 'static>(
+    reader: R,
+    threads: usize,
+    mut options: ProcessingOptions,
+) -> ProcessingHandle {
+    let pause = options.pause.get_or_insert_with(Default::default).clone();
+    let thread = thread::spawn(move || execute_transactions_with_options(reader, threads, options));
+    ProcessingHandle { pause, thread }
+}
+
+impl ProcessingHandle {
+    /// Stops reading transactions. The ones already read are still
+    /// processed.
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+
+    /// Waits for the run to finish, resuming it first if it was paused.
+    pub fn wait(self) -> ProcessingReport {
+        self.pause.resume();
+        self.thread.join().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use crate::{execute_transactions_handle, ClientStatus, ProcessingOptions};
+    use crate::handle::PauseSwitch;
+
+    const TRANSACTIONS: &str = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\nwithdrawal,1,3,0.5";
+
+    #[test]
+    fn test_paused_handle_completes_after_resume() {
+        let handle = execute_transactions_handle(TRANSACTIONS.as_bytes(), 1, ProcessingOptions::default());
+        handle.pause();
+        handle.resume();
+        let report = handle.wait();
+        assert_eq!(report.results, vec![ClientStatus { id: 1, available: 2.5, held: 0.0, held_from_withdrawals: 0.0, total: 2.5, locked: false, last_activity: None }]);
+    }
+
+    #[test]
+    fn test_reader_waits_while_paused() {
+        let pause = Arc::new(PauseSwitch::default());
+        pause.pause();
+        let options = ProcessingOptions { pause: Some(pause.clone()), ..Default::default() };
+        let handle = execute_transactions_handle(TRANSACTIONS.as_bytes(), 1, options);
+        thread::sleep(Duration::from_millis(50));
+        assert!(!handle.thread.is_finished());
+        pause.resume();
+        let report = handle.wait();
+        assert_eq!(report.results.len(), 1);
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::io::Read;
+use std::iter::Peekable;
+use std::sync::{Arc, Mutex};
+use std::vec::IntoIter;
+use crossbeam_channel::{unbounded, Receiver};
+use threadpool::ThreadPool;
+use crate::client_status::WorkerOutput;
+use crate::{process_transactions, thread_pool, ClientStatus, ProcessingError, ProcessingOptions};
+
+/// Yields the status of every client as soon as its worker is done with it.
+///
+/// Errors are only known once every worker finished, so they come after
+/// all the statuses. Dropping the iterator waits for the outstanding
+/// workers.
+pub struct ClientStatusIter {
+    pool: ThreadPool,
+    results: Receiver<WorkerOutput>,
+    errors: Arc<Mutex<Vec<ProcessingError>>>,
+    /// Statuses of the last worker that reported, not yielded yet.
+    statuses: VecDeque<ClientStatus>,
+    /// Errors the workers handed back, yielded after the shared ones.
+    worker_errors: Vec<ProcessingError>,
+    seen: HashSet<u32>,
+    pending_errors: Option<IntoIter<ProcessingError>>,
+}
+
+pub fn execute_transactions_iter<R: Read>(reader: R, threads: usize) -> ClientStatusIter {
+    start(reader, threads).0
+}
+
+/// Like [`execute_transactions_iter`], but the statuses come out ordered by
+/// client id.
+pub fn execute_transactions_sorted_iter<R: Read>(reader: R, threads: usize) -> SortedClientStatusIter {
+    let (inner, clients) = start(reader, threads);
+    sorted(inner, clients)
+}
+
+fn sorted(inner: ClientStatusIter, mut clients: Vec<u32>) -> SortedClientStatusIter {
+    clients.sort_unstable();
+    SortedClientStatusIter {
+        inner,
+        waiting: clients.into_iter().peekable(),
+        buffered: BTreeMap::new(),
+        workers_done: false,
+        deferred: VecDeque::new(),
+    }
+}
+
+fn start<R: Read>(reader: R, threads: usize) -> (ClientStatusIter, Vec<u32>) {
+    let (result, results) = unbounded();
+    let errors: Arc<Mutex<Vec<ProcessingError>>> = Arc::new(Mutex::new(vec![]));
+    let warnings = Arc::new(Mutex::new(vec![]));
+    let pool = thread_pool(threads, &warnings);
+
+    let (_, clients) = process_transactions(reader, &pool, &result, &errors, &warnings, &Arc::new(ProcessingOptions::default()));
+
+    let iter = ClientStatusIter {
+        pool,
+        results,
+        errors,
+        statuses: VecDeque::new(),
+        worker_errors: vec![],
+        seen: HashSet::new(),
+        pending_errors: None,
+    };
+    (iter, clients)
+}
+
+impl Iterator for ClientStatusIter {
+    type Item = Result<ClientStatus, ProcessingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pending_errors.is_none() {
+            // Every worker holds a sender, so this only fails once all of
+            // them are done.
+            while self.statuses.is_empty() {
+                let Ok(output) = self.results.recv() else {
+                    break;
+                };
+                self.statuses.extend(output.statuses);
+                self.worker_errors.extend(output.errors);
+            }
+            if let Some(status) = self.statuses.pop_front() {
+                if !self.seen.insert(status.id) {
+                    return Some(Err(ProcessingError::DuplicateClientResult(status.id)));
+                }
+                return Some(Ok(status));
+            }
+            let mut errors = std::mem::take(&mut *self.errors.lock().unwrap());
+            errors.append(&mut self.worker_errors);
+            self.pending_errors = Some(errors.into_iter());
+        }
+        self.pending_errors.as_mut()
+            .and_then(|errors| errors.next())
+            .map(Err)
+    }
+}
+
+impl Drop for ClientStatusIter {
+    fn drop(&mut self) {
+        self.pool.join();
+    }
+}
+
+/// Merges the statuses of the workers, which finish in any order, back into
+/// client id order.
+///
+/// The watermark is the lowest client id whose worker has not reported yet.
+/// A status is yielded as soon as it sits at the watermark; anything above
+/// it waits in a buffer. The buffer is not bounded: it holds every status
+/// that finished ahead of a lower client, so when the lowest client reports
+/// last, it holds all of them. A worker that never reports only holds the
+/// rest back until every other worker is done.
+///
+/// Errors come after all the statuses, duplicated results included, so
+/// the statuses stay sorted.
+pub struct SortedClientStatusIter {
+    inner: ClientStatusIter,
+    /// Dispatched clients that were not yielded yet, lowest first.
+    waiting: Peekable<IntoIter<u32>>,
+    buffered: BTreeMap<u32, ClientStatus>,
+    workers_done: bool,
+    /// Errors seen among the statuses, held until the buffer is drained.
+    deferred: VecDeque<Result<ClientStatus, ProcessingError>>,
+}
+
+impl SortedClientStatusIter {
+    fn pop_ready(&mut self) -> Option<ClientStatus> {
+        let (&id, _) = self.buffered.first_key_value()?;
+        if !self.workers_done {
+            if self.waiting.peek() != Some(&id) {
+                return None;
+            }
+            self.waiting.next();
+        }
+        self.buffered.remove(&id)
+    }
+}
+
+impl Iterator for SortedClientStatusIter {
+    type Item = Result<ClientStatus, ProcessingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(status) = self.pop_ready() {
+                return Some(Ok(status));
+            }
+            if self.workers_done {
+                return self.deferred.pop_front().or_else(|| self.inner.next());
+            }
+            match self.inner.next() {
+                Some(Ok(status)) => {
+                    self.buffered.insert(status.id, status);
+                }
+                Some(duplicate @ Err(ProcessingError::DuplicateClientResult(_))) => self.deferred.push_back(duplicate),
+                other => {
+                    self.workers_done = true;
+                    self.deferred.extend(other);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashSet, VecDeque};
+    use std::sync::Arc;
+    use crossbeam_channel::unbounded;
+    use threadpool::ThreadPool;
+    use crate::client_status::WorkerOutput;
+    use crate::iter::{sorted, ClientStatusIter};
+    use crate::{execute_transactions_iter, execute_transactions_sorted_iter, ClientStatus, ProcessingError};
+
+    #[test]
+    fn test_iterator_yields_every_client_status() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\nfoo,1,3,1.0";
+        let items: Vec<_> = execute_transactions_iter(transactions.as_bytes(), 2).collect();
+        let mut statuses: Vec<_> = items.iter().filter_map(|item| item.as_ref().ok()).map(|s| s.id).collect();
+        statuses.sort();
+        assert_eq!(statuses, vec![1, 2]);
+        assert!(items.last().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_iterator_can_be_consumed_partially() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\ndeposit,3,3,3.0\ndeposit,4,4,4.0";
+        let taken: Vec<_> = execute_transactions_iter(transactions.as_bytes(), 2).take(2).collect();
+        assert_eq!(taken.len(), 2);
+        assert!(taken.iter().all(|item| item.is_ok()));
+    }
+
+    #[test]
+    fn test_sorted_iterator_yields_statuses_by_client_id() {
+        let transactions = "type,client,tx,amount\ndeposit,5,1,1.0\ndeposit,3,2,1.0\ndeposit,9,3,1.0\nfoo,1,4,1.0\n\
+            deposit,1,5,1.0\ndeposit,7,6,1.0\ndeposit,2,7,1.0\ndeposit,8,8,1.0\ndeposit,4,9,1.0\ndeposit,6,10,1.0";
+        let items: Vec<_> = execute_transactions_sorted_iter(transactions.as_bytes(), 4).collect();
+        let statuses: Vec<_> = items.iter().filter_map(|item| item.as_ref().ok()).map(|s| s.id).collect();
+        assert_eq!(statuses, vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert!(items.last().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_sorted_iterator_keeps_sorting_after_a_duplicate() {
+        let status = |id| ClientStatus { id, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None };
+        let (result, results) = unbounded();
+        for id in [3, 2, 2, 4, 1] {
+            result.send(WorkerOutput { statuses: vec![status(id)], errors: vec![] }).unwrap();
+        }
+        result.send(WorkerOutput { statuses: vec![], errors: vec![ProcessingError::WorkerPanicked { client: 5, message: "boom".to_owned() }] }).unwrap();
+        drop(result);
+        let inner = ClientStatusIter {
+            pool: ThreadPool::new(1),
+            results,
+            errors: Arc::default(),
+            statuses: VecDeque::new(),
+            worker_errors: vec![],
+            seen: HashSet::new(),
+            pending_errors: None,
+        };
+        let items: Vec<_> = sorted(inner, vec![4, 3, 2, 1, 5]).collect();
+        let statuses: Vec<_> = items.iter().filter_map(|item| item.as_ref().ok()).map(|s| s.id).collect();
+        assert_eq!(statuses, vec![1, 2, 3, 4]);
+        assert!(matches!(items[4..], [Err(ProcessingError::DuplicateClientResult(2)), Err(ProcessingError::WorkerPanicked { client: 5, .. })]));
+    }
+}
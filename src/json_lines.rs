@@ -0,0 +1,54 @@
+use std::io::{self, Write};
+use crate::{ClientStatus, ProcessingOptions};
+
+/// Writes `status` as a single line holding a JSON object with the output
+/// columns and the effective available funds under `options`, so that every
+/// line can be read on its own.
+pub fn write_json_line<W: Write>(status: &ClientStatus, options: &ProcessingOptions, mut writer: W) -> io::Result<()> {
+    writeln!(
+        writer,
+        "{{\"client\":{},\"available\":{},\"effective_available\":{},\"held\":{},\"total\":{},\"locked\":{}}}",
+        status.id, status.available, status.effective_available(options), status.held, status.total, status.locked,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json_lines::write_json_line;
+    use crate::{execute_transactions, ClientStatus, ProcessingOptions};
+
+    /// Whether `value` is a JSON number or boolean, the only values the
+    /// lines hold.
+    fn is_json_scalar(value: &str) -> bool {
+        value == "true" || value == "false" || value.parse::<f64>().is_ok_and(f64::is_finite)
+    }
+
+    /// Whether `line` is a flat JSON object of quoted keys and scalars.
+    fn is_json_object(line: &str) -> bool {
+        let Some(fields) = line.strip_prefix('{').and_then(|line| line.strip_suffix('}')) else {
+            return false;
+        };
+        fields.split(',').all(|field| match field.split_once(':') {
+            Some((key, value)) => key.len() > 2 && key.starts_with('"') && key.ends_with('"') && is_json_scalar(value),
+            None => false,
+        })
+    }
+
+    #[test]
+    fn test_every_line_is_a_json_object() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.5\ndeposit,2,2,2.0\ndispute,2,2,\nwithdrawal,1,3,0.25";
+        let (mut results, _) = execute_transactions(transactions.as_bytes(), 2);
+        results.sort_by_key(|status: &ClientStatus| status.id);
+        let mut out = vec![];
+        for status in &results {
+            write_json_line(status, &ProcessingOptions::default(), &mut out).unwrap();
+        }
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines, vec![
+            "{\"client\":1,\"available\":1.25,\"effective_available\":1.25,\"held\":0,\"total\":1.25,\"locked\":false}",
+            "{\"client\":2,\"available\":0,\"effective_available\":0,\"held\":2,\"total\":2,\"locked\":false}",
+        ]);
+        assert!(lines.iter().all(|line| is_json_object(line)));
+    }
+}
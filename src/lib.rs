@@ -1,81 +1,876 @@
-use std::collections::HashMap;
-use std::error::Error;
-use std::io::Read;
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Read};
 use std::sync::{Arc, Mutex};
-use crossbeam_channel::unbounded;
-use csv::Trim;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+#[cfg(feature = "parallel")]
+use crossbeam_channel::{unbounded, Sender};
+use csv::StringRecord;
+pub use csv::Trim;
+#[cfg(feature = "parallel")]
 use threadpool::ThreadPool;
-use client_status::ClientStatus;
-use transaction::{RawTransaction, Transaction, TransactionStatus};
+#[cfg(not(feature = "parallel"))]
+use client_status::ClientAccount;
+#[cfg(feature = "parallel")]
+use client_status::WorkerOutput;
+use client_status::{Message, Routed};
+use checkpoint::Checkpoint;
+pub use checkpoint::CheckpointSink;
+pub use client_status::{AccountSnapshot, ClientStatus, ClientStatusError, ClientStatusParseError, ClientStatusRecord, MinorUnitsRecord, SnapshotSink, StatusSink, Validator};
+use transaction::{round, RawTransaction};
+pub use transaction::{Transaction, TransactionKind, TransactionParseError, TransactionStatus, DECIMALS};
+pub use options::{
+    ClientFilter, ErrorPolicy, LockedDisputePolicy, NegativeTotalPolicy, OptionsError, PartitionKey, ProcessingOptions,
+    ProcessingOptionsBuilder, TrailingRowPolicy, DEFAULT_READ_BUFFER_SIZE,
+};
+pub use report::{read_expected_results, ProcessingReport, ReportDiff, SettlementSummary, TransactionCounts, DEFAULT_EPSILON};
+use report::InputStats;
+pub use error::ProcessingError;
+#[cfg(feature = "parallel")]
+pub use iter::{execute_transactions_iter, execute_transactions_sorted_iter, ClientStatusIter, SortedClientStatusIter};
+pub use normalize::normalize_transactions;
+pub use warning::ProcessingWarning;
+pub use handle::{execute_transactions_handle, PauseSwitch, ProcessingHandle};
+pub use clock::{Clock, FixedClock, SystemClock};
+pub use error_report::write_error_report;
+pub use json_lines::write_json_line;
+pub use source::{CsvSource, TransactionSource};
+pub use checksum::ChecksumAlgorithm;
+pub use files::execute_files;
+#[cfg(unix)]
+pub use mmap::execute_transactions_mapped;
+use source::{FromSource, Input};
+pub use progress::count_rows;
+pub use audit::{AuditTrail, FinalStatuses, StatusTransition};
+pub use columns::read_columns;
+pub use estimate::{estimate_memory, MemoryEstimate};
+pub use binary::{encode_transaction, encode_transactions};
+use binary::BinaryReader;
+pub use engine::Engine;
+pub use outcome::{write_tx_log, Outcome, OutcomeSink, SkipReason, SkippedTransactions, TransactionOutcome};
+use outcome::record_outcome;
 
 mod transaction;
 mod client_status;
+mod options;
+mod report;
+mod error;
+#[cfg(feature = "parallel")]
+mod iter;
+mod normalize;
+mod warning;
+mod handle;
+mod clock;
+mod error_report;
+mod progress;
+mod binary;
+mod engine;
+mod outcome;
+mod estimate;
+mod checkpoint;
+mod columns;
+mod audit;
+mod json_lines;
+mod source;
+mod checksum;
+mod files;
+#[cfg(unix)]
+mod mmap;
 
-pub fn execute_transactions<R: Read>(reader: R, threads: usize) -> (Vec<ClientStatus>, Vec<Box<dyn Error + Send>>) {
-    let pool = ThreadPool::new(threads);
-    let result = Arc::new(Mutex::new(vec![]));
-    let errors: Arc<Mutex<Vec<Box<dyn Error + Send>>>> = Arc::new(Mutex::new(vec![]));
+pub fn execute_transactions<R: Read>(reader: R, threads: usize) -> (Vec<ClientStatus>, Vec<ProcessingError>) {
+    let report = execute_transactions_with_options(reader, threads, ProcessingOptions::default());
+    (report.results, report.errors)
+}
+
+/// Processes the transactions for at most `deadline`. Once it passes, no
+/// more transactions are read and the results reflect the ones read until
+/// then, which the workers still finish. The last value tells whether that
+/// happened.
+pub fn execute_transactions_timeout<R: Read>(
+    reader: R,
+    threads: usize,
+    deadline: Duration,
+) -> (Vec<ClientStatus>, Vec<ProcessingError>, bool) {
+    let options = ProcessingOptions { deadline: Some(Instant::now() + deadline), ..Default::default() };
+    let report = execute_transactions_with_options(reader, threads, options);
+    (report.results, report.errors, report.timed_out)
+}
+
+/// The input is read through a buffer of `options.read_buffer_size`
+/// bytes, so there is no need to hand over a buffered reader. Without the
+/// `parallel` feature every client is processed on the calling thread, so
+/// `threads` is ignored.
+pub fn execute_transactions_with_options<R: Read>(reader: R, threads: usize, options: ProcessingOptions) -> ProcessingReport {
+    execute_input(reader, threads, options)
+}
+
+/// Like [`execute_transactions_with_options`], for transactions coming from
+/// anywhere else than csv or the binary encoding.
+pub fn execute_source<S: TransactionSource>(source: S, threads: usize, options: ProcessingOptions) -> ProcessingReport {
+    execute_input(FromSource(source), threads, options)
+}
 
-    process_transactions(reader, &pool, &result, &errors);
+#[cfg(feature = "parallel")]
+fn execute_input<I: Input>(input: I, threads: usize, mut options: ProcessingOptions) -> ProcessingReport {
+    start_audit(&mut options);
+    let (result, result_receiver) = unbounded();
+    let errors: Arc<Mutex<Vec<ProcessingError>>> = Arc::new(Mutex::new(vec![]));
+    let warnings: Arc<Mutex<Vec<ProcessingWarning>>> = Arc::new(Mutex::new(vec![]));
+    let pool = thread_pool(threads, &warnings);
+
+    let options = Arc::new(options);
+    let (stats, _) = process_transactions(input, &pool, &result, &errors, &warnings, &options);
+    drop(result);
     pool.join();
 
-    (
-        Arc::try_unwrap(result).unwrap().into_inner().unwrap(),
-        Arc::try_unwrap(errors).unwrap().into_inner().unwrap()
-    )
+    // Every worker is done, so the errors are no longer shared.
+    let mut errors = Arc::try_unwrap(errors).unwrap().into_inner().unwrap();
+    let mut results = vec![];
+    for output in result_receiver.iter() {
+        results.extend(output.statuses);
+        errors.extend(output.errors);
+    }
+    build_report(results, errors, warnings, stats, &options)
+}
+
+/// How many threads per core the pool may have at most.
+#[cfg(feature = "parallel")]
+const MAX_THREADS_PER_CPU: usize = 64;
+
+/// Creates a pool with `threads` threads or, when that many can't be had,
+/// with as many as possible down to a single one, warning about it.
+#[cfg(feature = "parallel")]
+pub(crate) fn thread_pool(threads: usize, warnings: &Mutex<Vec<ProcessingWarning>>) -> ThreadPool {
+    let mut used = threads.clamp(1, num_cpus::get() * MAX_THREADS_PER_CPU);
+    let pool = loop {
+        match std::panic::catch_unwind(|| ThreadPool::new(used)) {
+            Ok(pool) => break pool,
+            Err(_) if used > 1 => used /= 2,
+            Err(e) => std::panic::resume_unwind(e),
+        }
+    };
+    if used != threads {
+        warnings.lock().unwrap().push(ProcessingWarning::ThreadPoolReduced { requested: threads, used });
+    }
+    pool
+}
+
+#[cfg(not(feature = "parallel"))]
+fn execute_input<I: Input>(input: I, _threads: usize, mut options: ProcessingOptions) -> ProcessingReport {
+    start_audit(&mut options);
+    let errors: Arc<Mutex<Vec<ProcessingError>>> = Arc::new(Mutex::new(vec![]));
+    let warnings: Arc<Mutex<Vec<ProcessingWarning>>> = Arc::new(Mutex::new(vec![]));
+    let mut accounts: HashMap<u32, ClientAccount> = HashMap::new();
+
+    let stats = input.read_into(&errors, &warnings, &options, |message| {
+        let routed = match message {
+            Message::Transaction(routed) => routed,
+            Message::Checkpoint(checkpoint) => {
+                checkpoint.expect(1);
+                checkpoint.add(accounts.values());
+                return;
+            }
+        };
+        let (client, row, line) = (routed.transaction.get_client(), routed.row, routed.line);
+        let account = accounts.entry(client).or_insert_with(|| ClientAccount::new(client));
+        if let Err(e) = account.process(routed, &options) {
+            errors.lock().unwrap().push(ProcessingError::ClientAt { client, row, line, source: e });
+        }
+    });
+
+    let results: Vec<ClientStatus> = accounts.values().filter_map(|account| account.finish(&options, &warnings)).collect();
+    build_report(results, Arc::try_unwrap(errors).unwrap().into_inner().unwrap(), warnings, stats, &options)
+}
+
+fn start_audit(options: &mut ProcessingOptions) {
+    if !options.audit_clients.is_empty() {
+        options.audit_trail.get_or_insert_with(Default::default);
+    }
+}
+
+fn build_report(
+    mut results: Vec<ClientStatus>,
+    mut errors: Vec<ProcessingError>,
+    warnings: Arc<Mutex<Vec<ProcessingWarning>>>,
+    stats: InputStats,
+    options: &ProcessingOptions,
+) -> ProcessingReport {
+    // Workers report their errors in batches and may have had transactions
+    // queued when the reader stopped, so the limit is enforced once more here.
+    if let Some(limit) = options.error_policy.error_limit() {
+        errors.truncate(limit);
+    }
+    if !options.client_priority.is_empty() {
+        sort_by_priority(&mut results, &options.client_priority);
+    }
+    errors.extend(check_unique_results(&results));
+    let warnings = Arc::try_unwrap(warnings).unwrap().into_inner().unwrap();
+    // Negative totals were already clamped as each client finished.
+    if options.on_negative_total == NegativeTotalPolicy::Error {
+        errors.extend(results.iter().filter(|status| status.total < 0f32).map(|status| ClientStatusError::NegativeTotal(status.id).into()));
+    }
+    if options.atomic && !errors.is_empty() {
+        results.clear();
+    }
+    if options.sort_errors {
+        errors.sort_by_key(|e| (e.line(), e.client(), e.tx()));
+    }
+
+    ProcessingReport {
+        results,
+        errors,
+        warnings,
+        filtered_transactions: stats.filtered_transactions,
+        timed_out: stats.timed_out,
+        transaction_counts: stats.counts,
+        amounts: stats.amounts,
+        transitions: options.audit_trail.as_ref().map(AuditTrail::transitions).unwrap_or_default(),
+        final_statuses: options.final_statuses.as_ref().map(FinalStatuses::statuses).unwrap_or_default(),
+        skipped: options.skipped.as_ref().map(SkippedTransactions::transactions).unwrap_or_default(),
+        input_checksum: stats.checksum,
+    }
+}
+
+fn sort_by_priority(results: &mut [ClientStatus], priority: &[u32]) {
+    let ranks: HashMap<u32, usize> = priority.iter().enumerate().map(|(rank, id)| (*id, rank)).collect();
+    results.sort_by_key(|status| (ranks.get(&status.id).copied().unwrap_or(usize::MAX), status.id));
 }
 
-fn process_transactions<R: Read>(
+/// Every client is handled by exactly one worker, so more than one result
+/// for the same id means the dispatch went wrong.
+fn check_unique_results(results: &[ClientStatus]) -> Vec<ProcessingError> {
+    let mut seen = HashSet::new();
+    results.iter()
+        .filter(|status| !seen.insert(status.id))
+        .map(|status| ProcessingError::DuplicateClientResult(status.id))
+        .collect()
+}
+
+fn csv_reader<R: Read>(reader: R, options: &ProcessingOptions) -> csv::Reader<R> {
+    csv::ReaderBuilder::new()
+        .has_headers(options.columns.is_none())
+        .delimiter(options.delimiter)
+        .trim(options.trim)
+        .from_reader(reader)
+}
+
+/// The column names of the input: the configured columns when it has no
+/// header row, or the header row otherwise.
+fn read_headers<R: Read>(reader: &mut csv::Reader<R>, options: &ProcessingOptions) -> Result<StringRecord, ProcessingError> {
+    let headers = match &options.columns {
+        Some(columns) => columns::check_columns(columns).map(|_| StringRecord::from(columns.clone()))?,
+        None => reader.headers()?.clone(),
+    };
+    if options.case_insensitive_headers {
+        Ok(headers.iter().map(str::to_lowercase).collect())
+    } else {
+        Ok(headers)
+    }
+}
+
+fn replace_field(record: &StringRecord, index: usize, value: &str) -> StringRecord {
+    record.iter()
+        .enumerate()
+        .map(|(i, field)| if i == index { value } else { field })
+        .collect()
+}
+
+/// Turns a csv record into a transaction. Every entry point reading csv
+/// goes through here, so they all agree on what a valid row is.
+fn parse_record(
+    record: &StringRecord,
+    headers: &StringRecord,
+    options: &ProcessingOptions,
+    warnings: &Mutex<Vec<ProcessingWarning>>,
+) -> Result<Transaction, ProcessingError> {
+    let (transaction, quarantined) = parse_raw_record(record, headers, options, warnings)?;
+    validate(transaction, quarantined, options, warnings)
+}
+
+/// Parses a record as it is, before [`validate`], telling whether it was
+/// assigned to the quarantine client.
+fn parse_raw_record(
+    record: &StringRecord,
+    headers: &StringRecord,
+    options: &ProcessingOptions,
+    warnings: &Mutex<Vec<ProcessingWarning>>,
+) -> Result<(Transaction, bool), ProcessingError> {
+    let amount_index = headers.iter().position(|header| header == "amount");
+    let decimal_amount = amount_index
+        .filter(|_| options.decimal_comma)
+        .and_then(|i| record.get(i).map(|amount| (i, amount.replace(',', "."))));
+    let normalized: Cow<StringRecord> = match decimal_amount {
+        Some((i, amount)) => Cow::Owned(replace_field(record, i, &amount)),
+        None => Cow::Borrowed(record),
+    };
+    let (mut raw_transaction, quarantined) = match (normalized.deserialize::<RawTransaction>(Some(headers)), options.quarantine_client) {
+        (Ok(rt), _) => (rt, false),
+        (Err(e), Some(quarantine_client)) => match quarantine(&normalized, headers, quarantine_client) {
+            Some((rt, client)) => {
+                warnings.lock().unwrap().push(ProcessingWarning::Quarantined { tx: rt.tx.unwrap_or_default(), client });
+                (rt, true)
+            }
+            None => return Err(e.into()),
+        },
+        (Err(e), None) => return Err(e.into()),
+    };
+    if options.retain_raw_amounts {
+        raw_transaction.raw_amount = amount_index.and_then(|i| record.get(i)).map(str::to_owned);
+    }
+    Ok((raw_transaction.try_into()?, quarantined))
+}
+
+/// The checks every transaction goes through before it is dispatched,
+/// whatever it was read from. Amounts are rounded last, as some of the
+/// checks need them as they were read.
+fn validate(
+    mut transaction: Transaction,
+    quarantined: bool,
+    options: &ProcessingOptions,
+    warnings: &Mutex<Vec<ProcessingWarning>>,
+) -> Result<Transaction, ProcessingError> {
+    let (client, tx) = (transaction.get_client(), transaction.get_tx());
+    if let Some(quarantine_client) = options.quarantine_client.filter(|&quarantine_client| quarantine_client == client && !quarantined) {
+        return Err(ProcessingError::QuarantineClientInUse(tx, quarantine_client));
+    }
+    if !transaction.kind().is_bulk() && options.reserved_tx_ids.contains(&tx) {
+        return Err(TransactionParseError::ReservedTxId(tx).into());
+    }
+    if let Some((amount, raw_amount)) = transaction.amount_mut() {
+        let rounded = round(*amount);
+        if options.reject_subprecision_amounts && *amount != 0f32 && rounded == 0f32 {
+            return Err(ClientStatusError::SubPrecisionAmount(tx).into());
+        }
+        if !options.retain_raw_amounts {
+            *raw_amount = None;
+        } else if raw_amount.is_none() {
+            *raw_amount = Some(amount.to_string());
+        }
+        if options.warn_on_precision_loss && (rounded - *amount).abs() > f32::EPSILON * amount.abs().max(1f32) {
+            warnings.lock().unwrap().push(ProcessingWarning::PrecisionTruncated { tx, original: *amount, rounded });
+        }
+        *amount = rounded;
+    }
+    check_type(transaction, options)
+}
+
+fn check_type(transaction: Transaction, options: &ProcessingOptions) -> Result<Transaction, ProcessingError> {
+    if options.allowed_types.contains(&transaction.kind()) {
+        Ok(transaction)
+    } else {
+        Err(TransactionParseError::TypeNotAllowed(transaction.kind()).into())
+    }
+}
+
+/// Assigns a record whose only problem is its client field to the
+/// quarantine client, returning the original field along with it.
+fn quarantine(record: &StringRecord, headers: &StringRecord, quarantine_client: u32) -> Option<(RawTransaction, String)> {
+    let client_index = headers.iter().position(|header| header == "client")?;
+    let client = record.get(client_index)?;
+    if client.parse::<u32>().is_ok() {
+        return None;
+    }
+    let reassigned = replace_field(record, client_index, &quarantine_client.to_string());
+    let raw_transaction = reassigned.deserialize::<RawTransaction>(Some(headers)).ok()?;
+    Some((raw_transaction, client.to_owned()))
+}
+
+const SCHEMA_MARKER: &str = "# schema:";
+
+/// Consumes the optional schema marker on the first line, returning the
+/// line as read. Without an expected schema the marker is just skipped.
+fn check_schema<R: Read>(input: &mut BufReader<R>, options: &ProcessingOptions) -> Result<Option<String>, ProcessingError> {
+    let mut schema_line = None;
+    if input.fill_buf()?.starts_with(b"#") {
+        let mut line = String::new();
+        input.read_line(&mut line)?;
+        schema_line = Some(line);
+    }
+    let found = schema_line.as_deref()
+        .and_then(|line| line.trim().strip_prefix(SCHEMA_MARKER))
+        .map(|schema| schema.trim().to_owned());
+    match &options.expected_schema {
+        Some(expected) if found.as_ref() != Some(expected) => {
+            Err(ProcessingError::SchemaMismatch { expected: expected.clone(), found })
+        }
+        _ => Ok(schema_line),
+    }
+}
+
+/// Reads the optional timestamp column, in seconds since the Unix epoch.
+fn parse_timestamp(record: &StringRecord, timestamp_index: Option<usize>, options: &ProcessingOptions) -> Result<Option<u64>, ProcessingError> {
+    let timestamp = match timestamp_index.and_then(|i| record.get(i)).filter(|timestamp| !timestamp.is_empty()) {
+        Some(timestamp) => timestamp.parse::<u64>()
+            .map_err(|_| TransactionParseError::InvalidTimestamp(timestamp.to_owned()))?,
+        None => return Ok(None),
+    };
+    if options.reject_future_timestamps && timestamp > options.clock.now() {
+        return Err(TransactionParseError::FutureTimestamp(timestamp).into());
+    }
+    Ok(Some(timestamp))
+}
+
+/// The rows of a csv input, past its schema marker and header row, read
+/// the same way whatever the reader is.
+pub(crate) struct CsvRows<R> {
+    reader: csv::Reader<BufReader<InputTail<R>>>,
+    headers: StringRecord,
+    schema_line: Option<String>,
+    tail: Arc<Mutex<Tail>>,
+    timestamp_index: Option<usize>,
+    record: StringRecord,
+}
+
+/// What [`CsvRows::next_row`] read.
+pub(crate) enum Row {
+    Read { line: u64, timestamp: Option<u64>, transaction: Transaction, quarantined: bool },
+    Failed(ProcessingError),
+    /// A partial last row, left out under [`TrailingRowPolicy::Ignore`].
+    Ignored,
+    End,
+}
+
+impl<R: Read> CsvRows<R> {
+    pub(crate) fn new(reader: R, options: &ProcessingOptions) -> Result<CsvRows<R>, ProcessingError> {
+        let tail = Arc::new(Mutex::new(Tail::default()));
+        let mut input = BufReader::with_capacity(options.read_buffer_size, InputTail { inner: reader, tail: tail.clone() });
+        let schema_line = check_schema(&mut input, options)?;
+        let mut reader = csv_reader(input, options);
+        let headers = read_headers(&mut reader, options)?;
+        let timestamp_index = headers.iter().position(|header| header == "timestamp");
+        Ok(CsvRows { reader, headers, schema_line, tail, timestamp_index, record: StringRecord::new() })
+    }
+
+    /// Reads the next row, yet to be [`validate`]d.
+    pub(crate) fn next_row(&mut self, options: &ProcessingOptions, warnings: &Mutex<Vec<ProcessingWarning>>) -> Row {
+        let (headers, record) = (&self.headers, &mut self.record);
+        // The position is taken before reading, so it points at the start
+        // of the row even if the csv layer gives up halfway through it.
+        let position = self.reader.position().clone();
+        let line = position.line() + self.schema_line.is_some() as u64;
+        let too_few_columns = |got: usize| TransactionParseError::TooFewColumns { expected: headers.len(), got, line };
+        let read = match self.reader.read_record(record) {
+            Ok(true) if record.len() < headers.len() => Err(too_few_columns(record.len()).into()),
+            Ok(true) => parse_timestamp(record, self.timestamp_index, options)
+                .and_then(|timestamp| Ok((timestamp, parse_raw_record(record, headers, options, warnings)?))),
+            Ok(false) => return Row::End,
+            Err(e) => match e.kind() {
+                csv::ErrorKind::UnequalLengths { len, .. } if (*len as usize) < headers.len() => Err(too_few_columns(*len as usize).into()),
+                _ => Err(e.into()),
+            },
+        };
+        let consumed = self.reader.position().byte() + self.schema_line.as_ref().map_or(0, |line| line.len() as u64);
+        let read = match options.on_trailing_partial_row {
+            TrailingRowPolicy::Parse => read,
+            _ if !self.tail.lock().unwrap().is_partial_row(consumed) => read,
+            TrailingRowPolicy::Ignore => return Row::Ignored,
+            TrailingRowPolicy::Error => Err(ProcessingError::PartialRow { line }),
+        };
+        match read {
+            Ok((timestamp, (transaction, quarantined))) => Row::Read { line, timestamp, transaction, quarantined },
+            Err(ProcessingError::Csv(source)) => {
+                let byte = position.byte() + self.schema_line.as_ref().map_or(0, |line| line.len() as u64);
+                Row::Failed(ProcessingError::CsvAt { line, byte, source })
+            }
+            Err(e) => Row::Failed(e),
+        }
+    }
+}
+
+/// Reads and parses every row of `reader`, handing the transactions that
+/// pass the client filter over to `dispatch` along with their timestamp.
+fn read_transactions<R: Read, F: FnMut(Message)>(
     reader: R,
-    pool: &ThreadPool,
-    result: &Arc<Mutex<Vec<ClientStatus>>>,
-    errors: &Arc<Mutex<Vec<Box<dyn Error + Send>>>>
-) {
-    let mut beams = HashMap::new();
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .trim(Trim::All)
-        .from_reader(reader);
-    for raw_transaction in reader.deserialize::<RawTransaction>() {
-        let raw_transaction = match raw_transaction {
-            Ok(rt) => rt,
-            Err(e) => {
-                let mut errors = errors.lock().unwrap();
-                errors.push(Box::new(e));
+    errors: &Arc<Mutex<Vec<ProcessingError>>>,
+    warnings: &Arc<Mutex<Vec<ProcessingWarning>>>,
+    options: &ProcessingOptions,
+    mut dispatch: F,
+) -> InputStats {
+    if options.binary_input {
+        return read_binary_transactions(reader, errors, warnings, options, dispatch);
+    }
+    let mut stats = InputStats::default();
+    let mut rows = match CsvRows::new(reader, options) {
+        Ok(rows) => rows,
+        Err(e) => {
+            errors.lock().unwrap().push(e);
+            return stats;
+        }
+    };
+    // Index of the first occurrence of every error, and how often it happened.
+    let mut repeated: HashMap<String, (usize, usize)> = HashMap::new();
+    let mut report_error = |e: ProcessingError| {
+        let mut errors = errors.lock().unwrap();
+        if options.collapse_repeated_errors {
+            match repeated.entry(e.to_string()) {
+                Entry::Occupied(mut entry) => {
+                    entry.get_mut().1 += 1;
+                    return;
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert((errors.len(), 1));
+                }
+            }
+        }
+        errors.push(e);
+    };
+    // The row at which the input ended, unless reading stopped before.
+    let mut end = None;
+    for row in 0.. {
+        if !keep_reading(errors, options, &mut stats) {
+            break;
+        }
+        dispatch_checkpoint(row, options, &mut dispatch);
+        let read = match rows.next_row(options, warnings) {
+            Row::Read { line, timestamp, transaction, quarantined } => {
+                validate(transaction, quarantined, options, warnings).map(|transaction| (line, timestamp, transaction))
+            }
+            Row::Failed(e) => Err(e),
+            Row::Ignored => {
+                count_row(options);
+                record_outcome(options, TransactionOutcome {
+                    row,
+                    tx: None,
+                    client: None,
+                    kind: None,
+                    outcome: Outcome::Ignored,
+                    reason: Some("partial last row".to_owned()),
+                    adjustment: false,
+                });
                 continue;
             }
+            Row::End => {
+                end = Some(row);
+                break;
+            }
         };
-        let transaction: Transaction = match raw_transaction.try_into() {
-            Ok(transaction) => transaction,
+        count_row(options);
+        match read {
+            Ok((line, timestamp, transaction)) => {
+                if accept(row, &transaction, options, &mut stats) {
+                    dispatch(Message::Transaction(Routed { row, line: Some(line), timestamp, adjustment: false, transaction }));
+                }
+            }
             Err(e) => {
-                let mut errors = errors.lock().unwrap();
-                errors.push(Box::new(e));
-                continue;
+                record_outcome(options, TransactionOutcome::unparsed(row, &e));
+                report_error(e);
+            }
+        }
+    }
+    if let Some(end) = end {
+        dispatch_adjustments(end, options, &mut stats, &mut dispatch);
+    }
+    if repeated.values().any(|(_, count)| *count > 1) {
+        let counts: HashMap<usize, usize> = repeated.into_values().collect();
+        let mut errors = errors.lock().unwrap();
+        *errors = std::mem::take(&mut *errors).into_iter()
+            .enumerate()
+            .map(|(index, error)| match counts.get(&index) {
+                Some(&count) if count > 1 => ProcessingError::Repeated { error: Box::new(error), count },
+                _ => error,
+            })
+            .collect();
+    }
+    stats
+}
+
+/// What was read of the input so far.
+#[derive(Clone, Copy, Debug, Default)]
+struct Tail {
+    bytes: u64,
+    last: Option<u8>,
+    ended: bool,
+}
+
+impl Tail {
+    /// Whether a row ending `consumed` bytes into the input is the last one
+    /// and lacks its newline.
+    fn is_partial_row(&self, consumed: u64) -> bool {
+        self.ended && consumed == self.bytes && !matches!(self.last, Some(b'\n' | b'\r') | None)
+    }
+}
+
+/// Keeps track of the end of the input, which the csv reader does not tell.
+struct InputTail<R> {
+    inner: R,
+    tail: Arc<Mutex<Tail>>,
+}
+
+impl<R: Read> Read for InputTail<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        let mut tail = self.tail.lock().unwrap();
+        if read == 0 {
+            tail.ended |= !buf.is_empty();
+        } else {
+            tail.bytes += read as u64;
+            tail.last = Some(buf[read - 1]);
+        }
+        Ok(read)
+    }
+}
+
+/// Like [`read_transactions`], for input in the binary encoding.
+fn read_binary_transactions<R: Read, F: FnMut(Message)>(
+    reader: R,
+    errors: &Arc<Mutex<Vec<ProcessingError>>>,
+    warnings: &Mutex<Vec<ProcessingWarning>>,
+    options: &ProcessingOptions,
+    dispatch: F,
+) -> InputStats {
+    match BinaryReader::new(BufReader::with_capacity(options.read_buffer_size, reader)) {
+        Ok(reader) => read_source(reader, errors, warnings, options, dispatch),
+        Err(e) => {
+            errors.lock().unwrap().push(e);
+            InputStats::default()
+        }
+    }
+}
+
+/// Like [`read_transactions`], for transactions that are already parsed.
+fn read_source<S: TransactionSource, F: FnMut(Message)>(
+    mut source: S,
+    errors: &Arc<Mutex<Vec<ProcessingError>>>,
+    warnings: &Mutex<Vec<ProcessingWarning>>,
+    options: &ProcessingOptions,
+    mut dispatch: F,
+) -> InputStats {
+    let mut stats = InputStats::default();
+    let mut end = None;
+    for row in 0.. {
+        if !keep_reading(errors, options, &mut stats) {
+            break;
+        }
+        dispatch_checkpoint(row, options, &mut dispatch);
+        let transaction = match source.next() {
+            Some(read) => {
+                let read_warnings = source.take_warnings();
+                let quarantined = read_warnings.iter().any(|warning| matches!(warning, ProcessingWarning::Quarantined { .. }));
+                if !read_warnings.is_empty() {
+                    warnings.lock().unwrap().extend(read_warnings);
+                }
+                read.and_then(|transaction| validate(transaction, quarantined, options, warnings))
             }
-        };
-        let client = transaction.get_client();
-        let sender = match beams.get(&client) {
-            Some(sender) => sender,
             None => {
-                let (sender, receiver) = unbounded();
-                let pool_result = result.clone();
-                let pool_errors = errors.clone();
-                pool.execute(move || client_status::build(client, receiver, pool_result, pool_errors));
-                beams.insert(client, sender);
-                beams.get(&client).unwrap()
+                end = Some(row);
+                break;
+            }
+        };
+        count_row(options);
+        match transaction {
+            Ok(transaction) if accept(row, &transaction, options, &mut stats) => {
+                let (line, timestamp) = (source.line(), source.timestamp());
+                dispatch(Message::Transaction(Routed { row, line, timestamp, adjustment: false, transaction }));
+            }
+            Ok(_) => {}
+            // Once reading fails there is nothing left to read, like after
+            // a binary record that was cut short.
+            Err(e @ ProcessingError::Io(_)) => {
+                errors.lock().unwrap().push(e);
+                break;
+            }
+            Err(e) => {
+                record_outcome(options, TransactionOutcome::unparsed(row, &e));
+                errors.lock().unwrap().push(e);
+            }
+        }
+    }
+    if let Some(end) = end {
+        dispatch_adjustments(end, options, &mut stats, &mut dispatch);
+    }
+    stats
+}
+
+/// Hands the adjustments over once the input was read to the end, numbering
+/// their rows from `first_row`.
+fn dispatch_adjustments<F: FnMut(Message)>(first_row: usize, options: &ProcessingOptions, stats: &mut InputStats, dispatch: &mut F) {
+    for (i, transaction) in options.adjustments.iter().enumerate() {
+        stats.record(transaction);
+        dispatch(Message::Transaction(Routed { row: first_row + i, line: None, timestamp: None, adjustment: true, transaction: transaction.clone() }));
+    }
+}
+
+/// Asks for a checkpoint once every `checkpoint_every` rows, before reading
+/// the next one.
+fn dispatch_checkpoint<F: FnMut(Message)>(row: usize, options: &ProcessingOptions, dispatch: &mut F) {
+    if let (Some(every), Some(sink)) = (options.checkpoint_every, &options.checkpoint_sink) {
+        if row > 0 && row.is_multiple_of(every) {
+            dispatch(Message::Checkpoint(Checkpoint::new(row, sink.clone())));
+        }
+    }
+}
+
+/// Waits while the reader is paused, then tells whether to read another
+/// row.
+fn keep_reading(errors: &Mutex<Vec<ProcessingError>>, options: &ProcessingOptions, stats: &mut InputStats) -> bool {
+    if let Some(pause) = &options.pause {
+        pause.wait_while_paused();
+    }
+    let limit_reached = options.error_policy.error_limit().is_some_and(|limit| errors.lock().unwrap().len() >= limit);
+    if limit_reached || options.stop_requested() {
+        return false;
+    }
+    if options.deadline_reached() {
+        stats.timed_out = true;
+        return false;
+    }
+    true
+}
+
+fn count_row(options: &ProcessingOptions) {
+    if let Some(rows_read) = &options.rows_read {
+        rows_read.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Whether the transaction passes the client filter, accounting for it
+/// either way.
+fn accept(row: usize, transaction: &Transaction, options: &ProcessingOptions, stats: &mut InputStats) -> bool {
+    if !options.client_filter.as_ref().is_none_or(|filter| filter.accepts(transaction.get_client())) {
+        stats.filtered_transactions += 1;
+        record_outcome(options, TransactionOutcome {
+            row,
+            tx: Some(transaction.get_tx()).filter(|_| !transaction.kind().is_bulk()),
+            client: Some(transaction.get_client()),
+            kind: Some(transaction.kind()),
+            outcome: Outcome::Ignored,
+            reason: Some("filtered out".to_owned()),
+            adjustment: false,
+        });
+        if let Some(skipped) = &options.skipped {
+            skipped.record(transaction.clone(), SkipReason::FilteredOut);
+        }
+        return false;
+    }
+    stats.record(transaction);
+    true
+}
+
+/// Hands every transaction over to the worker of its client. Returns what
+/// was learned about the input, along with the dispatched clients.
+#[cfg(feature = "parallel")]
+fn process_transactions<I: Input>(
+    input: I,
+    pool: &ThreadPool,
+    result: &Sender<WorkerOutput>,
+    errors: &Arc<Mutex<Vec<ProcessingError>>>,
+    warnings: &Arc<Mutex<Vec<ProcessingWarning>>>,
+    options: &Arc<ProcessingOptions>,
+) -> (InputStats, Vec<u32>) {
+    // Workers of single clients may wait in the pool until the end of the
+    // input, holding checkpoints back, while every shard runs from the start.
+    if options.deterministic_assignment || options.max_workers.is_some() || options.checkpoint_every.is_some() {
+        let shards = options.max_workers.unwrap_or_else(|| pool.max_count()).max(1);
+        return process_transactions_sharded(input, shards, pool, result, errors, warnings, options);
+    }
+    let mut beams = HashMap::new();
+    let mut clients = HashSet::new();
+    let stats = input.read_into(errors, warnings, options, |message| {
+        let routed = match message {
+            Message::Transaction(routed) => routed,
+            Message::Checkpoint(checkpoint) => {
+                checkpoint.expect(beams.len());
+                for sender in beams.values() {
+                    send_transaction(sender, Message::Checkpoint(checkpoint.clone()), errors);
+                }
+                return;
             }
         };
-        if let Err(e) = sender.send(transaction) {
-            let mut errors = errors.lock().unwrap();
-            errors.push(Box::new(e));
+        let client = routed.transaction.get_client();
+        clients.insert(client);
+        let sender = beams.entry(options.partition_of(&routed.transaction)).or_insert_with(|| {
+            let (sender, receiver) = unbounded();
+            let pool_result = result.clone();
+            let pool_errors = errors.clone();
+            let pool_warnings = warnings.clone();
+            let pool_options = options.clone();
+            if options.partition_key.is_some() {
+                pool.execute(move || client_status::build_shard(receiver, pool_result, pool_errors, pool_warnings, pool_options));
+            } else {
+                pool.execute(move || client_status::build(client, receiver, pool_result, pool_errors, pool_warnings, pool_options));
+            }
+            sender
+        });
+        send_transaction(sender, Message::Transaction(routed), errors);
+    });
+    (stats, clients.into_iter().collect())
+}
+
+/// The worker a partition is assigned to when there are `shards` of them.
+#[cfg(feature = "parallel")]
+fn shard_of(partition: u64, shards: usize) -> usize {
+    (partition % shards as u64) as usize
+}
+
+/// Starts `shards` workers up front, each owning the state of the clients
+/// routed to it, so the number of tasks and channels does not grow with
+/// the number of clients. A client goes to the same worker on every run,
+/// see [`shard_of`].
+#[cfg(feature = "parallel")]
+fn process_transactions_sharded<I: Input>(
+    input: I,
+    shards: usize,
+    pool: &ThreadPool,
+    result: &Sender<WorkerOutput>,
+    errors: &Arc<Mutex<Vec<ProcessingError>>>,
+    warnings: &Arc<Mutex<Vec<ProcessingWarning>>>,
+    options: &Arc<ProcessingOptions>,
+) -> (InputStats, Vec<u32>) {
+    let shards: Vec<_> = (0..shards).map(|_| {
+        let (sender, receiver) = unbounded();
+        let pool_result = result.clone();
+        let pool_errors = errors.clone();
+        let pool_warnings = warnings.clone();
+        let pool_options = options.clone();
+        pool.execute(move || client_status::build_shard(receiver, pool_result, pool_errors, pool_warnings, pool_options));
+        sender
+    }).collect();
+    let mut clients = HashSet::new();
+    let stats = input.read_into(errors, warnings, options, |message| {
+        let routed = match message {
+            Message::Transaction(routed) => routed,
+            Message::Checkpoint(checkpoint) => {
+                checkpoint.expect(shards.len());
+                for sender in &shards {
+                    send_transaction(sender, Message::Checkpoint(checkpoint.clone()), errors);
+                }
+                return;
+            }
+        };
+        let client = routed.transaction.get_client();
+        clients.insert(client);
+        let shard = shard_of(options.partition_of(&routed.transaction), shards.len());
+        send_transaction(&shards[shard], Message::Transaction(routed), errors);
+    });
+    (stats, clients.into_iter().collect())
+}
+
+#[cfg(feature = "parallel")]
+fn send_transaction(
+    sender: &Sender<Message>,
+    item: Message,
+    errors: &Mutex<Vec<ProcessingError>>,
+) {
+    // A checkpoint that cannot reach a worker never completes, as the
+    // state of that worker is lost anyway.
+    if let Err(e) = sender.send(item) {
+        if let Message::Transaction(routed) = e.into_inner() {
+            errors.lock().unwrap().push(ProcessingError::Channel(routed.transaction.get_tx()));
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::error::Error;
-    use crate::{ClientStatus, execute_transactions};
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::AtomicBool;
+    use std::io::Read;
+    use std::thread;
+    use std::time::Duration;
+    use crate::{AccountSnapshot, CheckpointSink, ClientFilter, ClientStatus, Engine, ErrorPolicy, FixedClock, NegativeTotalPolicy, ProcessingError, ProcessingOptions, ProcessingReport, ProcessingWarning, SnapshotSink, TrailingRowPolicy, Transaction, TransactionKind, Trim, Validator, check_unique_results, encode_transactions, execute_transactions, execute_transactions_timeout, execute_transactions_with_options};
     use crate::client_status::ClientStatusError;
     use crate::transaction::TransactionParseError;
 
@@ -84,10 +879,10 @@ mod tests {
         test_result(
             "type, client,tx,amount\ndeposit, 1,1,1.0\ndeposit,2,2,2.0\ndeposit,1,3,2.0\nwithdrawal,1,4,1.5\nwithdrawal,2,5,3.0",
             vec![
-                ClientStatus { id: 1, available: 1.5, held: 0.0, total: 1.5, locked: false },
-                ClientStatus { id: 2, available: 2.0, held: 0.0, total: 2.0, locked: false }
+                ClientStatus { id: 1, available: 1.5, held: 0.0, held_from_withdrawals: 0.0, total: 1.5, locked: false, last_activity: None },
+                ClientStatus { id: 2, available: 2.0, held: 0.0, held_from_withdrawals: 0.0, total: 2.0, locked: false, last_activity: None }
             ],
-            vec![Box::new(ClientStatusError::InsufficientFounds(3f32, 5, 2f32))]
+            vec![ClientStatusError::InsufficientFounds(3f32, 5, 2f32).into()]
         );
     }
 
@@ -96,20 +891,923 @@ mod tests {
         test_result(
             "type, client,tx,amount\ndeposit, 1,1,1.0\ndeposit,2,2,2.0\ndeposit,1,3,2.0\nwithdrawal,1,4,1.5\nwithdrawal42,2,5,3.0",
             vec![
-                ClientStatus { id: 1, available: 1.5, held: 0.0, total: 1.5, locked: false },
-                ClientStatus { id: 2, available: 2.0, held: 0.0, total: 2.0, locked: false }
+                ClientStatus { id: 1, available: 1.5, held: 0.0, held_from_withdrawals: 0.0, total: 1.5, locked: false, last_activity: None },
+                ClientStatus { id: 2, available: 2.0, held: 0.0, held_from_withdrawals: 0.0, total: 2.0, locked: false, last_activity: None }
             ],
-            vec![Box::new(TransactionParseError::InvalidTransactionType("withdrawal42".to_string()))]
+            vec![TransactionParseError::InvalidTransactionType("withdrawal42".to_string()).into()]
         );
     }
 
-    fn test_result(transactions: &str, expected_results: Vec<ClientStatus>, expected_errors: Vec<Box<dyn Error + Send>>) {
-        let (mut result, errors) = execute_transactions(transactions.as_bytes(), 1);
-        result.sort_by(|a, b| a.id.cmp(&b.id));
-        assert_eq!(expected_results, result);
-        assert_eq!(expected_errors.len(), errors.len());
-        for (e1, e2) in expected_errors.iter().zip(&errors) {
+    const MULTIPLE_ERRORS: &str = "type,client,tx,amount\ndeposit,1,1,1.0\nfoo,1,2,1.0\ndeposit,1,3,2.0\nbar,1,4,1.0\ndeposit,1,5,3.0\nbaz,1,6,1.0\ndeposit,1,7,4.0";
+
+    #[test]
+    fn test_fail_fast_stops_at_first_error() {
+        test_result_with_options(
+            MULTIPLE_ERRORS,
+            ProcessingOptions { error_policy: ErrorPolicy::FailFast, ..Default::default() },
+            vec![ClientStatus { id: 1, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None }],
+            vec![TransactionParseError::InvalidTransactionType("foo".to_string()).into()]
+        );
+    }
+
+    #[test]
+    fn test_continue_all_collects_every_error() {
+        test_result_with_options(
+            MULTIPLE_ERRORS,
+            ProcessingOptions { error_policy: ErrorPolicy::ContinueAll, ..Default::default() },
+            vec![ClientStatus { id: 1, available: 10.0, held: 0.0, held_from_withdrawals: 0.0, total: 10.0, locked: false, last_activity: None }],
+            vec![
+                TransactionParseError::InvalidTransactionType("foo".to_string()).into(),
+                TransactionParseError::InvalidTransactionType("bar".to_string()).into(),
+                TransactionParseError::InvalidTransactionType("baz".to_string()).into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_continue_until_stops_after_n_errors() {
+        test_result_with_options(
+            MULTIPLE_ERRORS,
+            ProcessingOptions { error_policy: ErrorPolicy::ContinueUntil(2), ..Default::default() },
+            vec![ClientStatus { id: 1, available: 3.0, held: 0.0, held_from_withdrawals: 0.0, total: 3.0, locked: false, last_activity: None }],
+            vec![
+                TransactionParseError::InvalidTransactionType("foo".to_string()).into(),
+                TransactionParseError::InvalidTransactionType("bar".to_string()).into(),
+            ]
+        );
+    }
+
+    const TWO_CLIENTS: &str = "type, client,tx,amount\ndeposit, 1,1,1.0\ndeposit,2,2,2.0\ndeposit,1,3,2.0\nwithdrawal,1,4,1.5\nwithdrawal,2,5,3.0";
+
+    #[test]
+    fn test_allowlist_only_processes_listed_clients() {
+        let report = test_result_with_options(
+            TWO_CLIENTS,
+            ProcessingOptions { client_filter: Some(ClientFilter::Allow(HashSet::from([1]))), ..Default::default() },
+            vec![ClientStatus { id: 1, available: 1.5, held: 0.0, held_from_withdrawals: 0.0, total: 1.5, locked: false, last_activity: None }],
+            vec![]
+        );
+        assert_eq!(report.filtered_transactions, 2);
+    }
+
+    #[test]
+    fn test_denylist_skips_listed_clients() {
+        let report = test_result_with_options(
+            TWO_CLIENTS,
+            ProcessingOptions { client_filter: Some(ClientFilter::Deny(HashSet::from([1]))), ..Default::default() },
+            vec![ClientStatus { id: 2, available: 2.0, held: 0.0, held_from_withdrawals: 0.0, total: 2.0, locked: false, last_activity: None }],
+            vec![ClientStatusError::InsufficientFounds(3f32, 5, 2f32).into()]
+        );
+        assert_eq!(report.filtered_transactions, 3);
+    }
+
+    #[test]
+    fn test_columns_are_mapped_by_name() {
+        test_result(
+            "tx,amount,client,type\n1,1.0,1,deposit\n2,2.0,2,deposit\n3,0.5,1,withdrawal",
+            vec![
+                ClientStatus { id: 1, available: 0.5, held: 0.0, held_from_withdrawals: 0.0, total: 0.5, locked: false, last_activity: None },
+                ClientStatus { id: 2, available: 2.0, held: 0.0, held_from_withdrawals: 0.0, total: 2.0, locked: false, last_activity: None }
+            ],
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_extra_columns_are_ignored() {
+        test_result(
+            "type,client,currency,tx,amount\ndeposit,1,EUR,1,1.0\nwithdrawal,1,EUR,2,0.5",
+            vec![ClientStatus { id: 1, available: 0.5, held: 0.0, held_from_withdrawals: 0.0, total: 0.5, locked: false, last_activity: None }],
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_precision_loss_is_only_reported_when_asked() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.123456789\ndeposit,1,2,2.5";
+        let report = execute_transactions_with_options(transactions.as_bytes(), 1, ProcessingOptions::default());
+        assert!(report.warnings.is_empty());
+
+        let options = ProcessingOptions { warn_on_precision_loss: true, ..Default::default() };
+        let report = test_result_with_options(
+            transactions,
+            options,
+            vec![ClientStatus { id: 1, available: 3.6235, held: 0.0, held_from_withdrawals: 0.0, total: 3.6235, locked: false, last_activity: None }],
+            vec![]
+        );
+        assert_eq!(report.warnings, vec![
+            ProcessingWarning::PrecisionTruncated { tx: 1, original: 1.1234568, rounded: 1.1235 }
+        ]);
+    }
+
+    #[test]
+    fn test_transactions_can_be_validated_by_type() {
+        let multiple_of_ten = Validator::new(|t| match t {
+            Transaction::Deposit { amount, .. } if amount % 10f32 != 0f32 => Err(format!("{} is not a multiple of 10", amount)),
+            _ => Ok(()),
+        });
+        let options = ProcessingOptions::builder().validator(TransactionKind::Deposit, multiple_of_ten).build().unwrap();
+        test_result_with_options(
+            "type,client,tx,amount\ndeposit,1,1,20.0\ndeposit,1,2,15.0\nwithdrawal,1,3,5.0\ndeposit,2,4,10",
+            options,
+            vec![
+                ClientStatus { id: 1, available: 15.0, held: 0.0, held_from_withdrawals: 0.0, total: 15.0, locked: false, last_activity: None },
+                ClientStatus { id: 2, available: 10.0, held: 0.0, held_from_withdrawals: 0.0, total: 10.0, locked: false, last_activity: None },
+            ],
+            vec![ClientStatusError::CustomValidationFailed { tx: 2, reason: "15 is not a multiple of 10".to_owned() }.into()]
+        );
+    }
+
+    #[test]
+    fn test_sorted_errors_come_in_the_same_order_on_every_run() {
+        let mut transactions = "type,client,tx,amount\n".to_owned();
+        for client in (1..=20).rev() {
+            transactions += &format!("withdrawal,{},{},1.0\nfoo,{},{},1.0\ndispute,{},{},\n", client, client * 10, client, client * 10 + 1, client, client * 10 + 2);
+        }
+        let run = || {
+            let options = ProcessingOptions { sort_errors: true, ..Default::default() };
+            let report = execute_transactions_with_options(transactions.as_bytes(), 4, options);
+            report.errors.iter().map(ToString::to_string).collect::<Vec<String>>()
+        };
+        let first = run();
+        assert_eq!(first.len(), 60);
+        // The unknown types know nothing about where they are and keep the
+        // order they were read in, ahead of the errors of the clients, which
+        // come in the order of their lines.
+        let unknown_type = TransactionParseError::InvalidTransactionType("foo".to_owned()).to_string();
+        assert!(first[..20].iter().all(|e| *e == unknown_type));
+        assert_eq!(first[20], ClientStatusError::InsufficientFounds(1.0, 200, 0.0).to_string());
+        assert_eq!(first[21], ClientStatusError::NonExistingTransaction(202).to_string());
+        assert_eq!(first[59], ClientStatusError::NonExistingTransaction(12).to_string());
+        for _ in 0..5 {
+            assert_eq!(run(), first);
+        }
+    }
+
+    #[test]
+    fn test_subprecision_amounts_can_be_rejected() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,0.00001\ndeposit,1,2,1.0\nwithdrawal,1,3,0.00004";
+        test_result_with_options(
+            transactions,
+            ProcessingOptions::default(),
+            vec![ClientStatus { id: 1, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None }],
+            vec![]
+        );
+        test_result_with_options(
+            transactions,
+            ProcessingOptions { reject_subprecision_amounts: true, ..Default::default() },
+            vec![ClientStatus { id: 1, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None }],
+            vec![ClientStatusError::SubPrecisionAmount(1).into(), ClientStatusError::SubPrecisionAmount(3).into()]
+        );
+    }
+
+    #[test]
+    fn test_subprecision_amounts_are_rejected_from_binary_input() {
+        let mut encoded = vec![];
+        encode_transactions("type,client,tx,amount\ndeposit,1,1,0.00001\ndeposit,1,2,1.0".as_bytes(), &mut encoded).unwrap();
+        let options = ProcessingOptions { binary_input: true, reject_subprecision_amounts: true, ..Default::default() };
+        let report = execute_transactions_with_options(encoded.as_slice(), 1, options);
+        assert_eq!(report.results[0].total, 1.0);
+        assert!(matches!(report.errors[..], [ProcessingError::Client(ClientStatusError::SubPrecisionAmount(1))]));
+    }
+
+    #[test]
+    fn test_rows_with_malformed_client_go_to_quarantine() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,one,2,2.0\ndeposit,two,three,2.0";
+        let options = ProcessingOptions { quarantine_client: Some(999), ..Default::default() };
+        let mut report = execute_transactions_with_options(transactions.as_bytes(), 1, options);
+        report.results.sort_by_key(|s| s.id);
+        assert_eq!(report.results, vec![
+            ClientStatus { id: 1, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None },
+            ClientStatus { id: 999, available: 2.0, held: 0.0, held_from_withdrawals: 0.0, total: 2.0, locked: false, last_activity: None },
+        ]);
+        // A row broken beyond its client field is still rejected.
+        assert!(matches!(report.errors.as_slice(), [ProcessingError::CsvAt { .. }]));
+        assert_eq!(report.warnings, vec![ProcessingWarning::Quarantined { tx: 2, client: "one".to_owned() }]);
+    }
+
+    #[test]
+    fn test_quarantine_client_cannot_be_used_by_real_rows() {
+        let transactions = "type,client,tx,amount\ndeposit,999,1,1.0\ndeposit,one,2,2.0";
+        let options = ProcessingOptions { quarantine_client: Some(999), ..Default::default() };
+        test_result_with_options(
+            transactions,
+            options,
+            vec![ClientStatus { id: 999, available: 2.0, held: 0.0, held_from_withdrawals: 0.0, total: 2.0, locked: false, last_activity: None }],
+            vec![ProcessingError::QuarantineClientInUse(1, 999)]
+        );
+    }
+
+    #[test]
+    fn test_decimal_comma_amounts() {
+        test_result_with_options(
+            "type;client;tx;amount\ndeposit;1;1;2,50\nwithdrawal;1;2;0,5",
+            ProcessingOptions { delimiter: b';', decimal_comma: true, ..Default::default() },
+            vec![ClientStatus { id: 1, available: 2.0, held: 0.0, held_from_withdrawals: 0.0, total: 2.0, locked: false, last_activity: None }],
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_errors_can_be_matched_by_category() {
+        let transactions = "type,client,tx,amount\nfoo,1,1,1.0\ndeposit,one,2,1.0\nwithdrawal,1,3,1.0";
+        let report = execute_transactions_with_options(transactions.as_bytes(), 1, ProcessingOptions::default());
+        assert!(matches!(
+            report.errors.as_slice(),
+            [
+                ProcessingError::Parse(TransactionParseError::InvalidTransactionType(_)),
+                ProcessingError::CsvAt { .. },
+                ProcessingError::ClientAt { client: 1, row: 2, line: Some(4), source: ClientStatusError::InsufficientFounds(..) },
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_csv_errors_carry_their_position() {
+        let transactions = b"type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,\xff\ndeposit,1,3,2.0";
+        let report = execute_transactions_with_options(&transactions[..], 1, ProcessingOptions::default());
+        assert_eq!(report.results, vec![ClientStatus { id: 1, available: 3.0, held: 0.0, held_from_withdrawals: 0.0, total: 3.0, locked: false, last_activity: None }]);
+        assert!(matches!(report.errors.as_slice(), [ProcessingError::CsvAt { line: 3, byte: 38, .. }]));
+    }
+
+    #[test]
+    fn test_reserved_tx_ids_are_rejected() {
+        test_result_with_options(
+            "type,client,tx,amount\ndeposit,1,0,5.0\ndeposit,1,1,1.0\ndispute,1,0,0",
+            ProcessingOptions { reserved_tx_ids: HashSet::from([0]), ..Default::default() },
+            vec![ClientStatus { id: 1, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None }],
+            vec![
+                TransactionParseError::ReservedTxId(0).into(),
+                TransactionParseError::ReservedTxId(0).into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repeated_errors_can_be_collapsed() {
+        let transactions = format!("type,client,tx,amount\ndeposit,1,1,1.0\n{}", "foo,1,2,1.0\n".repeat(1000));
+        let options = ProcessingOptions { collapse_repeated_errors: true, ..Default::default() };
+        let report = execute_transactions_with_options(transactions.as_bytes(), 1, options);
+        assert!(matches!(
+            report.errors.as_slice(),
+            [ProcessingError::Repeated { error, count: 1000 }] if matches!(**error, ProcessingError::Parse(TransactionParseError::InvalidTransactionType(_)))
+        ));
+        assert_eq!(report.errors[0].to_string(), "Transaction type foo is invalid (1000 times)");
+    }
+
+    #[test]
+    fn test_latest_timestamp_is_the_last_activity() {
+        test_result(
+            "type,client,tx,amount,timestamp\ndeposit,1,1,1.0,100\ndeposit,1,2,1.0,300\nwithdrawal,1,3,0.5,200\ndeposit,2,4,1.0,",
+            vec![
+                ClientStatus { id: 1, available: 1.5, held: 0.0, held_from_withdrawals: 0.0, total: 1.5, locked: false, last_activity: Some(300) },
+                ClientStatus { id: 2, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None },
+            ],
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_invalid_timestamp_is_rejected() {
+        test_result(
+            "type,client,tx,amount,timestamp\ndeposit,1,1,1.0,100\ndeposit,1,2,1.0,yesterday",
+            vec![ClientStatus { id: 1, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: Some(100) }],
+            vec![TransactionParseError::InvalidTimestamp("yesterday".to_owned()).into()]
+        );
+    }
+
+    #[test]
+    fn test_debug_client_snapshots_every_transaction() {
+        let snapshots = Arc::new(Mutex::new(vec![]));
+        let sink = snapshots.clone();
+        let options = ProcessingOptions {
+            debug_client: Some(1),
+            snapshot_sink: Some(SnapshotSink::new(move |snapshot| sink.lock().unwrap().push(snapshot))),
+            ..Default::default()
+        };
+        execute_transactions_with_options(TWO_CLIENTS.as_bytes(), 2, options);
+        assert_eq!(*snapshots.lock().unwrap(), vec![
+            AccountSnapshot { client: 1, row: 0, available: 1.0, held: 0.0, locked: false },
+            AccountSnapshot { client: 1, row: 2, available: 3.0, held: 0.0, locked: false },
+            AccountSnapshot { client: 1, row: 3, available: 1.5, held: 0.0, locked: false },
+        ]);
+    }
+
+    #[test]
+    fn test_matching_schema_marker_is_accepted() {
+        test_result_with_options(
+            "# schema: v2\ntype,client,tx,amount\ndeposit,1,1,1.0",
+            ProcessingOptions { expected_schema: Some("v2".to_owned()), ..Default::default() },
+            vec![ClientStatus { id: 1, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None }],
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_mismatched_schema_marker_stops_before_any_row() {
+        test_result_with_options(
+            "# schema: v1\ntype,client,tx,amount\ndeposit,1,1,1.0",
+            ProcessingOptions { expected_schema: Some("v2".to_owned()), ..Default::default() },
+            vec![],
+            vec![ProcessingError::SchemaMismatch { expected: "v2".to_owned(), found: Some("v1".to_owned()) }]
+        );
+    }
+
+    #[test]
+    fn test_schema_marker_is_skipped_when_not_expected() {
+        test_result(
+            "# schema: v1\ntype,client,tx,amount\ndeposit,1,1,1.0",
+            vec![ClientStatus { id: 1, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None }],
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_atomic_run_discards_results_on_any_error() {
+        test_result_with_options(
+            MULTIPLE_ERRORS,
+            ProcessingOptions { atomic: true, ..Default::default() },
+            vec![],
+            vec![
+                TransactionParseError::InvalidTransactionType("foo".to_string()).into(),
+                TransactionParseError::InvalidTransactionType("bar".to_string()).into(),
+                TransactionParseError::InvalidTransactionType("baz".to_string()).into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_atomic_run_without_errors_keeps_results() {
+        test_result_with_options(
+            "type,client,tx,amount\ndeposit,1,1,1.0",
+            ProcessingOptions { atomic: true, ..Default::default() },
+            vec![ClientStatus { id: 1, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None }],
+            vec![]
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_clients_are_always_assigned_to_the_same_worker() {
+        use crate::shard_of;
+        let assignments: Vec<usize> = [1, 2, 3, 4, 5, 42].iter().map(|client| shard_of(*client, 4)).collect();
+        assert_eq!(assignments, vec![1, 2, 3, 0, 1, 2]);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_clients_of_a_partition_share_a_worker() {
+        use std::collections::HashMap;
+        use crate::{OutcomeSink, PartitionKey};
+        let workers = Arc::new(Mutex::new(HashMap::new()));
+        let sink_workers = workers.clone();
+        let options = ProcessingOptions {
+            partition_key: Some(PartitionKey::new(|t| (t.get_client() > 2) as u64)),
+            outcome_sink: Some(OutcomeSink::new(move |outcome| {
+                sink_workers.lock().unwrap().entry(outcome.client.unwrap()).or_insert_with(HashSet::new).insert(thread::current().id());
+            })),
+            ..Default::default()
+        };
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\ndeposit,3,3,2.0\nwithdrawal,1,4,0.5\nwithdrawal,2,5,1.0\ndeposit,3,6,1.0";
+        let mut report = execute_transactions_with_options(transactions.as_bytes(), 4, options);
+        report.results.sort_by_key(|status| status.id);
+        assert_eq!(report.results.iter().map(|status| status.total).collect::<Vec<_>>(), vec![0.5, 1.0, 3.0]);
+        let workers = workers.lock().unwrap();
+        assert_eq!(workers[&1].len(), 1);
+        assert_eq!(workers[&1], workers[&2]);
+    }
+
+    #[test]
+    fn test_deterministic_assignment_gives_the_same_results() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\ndeposit,5,3,2.0\nwithdrawal,1,4,1.5\nwithdrawal,2,5,3.0\ndispute,5,3,0";
+        let expected = ProcessingOptions::default();
+        let options = ProcessingOptions { deterministic_assignment: true, ..Default::default() };
+        let mut expected = execute_transactions_with_options(transactions.as_bytes(), 2, expected);
+        let mut report = execute_transactions_with_options(transactions.as_bytes(), 2, options);
+        expected.results.sort_by_key(|s| s.id);
+        report.results.sort_by_key(|s| s.id);
+        assert!(expected.diff(&report).is_empty());
+        assert_eq!(report.errors.len(), 2);
+    }
+
+    #[test]
+    fn test_bounded_workers_give_the_same_results() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\ndeposit,5,3,2.0\nwithdrawal,1,4,1.5\nwithdrawal,2,5,3.0\ndispute,5,3,0";
+        let mut expected = execute_transactions_with_options(transactions.as_bytes(), 2, ProcessingOptions::default());
+        let options = ProcessingOptions { max_workers: Some(1), ..Default::default() };
+        let mut report = execute_transactions_with_options(transactions.as_bytes(), 2, options);
+        expected.results.sort_by_key(|s| s.id);
+        report.results.sort_by_key(|s| s.id);
+        assert!(expected.diff(&report).is_empty());
+    }
+
+    #[test]
+    fn test_timestamps_after_now_can_be_rejected() {
+        test_result_with_options(
+            "type,client,tx,amount,timestamp\ndeposit,1,1,1.0,100\ndeposit,1,2,1.0,101",
+            ProcessingOptions { reject_future_timestamps: true, clock: Arc::new(FixedClock(100)), ..Default::default() },
+            vec![ClientStatus { id: 1, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: Some(100) }],
+            vec![TransactionParseError::FutureTimestamp(101).into()]
+        );
+    }
+
+    #[test]
+    fn test_blank_deposit_amount_is_reported_as_missing() {
+        test_result(
+            "type,client,tx,amount\ndeposit,1,1,\ndeposit,1,2,1.0\ndispute,1,2,",
+            vec![ClientStatus { id: 1, available: 0.0, held: 1.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None }],
+            vec![TransactionParseError::MissingAmount(1).into()]
+        );
+    }
+
+    /// Hands over one row at a time, taking a while for each of them.
+    struct SlowReader {
+        rows: Vec<String>,
+    }
+
+    impl Read for SlowReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            thread::sleep(Duration::from_millis(5));
+            match self.rows.pop() {
+                Some(row) => {
+                    buf[..row.len()].copy_from_slice(row.as_bytes());
+                    Ok(row.len())
+                }
+                None => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_reading_stops_at_the_deadline() {
+        // Popped from the back, so the header goes last.
+        let mut rows: Vec<String> = (1..=100).rev().map(|tx| format!("deposit,1,{},1.0\n", tx)).collect();
+        rows.push("type,client,tx,amount\n".to_owned());
+        let (results, errors, timed_out) = execute_transactions_timeout(SlowReader { rows }, 1, Duration::from_millis(50));
+        assert!(timed_out);
+        assert!(errors.is_empty());
+        assert_eq!(results.len(), 1);
+        assert!(results[0].available < 100.0);
+    }
+
+    #[test]
+    fn test_fast_enough_runs_do_not_time_out() {
+        let (results, _, timed_out) = execute_transactions_timeout(TWO_CLIENTS.as_bytes(), 1, Duration::from_secs(60));
+        assert!(!timed_out);
+        assert_eq!(results.len(), 2);
+    }
+
+    #[test]
+    fn test_binary_input_gives_the_same_results_as_csv() {
+        let mut encoded = vec![];
+        encode_transactions(TWO_CLIENTS.as_bytes(), &mut encoded).unwrap();
+        let mut expected = execute_transactions_with_options(TWO_CLIENTS.as_bytes(), 1, ProcessingOptions::default());
+        let options = ProcessingOptions { binary_input: true, ..Default::default() };
+        let mut report = execute_transactions_with_options(encoded.as_slice(), 1, options);
+        expected.results.sort_by_key(|s| s.id);
+        report.results.sort_by_key(|s| s.id);
+        assert!(expected.diff(&report).is_empty());
+        assert_eq!(report.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_disallowed_types_are_rejected() {
+        let mut allowed_types: HashSet<TransactionKind> = TransactionKind::ALL.into_iter().collect();
+        allowed_types.remove(&TransactionKind::Chargeback);
+        test_result_with_options(
+            "type,client,tx,amount\ndeposit,1,1,1.0\ndispute,1,1,\nchargeback,1,1,",
+            ProcessingOptions { allowed_types, ..Default::default() },
+            vec![ClientStatus { id: 1, available: 0.0, held: 1.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None }],
+            vec![TransactionParseError::TypeNotAllowed(TransactionKind::Chargeback).into()]
+        );
+    }
+
+    #[test]
+    fn test_fields_keep_their_whitespace_when_only_trimming_headers() {
+        test_result_with_options(
+            "type, client ,tx,amount\n deposit,1,1,1.0\ndeposit,1,2,2.0",
+            ProcessingOptions { trim: Trim::Headers, ..Default::default() },
+            vec![ClientStatus { id: 1, available: 2.0, held: 0.0, held_from_withdrawals: 0.0, total: 2.0, locked: false, last_activity: None }],
+            vec![TransactionParseError::InvalidTransactionType(" deposit".to_owned()).into()]
+        );
+    }
+
+    #[test]
+    fn test_disputes_only_affect_the_client_they_name() {
+        let transactions = "type,client,tx,amount\ndeposit,1,5,1.0\ndeposit,2,5,2.0\ndispute,1,5,\nchargeback,1,5,";
+        for threads in [1, 2] {
+            let (mut results, errors) = execute_transactions(transactions.as_bytes(), threads);
+            results.sort_by_key(|s| s.id);
+            assert!(errors.is_empty());
+            assert_eq!(results, vec![
+                ClientStatus { id: 1, available: 0.0, held: 0.0, held_from_withdrawals: 0.0, total: 0.0, locked: true, last_activity: None },
+                ClientStatus { id: 2, available: 2.0, held: 0.0, held_from_withdrawals: 0.0, total: 2.0, locked: false, last_activity: None },
+            ]);
+        }
+    }
+
+    #[test]
+    fn test_phantom_clients_can_be_left_out() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndispute,2,7,\nchargeback,2,7,";
+        for threads in [1, 2] {
+            for include_phantom_clients in [true, false] {
+                let options = ProcessingOptions { include_phantom_clients, ..Default::default() };
+                let mut report = execute_transactions_with_options(transactions.as_bytes(), threads, options);
+                report.results.sort_by_key(|s| s.id);
+                let mut expected = vec![
+                    ClientStatus { id: 1, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None },
+                ];
+                if include_phantom_clients {
+                    expected.push(ClientStatus { id: 2, available: 0.0, held: 0.0, held_from_withdrawals: 0.0, total: 0.0, locked: false, last_activity: None });
+                }
+                assert_eq!(report.results, expected);
+                assert_eq!(report.errors.len(), 2);
+            }
+        }
+    }
+
+    #[test]
+    fn test_adjustments_are_applied_after_the_input() {
+        let options = ProcessingOptions {
+            adjustments: vec![Transaction::deposit(2, 6, 1.5), Transaction::withdrawal(1, 7, 0.5)],
+            ..Default::default()
+        };
+        test_result_with_options(
+            TWO_CLIENTS,
+            options,
+            vec![
+                ClientStatus { id: 1, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None },
+                ClientStatus { id: 2, available: 3.5, held: 0.0, held_from_withdrawals: 0.0, total: 3.5, locked: false, last_activity: None },
+            ],
+            vec![ClientStatusError::InsufficientFounds(3.0, 5, 2.0).into()]
+        );
+    }
+
+    const NEGATIVE_TOTAL: &str = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\nwithdrawal,1,3,2.5\ndispute,1,2,\nchargeback,1,2,";
+
+    #[test]
+    fn test_negative_totals_are_allowed_by_default() {
+        test_result(
+            NEGATIVE_TOTAL,
+            vec![ClientStatus { id: 1, available: -1.5, held: 0.0, held_from_withdrawals: 0.0, total: -1.5, locked: true, last_activity: None }],
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_negative_totals_can_be_clamped() {
+        let report = test_result_with_options(
+            NEGATIVE_TOTAL,
+            ProcessingOptions { on_negative_total: NegativeTotalPolicy::Clamp, ..Default::default() },
+            vec![ClientStatus { id: 1, available: 0.0, held: 0.0, held_from_withdrawals: 0.0, total: 0.0, locked: true, last_activity: None }],
+            vec![]
+        );
+        assert!(report.results.iter().all(|status| status.total == status.available + status.held));
+        assert_eq!(report.warnings, vec![ProcessingWarning::NegativeTotalClamped { client: 1, total: -1.5 }]);
+    }
+
+    #[test]
+    fn test_status_sink_sees_clamped_totals() {
+        use crate::StatusSink;
+        let streamed = Arc::new(Mutex::new(vec![]));
+        let sink = {
+            let streamed = streamed.clone();
+            StatusSink::new(move |status| streamed.lock().unwrap().push(status))
+        };
+        let options = ProcessingOptions { on_negative_total: NegativeTotalPolicy::Clamp, status_sink: Some(sink), ..Default::default() };
+        let report = execute_transactions_with_options(NEGATIVE_TOTAL.as_bytes(), 1, options);
+        assert_eq!(*streamed.lock().unwrap(), report.results);
+        assert_eq!(report.results[0].total, 0.0);
+    }
+
+    #[test]
+    fn test_negative_totals_can_be_errors() {
+        test_result_with_options(
+            NEGATIVE_TOTAL,
+            ProcessingOptions { on_negative_total: NegativeTotalPolicy::Error, ..Default::default() },
+            vec![ClientStatus { id: 1, available: -1.5, held: 0.0, held_from_withdrawals: 0.0, total: -1.5, locked: true, last_activity: None }],
+            vec![ClientStatusError::NegativeTotal(1).into()]
+        );
+    }
+
+    #[test]
+    fn test_overdrawn_clients_are_errors_of_the_negative_total_policy() {
+        let options = ProcessingOptions { overdraft_limit: Some(5.0), on_negative_total: NegativeTotalPolicy::Error, ..Default::default() };
+        let report = test_result_with_options(
+            "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,2,3.0\ndeposit,2,3,1.0\nwithdrawal,2,4,1.0",
+            options.clone(),
+            vec![
+                ClientStatus { id: 1, available: -2.0, held: 0.0, held_from_withdrawals: 0.0, total: -2.0, locked: false, last_activity: None },
+                ClientStatus { id: 2, available: 0.0, held: 0.0, held_from_withdrawals: 0.0, total: 0.0, locked: false, last_activity: None },
+            ],
+            vec![ClientStatusError::NegativeTotal(1).into()]
+        );
+        assert_eq!(report.results[0].effective_available(&options), 3.0);
+        assert_eq!(report.results[1].effective_available(&options), 5.0);
+    }
+
+    #[test]
+    fn test_bulk_operations_are_read_without_a_tx() {
+        test_result(
+            "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\ndispute,1,1,\ndispute,1,2,\nchargeback_all,1,,",
+            vec![ClientStatus { id: 1, available: 0.0, held: 0.0, held_from_withdrawals: 0.0, total: 0.0, locked: true, last_activity: None }],
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_checkpoints_resume_where_they_were_taken() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\ndispute,1,1,\ndeposit,1,3,2.0\nwithdrawal,2,4,1.5\nresolve,1,1,\nwithdrawal,1,5,0.5";
+        let checkpoints = Arc::new(Mutex::new(vec![]));
+        let sink_checkpoints = checkpoints.clone();
+        let options = ProcessingOptions {
+            checkpoint_every: Some(3),
+            checkpoint_sink: Some(CheckpointSink::new(move |state| sink_checkpoints.lock().unwrap().push(state))),
+            ..Default::default()
+        };
+        let mut report = execute_transactions_with_options(transactions.as_bytes(), 2, options);
+        report.results.sort_by_key(|status| status.id);
+
+        let mut engines: Vec<Engine> = checkpoints.lock().unwrap().iter().map(|state| Engine::load_state(state.as_slice()).unwrap()).collect();
+        engines.sort_by_key(Engine::rows);
+        assert_eq!(engines.iter().map(Engine::rows).collect::<Vec<_>>(), vec![3, 6]);
+        assert_eq!(engines[0].results(), vec![
+            ClientStatus { id: 1, available: 0.0, held: 1.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None },
+            ClientStatus { id: 2, available: 2.0, held: 0.0, held_from_withdrawals: 0.0, total: 2.0, locked: false, last_activity: None },
+        ]);
+        let mut resumed = engines.remove(0);
+        for transaction in [Transaction::deposit(1, 3, 2.0), Transaction::withdrawal(2, 4, 1.5), Transaction::resolve(1, 1), Transaction::withdrawal(1, 5, 0.5)] {
+            resumed.process(transaction).unwrap();
+        }
+        assert_eq!(resumed.results(), report.results);
+    }
+
+    /// Withdrawals are rounded like deposits before being compared against
+    /// the available funds.
+    #[test]
+    fn test_withdrawal_is_rounded_before_checking_the_funds() {
+        test_result(
+            "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,2,1.00004",
+            vec![ClientStatus { id: 1, available: 0.0, held: 0.0, held_from_withdrawals: 0.0, total: 0.0, locked: false, last_activity: None }],
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_transactions_past_the_rate_limit_are_dropped() {
+        test_result_with_options(
+            "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,1.0\ndeposit,1,3,2.0\nwithdrawal,1,4,0.5\ndeposit,1,5,4.0\ndeposit,2,6,1.0",
+            ProcessingOptions { max_transactions_per_client: Some(2), ..Default::default() },
+            vec![
+                ClientStatus { id: 1, available: 3.0, held: 0.0, held_from_withdrawals: 0.0, total: 3.0, locked: false, last_activity: None },
+                ClientStatus { id: 2, available: 2.0, held: 0.0, held_from_withdrawals: 0.0, total: 2.0, locked: false, last_activity: None },
+            ],
+            vec![ClientStatusError::RateLimitExceeded(1).into(), ClientStatusError::RateLimitExceeded(1).into()]
+        );
+    }
+
+    #[test]
+    fn test_headerless_input_uses_the_given_columns() {
+        let columns = crate::read_columns("columns = [\"client\", \"type\", \"amount\", \"tx\"]".as_bytes()).unwrap();
+        test_result_with_options(
+            "1,deposit,1.0,1\n2,deposit,2.0,2\n1,withdrawal,0.5,3",
+            ProcessingOptions { columns: Some(columns), ..Default::default() },
+            vec![
+                ClientStatus { id: 1, available: 0.5, held: 0.0, held_from_withdrawals: 0.0, total: 0.5, locked: false, last_activity: None },
+                ClientStatus { id: 2, available: 2.0, held: 0.0, held_from_withdrawals: 0.0, total: 2.0, locked: false, last_activity: None },
+            ],
+            vec![]
+        );
+    }
+
+    const TRUNCATED: &str = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\nwithdrawal,1,3";
+
+    #[test]
+    fn test_truncated_last_row_can_be_ignored() {
+        let options = || ProcessingOptions { on_trailing_partial_row: TrailingRowPolicy::Ignore, ..Default::default() };
+        let expected = vec![ClientStatus { id: 1, available: 3.0, held: 0.0, held_from_withdrawals: 0.0, total: 3.0, locked: false, last_activity: None }];
+        test_result_with_options(TRUNCATED, options(), expected.clone(), vec![]);
+        // Only the last row is ever partial.
+        test_result_with_options("type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\n", options(), expected, vec![]);
+    }
+
+    #[test]
+    fn test_truncated_last_row_can_be_an_error() {
+        let report = execute_transactions_with_options(
+            TRUNCATED.as_bytes(),
+            2,
+            ProcessingOptions { on_trailing_partial_row: TrailingRowPolicy::Error, ..Default::default() },
+        );
+        assert_eq!(report.results[0].total, 3.0);
+        assert!(matches!(report.errors.as_slice(), [ProcessingError::PartialRow { line: 4 }]));
+        let report = execute_transactions_with_options(TRUNCATED.as_bytes(), 2, ProcessingOptions::default());
+        assert!(matches!(report.errors.as_slice(), [ProcessingError::Parse(TransactionParseError::TooFewColumns { line: 4, .. })]));
+    }
+
+    #[test]
+    fn test_audited_clients_record_their_transitions() {
+        use crate::{StatusTransition, TransactionStatus};
+        let options = ProcessingOptions { audit_clients: HashSet::from([1]), ..Default::default() };
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\ndispute,1,1,\ndispute,2,2,\nresolve,1,1,\nwithdrawal,1,3,5.0";
+        let report = execute_transactions_with_options(transactions.as_bytes(), 2, options);
+        assert_eq!(report.transitions.keys().collect::<Vec<_>>(), vec![&1]);
+        assert_eq!(report.transitions[&1], vec![
+            StatusTransition { tx: 1, from: None, to: TransactionStatus::Deposited, row: 0, raw_amount: None },
+            StatusTransition { tx: 1, from: Some(TransactionStatus::Deposited), to: TransactionStatus::OnDispute, row: 2, raw_amount: None },
+            StatusTransition { tx: 1, from: Some(TransactionStatus::OnDispute), to: TransactionStatus::Resolved, row: 4, raw_amount: None },
+            StatusTransition { tx: 3, from: None, to: TransactionStatus::FailedWithdrawal, row: 5, raw_amount: None },
+        ]);
+    }
+
+    #[test]
+    fn test_disputes_are_audited_against_the_raw_amount() {
+        use crate::TransactionStatus;
+        let options = ProcessingOptions { audit_clients: HashSet::from([1]), retain_raw_amounts: true, ..Default::default() };
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.123456\ndispute,1,1,";
+        let report = execute_transactions_with_options(transactions.as_bytes(), 2, options);
+        let dispute = report.transitions[&1].iter().find(|transition| transition.to == TransactionStatus::OnDispute).unwrap();
+        assert_eq!(dispute.raw_amount.as_deref(), Some("1.123456"));
+        assert_eq!(report.results[0].held, 1.1235);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_unreasonable_thread_count_is_reduced() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\nwithdrawal,1,3,0.5";
+        let report = execute_transactions_with_options(transactions.as_bytes(), usize::MAX, ProcessingOptions::default());
+        let mut results = report.results;
+        results.sort_by_key(|status| status.id);
+        assert_eq!(results, vec![
+            ClientStatus { id: 1, available: 0.5, held: 0.0, held_from_withdrawals: 0.0, total: 0.5, locked: false, last_activity: None },
+            ClientStatus { id: 2, available: 2.0, held: 0.0, held_from_withdrawals: 0.0, total: 2.0, locked: false, last_activity: None },
+        ]);
+        assert!(matches!(
+            report.warnings[..],
+            [ProcessingWarning::ThreadPoolReduced { requested: usize::MAX, used }] if used < usize::MAX
+        ));
+    }
+
+    #[test]
+    fn test_headers_can_be_matched_regardless_of_case() {
+        let transactions = "Type,Client,TX,Amount\ndeposit,1,1,1.0\nwithdrawal,1,2,0.5";
+        let options = ProcessingOptions { case_insensitive_headers: true, ..Default::default() };
+        let report = execute_transactions_with_options(transactions.as_bytes(), 2, options);
+        assert!(report.errors.is_empty());
+        assert_eq!(report.results, vec![
+            ClientStatus { id: 1, available: 0.5, held: 0.0, held_from_withdrawals: 0.0, total: 0.5, locked: false, last_activity: None },
+        ]);
+        let report = execute_transactions_with_options(transactions.as_bytes(), 2, ProcessingOptions::default());
+        assert_eq!(report.errors.len(), 2);
+        assert!(report.results.is_empty());
+    }
+
+    #[test]
+    fn test_balances_are_reported_with_the_output_precision() {
+        use crate::StatusSink;
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.2345\ndeposit,1,2,1.0001\ndispute,1,2,\nwithdrawal,1,3,0.0049";
+        let streamed = Arc::new(Mutex::new(vec![]));
+        let sink = {
+            let streamed = streamed.clone();
+            StatusSink::new(move |status| streamed.lock().unwrap().push(status))
+        };
+        let options = ProcessingOptions { output_precision: Some(2), status_sink: Some(sink), ..Default::default() };
+        let report = execute_transactions_with_options(transactions.as_bytes(), 2, options);
+        let rounded = ClientStatus { id: 1, available: 1.23, held: 1.0, held_from_withdrawals: 0.0, total: 2.23, locked: false, last_activity: None };
+        assert_eq!(*streamed.lock().unwrap(), vec![rounded.clone()]);
+        // The report keeps every decimal, for whatever is computed from it.
+        assert_eq!(report.results, vec![
+            ClientStatus { id: 1, available: 1.2296, held: 1.0001, held_from_withdrawals: 0.0, total: 2.2297, locked: false, last_activity: None },
+        ]);
+        assert_eq!(report.results[0].rounded(2), rounded);
+    }
+
+    #[test]
+    fn test_statuses_are_handed_to_the_sink() {
+        use crate::StatusSink;
+        let streamed = Arc::new(Mutex::new(vec![]));
+        let sink = {
+            let streamed = streamed.clone();
+            StatusSink::new(move |status| streamed.lock().unwrap().push(status))
+        };
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0";
+        let options = ProcessingOptions { status_sink: Some(sink), ..Default::default() };
+        let mut report = execute_transactions_with_options(transactions.as_bytes(), 2, options);
+        let mut streamed = streamed.lock().unwrap().clone();
+        streamed.sort_by_key(|status| status.id);
+        report.results.sort_by_key(|status| status.id);
+        assert_eq!(streamed, report.results);
+    }
+
+    #[test]
+    fn test_deposits_withdrawn_right_away_are_suspicious() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,5.0\nwithdrawal,1,2,5.0\ndeposit,1,3,3.0\nwithdrawal,1,4,3.0\n\
+            deposit,2,5,5.0\nwithdrawal,2,6,5.0\ndeposit,2,7,3.0\nwithdrawal,2,8,2.0\ndeposit,2,9,1.0\nwithdrawal,2,10,1.0\n\
+            deposit,3,11,4.0\ndeposit,3,12,1.0\ndispute,3,12,\nwithdrawal,3,13,4.0\n\
+            deposit,3,14,2.0\ndispute,3,14,\nresolve,3,14,\nwithdrawal,3,15,2.0";
+        let options = ProcessingOptions { suspicious_pairs: Some(2), ..Default::default() };
+        let report = execute_transactions_with_options(transactions.as_bytes(), 2, options);
+        assert!(report.errors.is_empty());
+        assert_eq!(report.warnings.len(), 2);
+        assert!(report.warnings.contains(&ProcessingWarning::SuspiciousActivity(1)));
+        assert!(report.warnings.contains(&ProcessingWarning::SuspiciousActivity(3)));
+        let report = execute_transactions_with_options(transactions.as_bytes(), 2, ProcessingOptions::default());
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_input_is_read_whatever_the_buffer_size() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\nwithdrawal,1,3,0.5";
+        let options = ProcessingOptions { read_buffer_size: 1, ..Default::default() };
+        let mut report = execute_transactions_with_options(transactions.as_bytes(), 2, options);
+        report.results.sort_by_key(|status| status.id);
+        let mut expected = execute_transactions_with_options(transactions.as_bytes(), 2, ProcessingOptions::default());
+        expected.results.sort_by_key(|status| status.id);
+        assert!(report.errors.is_empty());
+        assert_eq!(report.results, expected.results);
+    }
+
+    #[test]
+    fn test_short_rows_are_reported_as_such() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2\ndeposit,1,3,2.0";
+        let report = execute_transactions_with_options(transactions.as_bytes(), 2, ProcessingOptions::default());
+        assert_eq!(report.results, vec![
+            ClientStatus { id: 1, available: 3.0, held: 0.0, held_from_withdrawals: 0.0, total: 3.0, locked: false, last_activity: None },
+        ]);
+        assert!(matches!(
+            report.errors[..],
+            [ProcessingError::Parse(TransactionParseError::TooFewColumns { expected: 4, got: 3, line: 3 })]
+        ));
+        assert_eq!(report.errors[0].line(), Some(3));
+    }
+
+    #[test]
+    fn test_skipped_transactions_are_kept_with_their_reason() {
+        use crate::SkipReason;
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\ndeposit,1,3,1.0\ndeposit,1,4,1.0";
+        let options = ProcessingOptions {
+            client_filter: Some(ClientFilter::Allow(HashSet::from([1]))),
+            max_transactions_per_client: Some(2),
+            skipped: Some(Default::default()),
+            ..Default::default()
+        };
+        let report = execute_transactions_with_options(transactions.as_bytes(), 2, options);
+        assert_eq!(report.skipped, vec![
+            (Transaction::deposit(2, 2, 2.0), SkipReason::FilteredOut),
+            (Transaction::deposit(1, 4, 1.0), SkipReason::RateLimited),
+        ]);
+        let report = execute_transactions_with_options(transactions.as_bytes(), 2, ProcessingOptions::default());
+        assert!(report.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_priority_clients_lead_the_results() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\ndeposit,3,3,3.0\ndeposit,4,4,4.0";
+        let options = ProcessingOptions { client_priority: vec![3, 2], ..Default::default() };
+        let report = execute_transactions_with_options(transactions.as_bytes(), 2, options);
+        let ids: Vec<u32> = report.results.iter().map(|status| status.id).collect();
+        assert_eq!(ids, vec![3, 2, 1, 4]);
+    }
+
+    #[test]
+    fn test_stop_prevents_further_reading() {
+        let stop = Arc::new(AtomicBool::new(true));
+        let report = test_result_with_options(
+            TWO_CLIENTS,
+            ProcessingOptions { stop: Some(stop), ..Default::default() },
+            vec![],
+            vec![]
+        );
+        assert_eq!(report.filtered_transactions, 0);
+    }
+
+    #[test]
+    fn test_duplicate_client_results_are_detected() {
+        let status = ClientStatus { id: 1, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None };
+        let other = ClientStatus { id: 2, ..status.clone() };
+        assert!(check_unique_results(&[status.clone(), other.clone()]).is_empty());
+        assert!(matches!(
+            check_unique_results(&[status.clone(), other, status]).as_slice(),
+            [ProcessingError::DuplicateClientResult(1)]
+        ));
+    }
+
+    fn test_result(transactions: &str, expected_results: Vec<ClientStatus>, expected_errors: Vec<ProcessingError>) {
+        test_result_with_options(transactions, ProcessingOptions::default(), expected_results, expected_errors);
+    }
+
+    fn test_result_with_options(
+        transactions: &str,
+        options: ProcessingOptions,
+        expected_results: Vec<ClientStatus>,
+        expected_errors: Vec<ProcessingError>,
+    ) -> ProcessingReport {
+        let mut report = execute_transactions_with_options(transactions.as_bytes(), 1, options);
+        report.results.sort_by_key(|s| s.id);
+        assert_eq!(expected_results, report.results);
+        assert_eq!(expected_errors.len(), report.errors.len());
+        for (e1, e2) in expected_errors.iter().zip(&report.errors) {
             assert_eq!(e1.to_string(), e2.to_string())
         }
+        report
     }
 }
\ No newline at end of file
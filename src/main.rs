@@ -1,25 +1,535 @@
 use std::env::args;
 use std::fs::File;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 use csv::WriterBuilder;
-use solution::execute_transactions;
+use std::process;
+use solution::{
+    count_rows, encode_transactions, estimate_memory, execute_transactions_with_options, normalize_transactions, read_columns,
+    read_expected_results, write_error_report, write_json_line, write_tx_log, ChecksumAlgorithm, ClientStatus, ClientStatusRecord, MinorUnitsRecord, OutcomeSink,
+    ProcessingOptions, ReportDiff, StatusSink, DECIMALS, DEFAULT_EPSILON,
+};
 
-const USAGE: &'static str = "Usage: ./solution [input file]";
+const USAGE: &str = "Usage: ./solution <command> [arguments], or ./solution [input file] to process it
+
+Commands:
+    process     Prints the balance of every client
+    validate    Checks the transactions, only printing the errors
+    normalize   Writes the valid transactions in canonical form
+    estimate    Estimates the memory processing would take
+    verify      Checks that the balances match the expected ones
+
+Run ./solution <command> --help for the arguments of each one.";
+
+const PROCESS_USAGE: &str = "Usage: ./solution process [--errors-out errors.json] [--tx-log log.csv] [--progress] [--binary | --encode out.bin] [--expect expected.csv [--epsilon e]] [--format csv|jsonl] [--minor-units | --precision decimals] [--schema schema.toml] [--buffer-size bytes] [--checksum crc32|adler32] [--overdraft-limit amount] [input file, or - for stdin]
+
+--minor-units only goes with the csv format. Only the jsonl format has the effective available funds, which differ from the available ones with --overdraft-limit.";
+
+const VALIDATE_USAGE: &str = "Usage: ./solution validate [--errors-out errors.json] [--binary] [--buffer-size bytes] [input file, or - for stdin]
+
+Exits with 1 when any transaction fails.";
+
+const NORMALIZE_USAGE: &str = "Usage: ./solution normalize <input file> <output file>
+
+Writes the valid transactions sorted by client and transaction id, and prints the errors of the rest.";
+
+const ESTIMATE_USAGE: &str = "Usage: ./solution estimate <input file>";
+
+const VERIFY_USAGE: &str = "Usage: ./solution verify [--epsilon e] [--binary] [--buffer-size bytes] <input file> <expected.csv>
+
+Exits with 1 when the balances differ from the expected ones.";
+
+const PROCESS_FLAGS: &[&str] = &[
+    "--errors-out", "--tx-log", "--progress", "--binary", "--encode", "--expect", "--epsilon", "--minor-units", "--schema",
+    "--precision", "--buffer-size", "--format", "--checksum", "--overdraft-limit",
+];
+
+const VALIDATE_FLAGS: &[&str] = &["--errors-out", "--binary", "--buffer-size"];
+
+const VERIFY_FLAGS: &[&str] = &["--epsilon", "--binary", "--buffer-size"];
+
+/// Input files tend to be large, so they are read a megabyte at a time.
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+#[cfg(unix)]
+static STOP: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: libc::c_int) {
+    if let Some(stop) = STOP.get() {
+        stop.store(true, Ordering::SeqCst);
+    }
+}
+
+/// On Ctrl-C, stops reading the input and prints the results computed so
+/// far instead of dying without output.
+#[cfg(unix)]
+fn handle_interrupts() -> Arc<AtomicBool> {
+    let stop = STOP.get_or_init(|| Arc::new(AtomicBool::new(false))).clone();
+    // The handler only stores into an atomic, which is safe to do from a
+    // signal handler. Reads interrupted by the signal are restarted, and
+    // the reader sees the flag before the next row.
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_interrupt as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+    }
+    stop
+}
+
+/// Elsewhere Ctrl-C still ends the process straight away.
+#[cfg(not(unix))]
+fn handle_interrupts() -> Arc<AtomicBool> {
+    Arc::new(AtomicBool::new(false))
+}
+
+struct Args {
+    input: String,
+    /// Where to write the errors as JSON, on top of printing them.
+    errors_out: Option<String>,
+    /// Where to write what became of every row.
+    tx_log: Option<String>,
+    /// Whether to show how many rows were read so far on stderr.
+    progress: bool,
+    /// Whether the input is in the binary encoding instead of csv.
+    binary: bool,
+    /// Where to write the csv input in the binary encoding, instead of
+    /// processing it.
+    encode: Option<String>,
+    /// Results the run must produce, or it fails.
+    expect: Option<String>,
+    /// How far apart expected and actual amounts can be.
+    epsilon: f32,
+    /// Whether to print the amounts as integer ten-thousandths.
+    minor_units: bool,
+    /// Column layout of an input without a header row.
+    schema: Option<String>,
+    /// Decimals to print the amounts with.
+    precision: Option<u32>,
+    /// Size of the buffer the input is read through.
+    buffer_size: usize,
+    /// Whether to print a JSON object per client and line, as soon as
+    /// every client is done, instead of csv.
+    json_lines: bool,
+    /// How to sum up the input, printing the checksum on stderr.
+    checksum: Option<ChecksumAlgorithm>,
+    /// How far below zero withdrawals can take the available funds.
+    overdraft_limit: Option<f32>,
+}
+
+/// What the binary was asked to do.
+enum Command {
+    Process(Args),
+    Validate(Args),
+    Normalize { input: String, output: String },
+    Estimate(String),
+    Verify(Args),
+    Help(&'static str),
+}
+
+/// Reads the command out of the arguments, which don't include the name
+/// of the binary.
+fn parse_command<I: IntoIterator<Item = String>>(args: I) -> Result<Command, &'static str> {
+    let mut args: Vec<String> = args.into_iter().collect();
+    let (usage, command) = match args.first().map(String::as_str) {
+        Some("process") => (PROCESS_USAGE, "process"),
+        Some("validate") => (VALIDATE_USAGE, "validate"),
+        Some("normalize") => (NORMALIZE_USAGE, "normalize"),
+        Some("estimate") => (ESTIMATE_USAGE, "estimate"),
+        Some("verify") => (VERIFY_USAGE, "verify"),
+        Some("help" | "--help" | "-h") => return Ok(Command::Help(USAGE)),
+        None => return Err(USAGE),
+        // A bare input file is processed, as before there were commands.
+        Some(_) => return parse_args(args.into_iter(), PROCESS_FLAGS).map(Command::Process).ok_or(PROCESS_USAGE),
+    };
+    args.remove(0);
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        return Ok(Command::Help(usage));
+    }
+    let command = match command {
+        "process" => parse_args(args.into_iter(), PROCESS_FLAGS).map(Command::Process),
+        "validate" => parse_args(args.into_iter(), VALIDATE_FLAGS).map(Command::Validate),
+        "normalize" => match <[String; 2]>::try_from(args) {
+            Ok([input, output]) => Some(Command::Normalize { input, output }),
+            Err(_) => None,
+        },
+        "estimate" => match <[String; 1]>::try_from(args) {
+            Ok([input]) => Some(Command::Estimate(input)),
+            Err(_) => None,
+        },
+        _ => {
+            // The expected results go last, after the input.
+            let expect = args.pop().filter(|_| !args.is_empty());
+            parse_args(args.into_iter(), VERIFY_FLAGS).filter(|_| expect.is_some()).map(|args| Command::Verify(Args { expect, ..args }))
+        }
+    };
+    command.ok_or(usage)
+}
+
+/// Reads the arguments of a command, which only takes the given `flags`.
+fn parse_args<I: Iterator<Item = String>>(mut args: I, flags: &[&str]) -> Option<Args> {
+    let mut input = None;
+    let mut errors_out = None;
+    let mut tx_log = None;
+    let mut progress = false;
+    let mut binary = false;
+    let mut encode = None;
+    let mut expect = None;
+    let mut epsilon = DEFAULT_EPSILON;
+    let mut minor_units = false;
+    let mut schema = None;
+    let mut precision = None;
+    let mut json_lines = false;
+    let mut buffer_size = BUFFER_SIZE;
+    let mut checksum = None;
+    let mut overdraft_limit = None;
+    while let Some(arg) = args.next() {
+        if arg.starts_with("--") && !flags.contains(&arg.as_str()) {
+            return None;
+        }
+        match arg.as_str() {
+            "--errors-out" => errors_out = Some(args.next()?),
+            "--tx-log" => tx_log = Some(args.next()?),
+            "--progress" => progress = true,
+            "--binary" => binary = true,
+            "--encode" => encode = Some(args.next()?),
+            "--expect" => expect = Some(args.next()?),
+            "--epsilon" => epsilon = args.next()?.parse().ok()?,
+            "--minor-units" => minor_units = true,
+            "--schema" => schema = Some(args.next()?),
+            "--precision" => precision = Some(args.next()?.parse().ok().filter(|decimals| *decimals <= DECIMALS)?),
+            "--buffer-size" => buffer_size = args.next()?.parse().ok().filter(|size| *size > 0)?,
+            "--format" => json_lines = match args.next()?.as_str() {
+                "csv" => false,
+                "jsonl" => true,
+                _ => return None,
+            },
+            "--checksum" => checksum = Some(match args.next()?.as_str() {
+                "crc32" => ChecksumAlgorithm::Crc32,
+                "adler32" => ChecksumAlgorithm::Adler32,
+                _ => return None,
+            }),
+            "--overdraft-limit" => overdraft_limit = Some(args.next()?.parse().ok().filter(|limit: &f32| limit.is_finite() && *limit >= 0f32)?),
+            _ if input.is_none() => input = Some(arg),
+            _ => return None,
+        }
+    }
+    if minor_units && json_lines {
+        return None;
+    }
+    Some(Args { input: input?, errors_out, tx_log, progress, binary, encode, expect, epsilon, minor_units, schema, precision, json_lines, buffer_size, checksum, overdraft_limit })
+}
+
+/// Writes a JSON line for every client as soon as its worker is done with
+/// it, flushed right away for whoever reads the output as it comes.
+fn json_lines_sink<W: Write + Send + 'static>(overdraft_limit: Option<f32>, writer: W) -> StatusSink {
+    let options = ProcessingOptions { overdraft_limit, ..Default::default() };
+    let writer = Mutex::new(writer);
+    StatusSink::new(move |status| {
+        let mut writer = writer.lock().unwrap();
+        write_json_line(&status, &options, &mut *writer).unwrap();
+        writer.flush().unwrap();
+    })
+}
+
+/// `status` as it is printed, with `precision` decimals when given.
+fn rounded(status: &ClientStatus, precision: Option<u32>) -> ClientStatus {
+    precision.map_or_else(|| status.clone(), |decimals| status.rounded(decimals))
+}
+
+fn describe(status: &ClientStatusRecord) -> String {
+    format!("available={} held={} total={} locked={}", status.available, status.held, status.total, status.locked)
+}
+
+fn print_diff(diff: &ReportDiff) {
+    for (status, expected) in &diff.changed {
+        let (status, expected) = (ClientStatusRecord::from(status), ClientStatusRecord::from(expected));
+        eprintln!("client {}: expected {}, got {}", status.client, describe(&expected), describe(&status));
+    }
+    for status in diff.only_in_self.iter().map(ClientStatusRecord::from) {
+        eprintln!("client {}: not expected, got {}", status.client, describe(&status));
+    }
+    for expected in diff.only_in_other.iter().map(ClientStatusRecord::from) {
+        eprintln!("client {}: expected {}, got nothing", expected.client, describe(&expected));
+    }
+}
+
+/// Prints the rows read so far until `done` is set, out of `total` when
+/// the input could be counted up front.
+fn show_progress(rows_read: Arc<AtomicUsize>, total: Option<usize>, done: Arc<AtomicBool>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        let finished = done.load(Ordering::SeqCst);
+        let read = rows_read.load(Ordering::Relaxed);
+        match total {
+            Some(total) if total > 0 => eprint!("\r{}/{} rows ({}%)", read, total, read * 100 / total),
+            _ => eprint!("\r{} rows", read),
+        }
+        if finished {
+            eprintln!();
+            return;
+        }
+        thread::sleep(Duration::from_millis(200));
+    })
+}
 
 fn main() {
-    let file_path = match args().skip(1).next() {
-        Some(f) => f,
-        None => panic!("{}", USAGE)
+    let command = match parse_command(args().skip(1)) {
+        Ok(command) => command,
+        Err(usage) => panic!("{}", usage)
     };
-    let file = File::open(file_path).unwrap();
-    let (result, errors) = execute_transactions(&file, num_cpus::get());
+    match command {
+        Command::Process(args) => process(args),
+        Command::Validate(args) => validate(args),
+        Command::Normalize { input, output } => normalize(&input, &output),
+        Command::Estimate(input) => estimate(&input),
+        Command::Verify(args) => verify(args),
+        Command::Help(usage) => println!("{}", usage),
+    }
+}
+
+fn open_input(input: &str) -> Box<dyn Read> {
+    if input == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(File::open(input).unwrap())
+    }
+}
+
+fn validate(args: Args) {
+    let options = ProcessingOptions { binary_input: args.binary, read_buffer_size: args.buffer_size, ..Default::default() };
+    let report = execute_transactions_with_options(open_input(&args.input), num_cpus::get(), options);
+    if let Some(errors_out) = args.errors_out {
+        write_error_report(&report.errors, File::create(errors_out).unwrap()).unwrap();
+    }
+    for e in &report.errors {
+        eprintln!("{}", e);
+    }
+    eprintln!("{} errors", report.errors.len());
+    if !report.errors.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn normalize(input: &str, output: &str) {
+    let errors = normalize_transactions(open_input(input), io::BufWriter::new(File::create(output).unwrap()));
     for e in errors {
-        eprintln!("{}", e.to_string());
+        eprintln!("{}", e);
+    }
+}
+
+fn estimate(input: &str) {
+    let estimate = estimate_memory(File::open(input).unwrap()).unwrap();
+    println!("rows: {}", estimate.rows);
+    println!("skipped rows: {}", estimate.skipped_rows);
+    println!("clients: {}", estimate.clients);
+    println!("most transactions of a client: {}", estimate.max_transactions_per_client);
+    println!("bytes: {} ({} for the accounts, {} for the channels)", estimate.total_bytes(), estimate.status_bytes, estimate.channel_bytes);
+}
+
+fn verify(args: Args) {
+    let options = ProcessingOptions { binary_input: args.binary, read_buffer_size: args.buffer_size, ..Default::default() };
+    let report = execute_transactions_with_options(open_input(&args.input), num_cpus::get(), options);
+    let expected = read_expected_results(File::open(args.expect.unwrap()).unwrap()).unwrap();
+    let diff = report.diff_expected(&expected, args.epsilon);
+    if !diff.is_empty() {
+        print_diff(&diff);
+        process::exit(1);
+    }
+    eprintln!("The balances of {} clients match", report.results.len());
+}
+
+fn process(args: Args) {
+    let columns = args.schema.as_ref().map(|schema| read_columns(File::open(schema).unwrap()).unwrap());
+    // Only files can be counted up front, stdin just shows the rows read.
+    let (input, total): (Box<dyn Read>, Option<usize>) = if args.input == "-" {
+        (Box::new(io::stdin().lock()), None)
+    } else {
+        let mut file = File::open(&args.input).unwrap();
+        // Without a header row, the first row is a transaction as well.
+        let total = if args.progress { Some(count_rows(&mut file).unwrap() + columns.is_some() as usize) } else { None };
+        (Box::new(file), total)
+    };
+    if let Some(encode) = args.encode {
+        let written = encode_transactions(input, io::BufWriter::new(File::create(encode).unwrap())).unwrap();
+        eprintln!("Encoded {} transactions", written);
+        return;
+    }
+    let stop = handle_interrupts();
+    let rows_read = Arc::new(AtomicUsize::new(0));
+    let done = Arc::new(AtomicBool::new(false));
+    let progress = args.progress.then(|| show_progress(rows_read.clone(), total, done.clone()));
+    let outcomes = Arc::new(Mutex::new(vec![]));
+    let outcome_sink = args.tx_log.is_some().then(|| {
+        let outcomes = outcomes.clone();
+        OutcomeSink::new(move |outcome| outcomes.lock().unwrap().push(outcome))
+    });
+    let options = ProcessingOptions {
+        stop: Some(stop.clone()),
+        rows_read: Some(rows_read),
+        binary_input: args.binary,
+        outcome_sink,
+        columns,
+        read_buffer_size: args.buffer_size,
+        checksum: args.checksum,
+        overdraft_limit: args.overdraft_limit,
+        output_precision: args.precision,
+        status_sink: args.json_lines.then(|| json_lines_sink(args.overdraft_limit, io::stdout())),
+        ..Default::default()
+    };
+    let report = execute_transactions_with_options(input, num_cpus::get(), options);
+    done.store(true, Ordering::SeqCst);
+    if let Some(progress) = progress {
+        progress.join().unwrap();
+    }
+    let mismatch = args.expect.map(|expect| {
+        let expected = read_expected_results(File::open(expect).unwrap()).unwrap();
+        report.diff_expected(&expected, args.epsilon)
+    }).filter(|diff| !diff.is_empty());
+    if let Some(tx_log) = args.tx_log {
+        write_tx_log(&outcomes.lock().unwrap(), File::create(tx_log).unwrap()).unwrap();
+    }
+    if let Some(errors_out) = args.errors_out {
+        write_error_report(&report.errors, File::create(errors_out).unwrap()).unwrap();
+    }
+    for e in report.errors {
+        eprintln!("{}", e);
     }
-    let mut wtr = WriterBuilder::new().has_headers(true).from_writer(vec![]);
-    wtr.write_record(&["client","available","held","total","locked"]).unwrap();
-    for client in result {
-        wtr.write_record(&client.to_record()).unwrap();
+    for w in report.warnings {
+        eprintln!("Warning: {}", w);
+    }
+    if let Some(checksum) = report.input_checksum {
+        eprintln!("Input checksum: {:08x}", checksum);
+    } else if args.checksum.is_some() {
+        eprintln!("Input checksum: none, the input was not read to its end");
+    }
+    if stop.load(Ordering::SeqCst) {
+        eprintln!("Interrupted, the results only cover the transactions read so far");
+    }
+    // The jsonl lines were written by the status sink already.
+    if !args.json_lines {
+        let mut wtr = WriterBuilder::new().has_headers(false).from_writer(io::stdout().lock());
+        wtr.write_record(ClientStatusRecord::COLUMNS).unwrap();
+        for client in report.results.iter().map(|client| rounded(client, args.precision)) {
+            if args.minor_units {
+                wtr.serialize(MinorUnitsRecord::from(&client)).unwrap();
+            } else {
+                wtr.serialize(ClientStatusRecord::from(&client)).unwrap();
+            }
+        }
+        wtr.flush().unwrap();
+        drop(wtr);
+        println!();
+    }
+    if let Some(diff) = mismatch {
+        print_diff(&diff);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::thread;
+    use std::time::Duration;
+    use solution::{execute_transactions_with_options, ProcessingOptions};
+    use crate::{json_lines_sink, parse_command, Command, ESTIMATE_USAGE, NORMALIZE_USAGE, PROCESS_USAGE, USAGE, VALIDATE_USAGE, VERIFY_USAGE};
+
+    fn parse(args: &[&str]) -> Result<Command, &'static str> {
+        parse_command(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_bare_input_is_processed() {
+        assert!(matches!(parse(&["input.csv"]), Ok(Command::Process(args)) if args.input == "input.csv"));
+        assert!(matches!(parse(&[]), Err(USAGE)));
+    }
+
+    #[test]
+    fn test_process_takes_every_flag() {
+        let command = parse(&["process", "--format", "jsonl", "--precision", "2", "--schema", "schema.toml", "--tx-log", "log.csv", "-"]);
+        let Ok(Command::Process(args)) = command else {
+            panic!("expected a process command");
+        };
+        assert!(args.json_lines);
+        assert_eq!(args.precision, Some(2));
+        assert_eq!(args.schema.as_deref(), Some("schema.toml"));
+        assert_eq!(args.tx_log.as_deref(), Some("log.csv"));
+        assert_eq!(args.input, "-");
+        assert!(matches!(parse(&["process", "--overdraft-limit", "10.5", "input.csv"]), Ok(Command::Process(args)) if args.overdraft_limit == Some(10.5)));
+        assert!(matches!(parse(&["process", "--overdraft-limit", "-1", "input.csv"]), Err(PROCESS_USAGE)));
+        assert!(matches!(parse(&["process", "--precision", "5", "input.csv"]), Err(PROCESS_USAGE)));
+        assert!(matches!(parse(&["process", "--format", "jsonl", "--minor-units", "input.csv"]), Err(PROCESS_USAGE)));
+        assert!(matches!(parse(&["process", "--help"]), Ok(Command::Help(PROCESS_USAGE))));
+    }
+
+    #[test]
+    fn test_validate_rejects_flags_it_ignores() {
+        assert!(matches!(parse(&["validate", "--binary", "--errors-out", "errors.json", "input.bin"]), Ok(Command::Validate(args)) if args.binary));
+        for flag in ["--schema", "--tx-log", "--format"] {
+            assert!(matches!(parse(&["validate", flag, "value", "input.csv"]), Err(VALIDATE_USAGE)));
+        }
+    }
+
+    #[test]
+    fn test_verify_takes_the_expected_results_last() {
+        let command = parse(&["verify", "--epsilon", "0.01", "input.csv", "expected.csv"]);
+        assert!(matches!(command, Ok(Command::Verify(args)) if args.input == "input.csv" && args.expect.as_deref() == Some("expected.csv")));
+        assert!(matches!(parse(&["verify", "input.csv"]), Err(VERIFY_USAGE)));
+        assert!(matches!(parse(&["verify", "--expect", "other.csv", "input.csv", "expected.csv"]), Err(VERIFY_USAGE)));
+        assert!(matches!(parse(&["verify", "--format", "jsonl", "input.csv", "expected.csv"]), Err(VERIFY_USAGE)));
+    }
+
+    /// Hands every flushed line over, then waits to be let go on.
+    struct Lines {
+        line: Vec<u8>,
+        lines: Sender<String>,
+        go_on: Receiver<()>,
+    }
+
+    impl Write for Lines {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.line.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            let line = String::from_utf8(std::mem::take(&mut self.line)).unwrap();
+            self.lines.send(line).unwrap();
+            self.go_on.recv().unwrap();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_lines_are_written_while_the_run_goes_on() {
+        let (lines, received) = channel();
+        let (go_on, waiting) = channel();
+        let sink = json_lines_sink(None, Lines { line: vec![], lines, go_on: waiting });
+        let run = thread::spawn(move || {
+            let options = ProcessingOptions { status_sink: Some(sink), ..Default::default() };
+            execute_transactions_with_options("type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0".as_bytes(), 1, options)
+        });
+        let first = received.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(first.starts_with("{\"client\":"));
+        // The run waits on the sink, so it can't be over yet.
+        assert!(!run.is_finished());
+        go_on.send(()).unwrap();
+        received.recv_timeout(Duration::from_secs(10)).unwrap();
+        go_on.send(()).unwrap();
+        assert_eq!(run.join().unwrap().results.len(), 2);
+    }
+
+    #[test]
+    fn test_normalize_and_estimate_take_files_only() {
+        assert!(matches!(parse(&["normalize", "in.csv", "out.csv"]), Ok(Command::Normalize { input, output }) if input == "in.csv" && output == "out.csv"));
+        assert!(matches!(parse(&["normalize", "in.csv"]), Err(NORMALIZE_USAGE)));
+        assert!(matches!(parse(&["estimate", "in.csv"]), Ok(Command::Estimate(input)) if input == "in.csv"));
+        assert!(matches!(parse(&["estimate", "in.csv", "extra"]), Err(ESTIMATE_USAGE)));
     }
-    let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
-    println!("{}", data);
 }
@@ -0,0 +1,96 @@
+use std::fs::File;
+use std::io::{self, Cursor};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::{ptr, slice};
+use crate::{execute_transactions_with_options, ProcessingOptions, ProcessingReport};
+
+/// A read-only map of a whole file, unmapped when dropped.
+struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mmap {
+    /// # Safety
+    ///
+    /// The file must not change while it is mapped, see
+    /// [`execute_transactions_mapped`].
+    unsafe fn map(file: &File) -> io::Result<Mmap> {
+        let len = usize::try_from(file.metadata()?.len()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // Mapping nothing is an error, but there is nothing to map either.
+        if len == 0 {
+            return Ok(Mmap { ptr: ptr::null_mut(), len });
+        }
+        let ptr = unsafe { libc::mmap(ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0) };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        // Only a hint, so it failing changes nothing.
+        unsafe { libc::madvise(ptr, len, libc::MADV_SEQUENTIAL) };
+        Ok(Mmap { ptr, len })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe { libc::munmap(self.ptr, self.len) };
+        }
+    }
+}
+
+/// Like [`execute_transactions_with_options`], reading the file at `path`
+/// straight from memory instead of copying it through read calls.
+///
+/// # Safety
+///
+/// The file must stay as it is until the run is over. Reading past the end
+/// of a file that was truncated meanwhile kills the process with `SIGBUS`,
+/// and whatever is written to it may or may not be read.
+pub unsafe fn execute_transactions_mapped<P: AsRef<Path>>(
+    path: P,
+    threads: usize,
+    options: ProcessingOptions,
+) -> io::Result<ProcessingReport> {
+    let file = File::open(path)?;
+    let map = unsafe { Mmap::map(&file)? };
+    Ok(execute_transactions_with_options(Cursor::new(map.as_slice()), threads, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+    use crate::mmap::execute_transactions_mapped;
+    use crate::{execute_transactions_with_options, ProcessingOptions};
+
+    #[test]
+    fn test_mapped_file_is_processed_like_its_contents() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\nwithdrawal,1,3,0.5\n";
+        let path = env::temp_dir().join(format!("solution-mapped-{}.csv", std::process::id()));
+        fs::write(&path, transactions).unwrap();
+        let mut mapped = unsafe { execute_transactions_mapped(&path, 2, ProcessingOptions::default()).unwrap() };
+        fs::remove_file(&path).unwrap();
+        let mut read = execute_transactions_with_options(transactions.as_bytes(), 2, ProcessingOptions::default());
+        mapped.results.sort_by_key(|status| status.id);
+        read.results.sort_by_key(|status| status.id);
+        assert_eq!(mapped.results, read.results);
+        assert!(mapped.errors.is_empty());
+    }
+
+    #[test]
+    fn test_empty_file_has_no_results() {
+        let path = env::temp_dir().join(format!("solution-mapped-empty-{}.csv", std::process::id()));
+        fs::write(&path, "").unwrap();
+        let report = unsafe { execute_transactions_mapped(&path, 1, ProcessingOptions::default()).unwrap() };
+        fs::remove_file(&path).unwrap();
+        assert!(report.results.is_empty());
+    }
+}
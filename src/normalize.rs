@@ -0,0 +1,58 @@
+use std::io::{Read, Write};
+use std::sync::Mutex;
+use csv::WriterBuilder;
+use crate::{csv_reader, parse_record, ProcessingError, ProcessingOptions};
+
+/// Writes the valid transactions of `reader` into `writer` in canonical
+/// form: trimmed, sorted by client and then by transaction id, with the
+/// invalid rows left out. Transactions sharing a client and id keep the
+/// order they had in the input.
+///
+/// The errors of the rows that were dropped are returned, together with
+/// any error writing the output.
+pub fn normalize_transactions<R: Read, W: Write>(reader: R, writer: W) -> Vec<ProcessingError> {
+    let options = ProcessingOptions::default();
+    let mut errors = vec![];
+    let mut reader = csv_reader(reader, &options);
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => return vec![e.into()],
+    };
+    let mut transactions = vec![];
+    let warnings = Mutex::new(vec![]);
+    for record in reader.records() {
+        match record.map_err(ProcessingError::from).and_then(|record| parse_record(&record, &headers, &options, &warnings)) {
+            Ok(transaction) => transactions.push(transaction),
+            Err(e) => errors.push(e),
+        }
+    }
+    transactions.sort_by_key(|t| (t.get_client(), t.get_tx()));
+
+    let mut wtr = WriterBuilder::new().has_headers(true).from_writer(writer);
+    let written = wtr.write_record(["type", "client", "tx", "amount"])
+        .and_then(|_| transactions.iter().try_for_each(|t| wtr.write_record(t.to_record())))
+        .map_err(ProcessingError::from)
+        .and_then(|_| wtr.flush().map_err(ProcessingError::from));
+    if let Err(e) = written {
+        errors.push(e);
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::normalize_transactions;
+
+    #[test]
+    fn test_normalize_sorts_and_drops_invalid_rows() {
+        let transactions = "type, client,tx,amount\ndeposit, 2,3,2.0\nfoo,1,9,1.0\ndeposit,1,2, 1.5\ndeposit,1,1,1.0\ndispute,1,1,0\n";
+        let mut output = vec![];
+        let errors = normalize_transactions(transactions.as_bytes(), &mut output);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "Transaction type foo is invalid");
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "type,client,tx,amount\ndeposit,1,1,1\ndispute,1,1,\ndeposit,1,2,1.5\ndeposit,2,3,2\n"
+        );
+    }
+}
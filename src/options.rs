@@ -0,0 +1,618 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Instant;
+use thiserror::Error;
+use crate::{AuditTrail, CheckpointSink, ChecksumAlgorithm, Clock, FinalStatuses, OutcomeSink, PauseSwitch, SkippedTransactions, SnapshotSink, StatusSink, SystemClock, Transaction, TransactionKind, Trim, Validator};
+use crate::transaction::DECIMALS;
+
+/// Decides what happens to the processing once errors start to show up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorPolicy {
+    /// Stop reading transactions as soon as the first error occurs.
+    FailFast,
+    /// Collect every error and keep going until the end of the input.
+    ContinueAll,
+    /// Keep going until the given number of errors has been collected.
+    ContinueUntil(usize),
+}
+
+impl ErrorPolicy {
+    pub(crate) fn error_limit(&self) -> Option<usize> {
+        match self {
+            ErrorPolicy::FailFast => Some(1),
+            ErrorPolicy::ContinueAll => None,
+            ErrorPolicy::ContinueUntil(n) => Some(*n),
+        }
+    }
+}
+
+/// Restricts the set of clients whose transactions are processed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientFilter {
+    /// Only the listed clients are processed.
+    Allow(HashSet<u32>),
+    /// Every client but the listed ones is processed.
+    Deny(HashSet<u32>),
+}
+
+impl ClientFilter {
+    pub(crate) fn accepts(&self, client: u32) -> bool {
+        match self {
+            ClientFilter::Allow(clients) => clients.contains(&client),
+            ClientFilter::Deny(clients) => !clients.contains(&client),
+        }
+    }
+}
+
+/// Groups the transactions into partitions processed by a single worker
+/// each, instead of giving every client a worker of its own. Without the
+/// `parallel` feature there is a single worker anyway.
+#[derive(Clone)]
+#[cfg_attr(not(feature = "parallel"), allow(dead_code))]
+pub struct PartitionKey(pub(crate) Arc<dyn Fn(&Transaction) -> u64 + Send + Sync>);
+
+impl PartitionKey {
+    pub fn new<F: Fn(&Transaction) -> u64 + Send + Sync + 'static>(f: F) -> PartitionKey {
+        PartitionKey(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for PartitionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PartitionKey")
+    }
+}
+
+/// What happens to the disputes still open on an account once a chargeback
+/// locks it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LockedDisputePolicy {
+    /// They can still be resolved or charged back as usual.
+    Processable,
+    /// Their funds stay held, and resolving or charging them back is
+    /// rejected.
+    Frozen,
+    /// They are resolved along with the chargeback, releasing their funds.
+    AutoResolve,
+}
+
+/// What to do with a last row that does not end with a newline, as when
+/// streamed input stops in the middle of a line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrailingRowPolicy {
+    /// Parses it like any other row, so a complete one goes through and a
+    /// truncated one fails like any broken row.
+    Parse,
+    /// Skips it, whether it is complete or not.
+    Ignore,
+    /// Reports it as `ProcessingError::PartialRow`.
+    Error,
+}
+
+/// What to do with a client whose total ends up below zero, like after the
+/// chargeback of a deposit that was already withdrawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NegativeTotalPolicy {
+    Allow,
+    /// Reports the total as zero, with a warning, taking what the client
+    /// owes off its available funds so they still add up to the total.
+    Clamp,
+    /// Reports an error for the client, keeping its result as is.
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct ProcessingOptions {
+    pub error_policy: ErrorPolicy,
+    pub client_filter: Option<ClientFilter>,
+    /// Keeps the amount of deposits and withdrawals exactly as it appeared
+    /// in the input, so disputes can be audited against it. The audit
+    /// trail of `audit_clients` shows it.
+    pub retain_raw_amounts: bool,
+    /// Limits the funds a dispute holds to what is currently available.
+    /// By default the whole disputed amount is held, even if that makes
+    /// the available funds negative.
+    pub cap_dispute_holds: bool,
+    /// Minimum number of input rows between a dispute and its chargeback.
+    pub min_dispute_age: Option<usize>,
+    /// Clients whose results come first, in this order. The rest follow
+    /// sorted by id. Results are left in completion order when empty.
+    pub client_priority: Vec<u32>,
+    /// Once set, no more transactions are read. The workers still finish
+    /// the ones they already got, so the results reflect everything read
+    /// until then.
+    pub stop: Option<Arc<AtomicBool>>,
+    /// Reports amounts with more decimals than the precision the
+    /// transactions are processed with.
+    pub warn_on_precision_loss: bool,
+    /// Client that rows with an unreadable client field are assigned to
+    /// for manual review, instead of being dropped. Rows that really
+    /// belong to this client are rejected, so its balance only ever
+    /// reflects quarantined rows.
+    pub quarantine_client: Option<u32>,
+    /// Field delimiter of the input.
+    pub delimiter: u8,
+    /// Reads amounts written with a decimal comma, like `2,50`. Only
+    /// makes sense with a delimiter other than the comma.
+    pub decimal_comma: bool,
+    /// Holds the reader between two rows while paused.
+    pub pause: Option<Arc<PauseSwitch>>,
+    /// Ignores a resolve on a transaction that is already resolved, which
+    /// is harmless when replaying a feed.
+    pub tolerate_redundant_resolves: bool,
+    /// Transaction ids the feed uses as placeholders, like `0`. Rows using
+    /// them are rejected instead of being stored and becoming disputable.
+    pub reserved_tx_ids: HashSet<u32>,
+    /// Reports identical errors found while reading the rows once, along
+    /// with how many times they happened.
+    pub collapse_repeated_errors: bool,
+    /// Deposits and withdrawals above this amount are rejected.
+    pub max_transaction_amount: Option<f32>,
+    /// Client whose balance is handed over to `snapshot_sink` after each of
+    /// its transactions. Every other client is left alone.
+    pub debug_client: Option<u32>,
+    pub snapshot_sink: Option<SnapshotSink>,
+    /// Skips, without an error, withdrawals of clients that never deposited
+    /// anything, as feeds shared across systems tend to contain them.
+    pub ignore_withdrawal_without_balance: bool,
+    /// Schema the input must declare on its first line, as in
+    /// `# schema: v2`, before any row is read.
+    pub expected_schema: Option<String>,
+    /// Discards every result when there is any error, so a broken input
+    /// never produces partial balances. The whole input is still read to
+    /// collect the errors.
+    pub atomic: bool,
+    /// Allows disputing withdrawals. The withdrawn funds are held while
+    /// disputed and given back on a chargeback.
+    pub dispute_withdrawals: bool,
+    /// Starts one worker per thread and always assigns a client to the
+    /// worker `client % threads`, instead of starting a worker per client
+    /// as they show up. The same input then gets processed by the same
+    /// workers in the same order on every run, although their results
+    /// still arrive in whatever order they finish.
+    pub deterministic_assignment: bool,
+    /// Number of workers sharing the clients between them, instead of a
+    /// worker per client. Clients are assigned like with
+    /// `deterministic_assignment`. At most `threads` of them run at once.
+    pub max_workers: Option<usize>,
+    /// Source of the current time for every decision that depends on it.
+    pub clock: Arc<dyn Clock>,
+    /// Rejects rows whose timestamp is after the current time.
+    pub reject_future_timestamps: bool,
+    /// Disputes that would hold more than this for a single client are
+    /// rejected.
+    pub max_held_per_client: Option<f32>,
+    /// Once reached, no more transactions are read, like with `stop`.
+    pub deadline: Option<Instant>,
+    pub locked_disputes: LockedDisputePolicy,
+    /// Counts the rows read so far, for showing progress.
+    pub rows_read: Option<Arc<AtomicUsize>>,
+    /// Reads the input in the binary encoding of `encode_transactions`
+    /// instead of as csv.
+    pub binary_input: bool,
+    /// Types of transactions that are processed. The rest are rejected.
+    pub allowed_types: HashSet<TransactionKind>,
+    /// Receives what became of every row of the input.
+    pub outcome_sink: Option<OutcomeSink>,
+    /// Deposits that would take the balance of a client, held funds
+    /// included, above this are rejected.
+    pub max_balance: Option<f32>,
+    /// Credits deposits that would go above `max_balance`, or the largest
+    /// amount that can still be rounded without it, up to that limit
+    /// instead of rejecting them. The rest of the deposit is lost, so the balance is
+    /// no longer the sum of the transactions and only what was credited can
+    /// be disputed. Close to `f32::MAX` amounts are also far less precise
+    /// than the four decimals they are rounded to.
+    pub saturating_arithmetic: bool,
+    /// Whitespace trimmed off the headers and fields of the input.
+    pub trim: Trim,
+    /// Transactions applied once the whole input was read, like manual
+    /// corrections by an operator. They skip the client filter and the
+    /// allowed types, and are told apart from the input in the outcomes.
+    /// Corrections that depend on the results go through `Engine::adjust`.
+    pub adjustments: Vec<Transaction>,
+    pub on_negative_total: NegativeTotalPolicy,
+    /// Hands the state of every account over to `checkpoint_sink` once
+    /// every this many rows, so a crashed run can resume near where it
+    /// stopped. Implies the workers of `max_workers`.
+    pub checkpoint_every: Option<usize>,
+    pub checkpoint_sink: Option<CheckpointSink>,
+    /// Transactions of a client past this many in a run are rejected.
+    pub max_transactions_per_client: Option<u64>,
+    /// Partitions the transactions, like by account group, so all the
+    /// clients of a partition share a worker. Partitions are assigned to
+    /// the workers of `max_workers` like clients are. By client when unset.
+    pub partition_key: Option<PartitionKey>,
+    /// Remembers the ids of resolves and chargebacks that refer to nothing,
+    /// so disputing one of them later is reported as such instead of as a
+    /// missing transaction. Disputing a missing transaction twice is still
+    /// about a missing transaction.
+    pub track_operation_ids: bool,
+    /// Names of the columns of an input without a header row, in order,
+    /// as read by `read_columns`. The first row is a header otherwise.
+    pub columns: Option<Vec<String>>,
+    pub on_trailing_partial_row: TrailingRowPolicy,
+    /// Clients whose transactions have every change of status recorded
+    /// into `audit_trail`, and the report.
+    pub audit_clients: HashSet<u32>,
+    /// Set by the run when there are `audit_clients`, unless one is given.
+    pub audit_trail: Option<AuditTrail>,
+    /// Deposits of less than this are never disputed, so they are only
+    /// added to the balance instead of being kept, along with their id.
+    /// Disputing one is reported as if it never happened, while reusing its
+    /// id is still a `DuplicatedTransaction`.
+    pub aggregate_deposits_below: Option<f32>,
+    /// Where to keep the status every transaction ended in, for
+    /// `ProcessingReport::transactions_with_status`. Nothing is kept
+    /// otherwise.
+    pub final_statuses: Option<FinalStatuses>,
+    /// Matches the names of the columns regardless of their case, so that
+    /// a `Type` or `TX` header is read as `type` or `tx`.
+    pub case_insensitive_headers: bool,
+    /// How many rows back a transaction can be disputed from. Older ones
+    /// are rejected and stay as they were.
+    pub dispute_lookback: Option<usize>,
+    /// Decimals the balances are handed to `status_sink` with. The report
+    /// keeps them as computed, with four, so output rounds them itself with
+    /// `ClientStatus::rounded`.
+    pub output_precision: Option<u32>,
+    /// Gets the status of every client as soon as its worker is done with
+    /// it, rounded to `output_precision`. The report still has them all,
+    /// in the end. Negative totals are clamped before the sink gets them,
+    /// so it sees the same balances as the report, but an atomic run could
+    /// only discard them after the fact, so `build` rejects the two
+    /// together.
+    pub status_sink: Option<StatusSink>,
+    /// Warns about clients making this many deposits in a row, each one
+    /// withdrawn right away for the same amount.
+    pub suspicious_pairs: Option<usize>,
+    /// Size in bytes of the buffer the input is read through.
+    pub read_buffer_size: usize,
+    /// Where to keep the transactions left out of the run, and why, for
+    /// `ProcessingReport::skipped`. Nothing is kept otherwise.
+    pub skipped: Option<SkippedTransactions>,
+    /// Reports clients that only ever showed up in disputes, resolves and
+    /// chargebacks, with nothing to their name. They are left out of the
+    /// results otherwise, still along with the errors of their rows.
+    pub include_phantom_clients: bool,
+    /// Rejects deposits, withdrawals and fees whose amount is too small to
+    /// survive the rounding, instead of processing them as zero. Applies to
+    /// every input, as long as the amounts reach it unrounded.
+    pub reject_subprecision_amounts: bool,
+    /// Sorts the errors by line, then client and then transaction, so the
+    /// same input reports them in the same order however the workers ran.
+    /// Errors of a worker keep their order among those with the same key,
+    /// and the ones missing a field come before the rest.
+    pub sort_errors: bool,
+    /// How far below zero withdrawals and fees can take the available
+    /// funds. Also part of `ClientStatus::effective_available`. The builder
+    /// rejects it together with `NegativeTotalPolicy::Clamp`.
+    pub overdraft_limit: Option<f32>,
+    /// Checks of its own for each type of transaction, run before the
+    /// transaction is applied. A transaction failing one is rejected with
+    /// `ClientStatusError::CustomValidationFailed`.
+    pub validators: HashMap<TransactionKind, Validator>,
+    /// Computes a checksum of the input as it is read, for
+    /// `ProcessingReport::input_checksum`. Only readers are summed up: a
+    /// run over a transaction source or several files is rejected with
+    /// `ProcessingError::ChecksumUnsupported` instead.
+    pub checksum: Option<ChecksumAlgorithm>,
+}
+
+/// Same as the one of `std::io::BufReader`.
+pub const DEFAULT_READ_BUFFER_SIZE: usize = 8 * 1024;
+
+impl ProcessingOptions {
+    pub fn builder() -> ProcessingOptionsBuilder {
+        ProcessingOptionsBuilder::default()
+    }
+
+    /// Options for feeds that have to be processed exactly as they are:
+    /// any error discards the results.
+    pub fn strict() -> ProcessingOptions {
+        ProcessingOptions::builder().strict().options
+    }
+
+    /// Options for messy feeds: whatever is harmless is let through.
+    pub fn lenient() -> ProcessingOptions {
+        ProcessingOptions::builder().lenient().options
+    }
+
+    /// The partition of the worker processing the transaction.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn partition_of(&self, transaction: &Transaction) -> u64 {
+        match &self.partition_key {
+            Some(PartitionKey(key)) => key(transaction),
+            None => transaction.get_client() as u64,
+        }
+    }
+
+    pub(crate) fn stop_requested(&self) -> bool {
+        self.stop.as_ref().is_some_and(|stop| stop.load(Ordering::SeqCst))
+    }
+
+    pub(crate) fn deadline_reached(&self) -> bool {
+        self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+impl Default for ProcessingOptions {
+    fn default() -> Self {
+        ProcessingOptions {
+            error_policy: ErrorPolicy::ContinueAll,
+            client_filter: None,
+            retain_raw_amounts: false,
+            cap_dispute_holds: false,
+            min_dispute_age: None,
+            client_priority: vec![],
+            stop: None,
+            warn_on_precision_loss: false,
+            quarantine_client: None,
+            delimiter: b',',
+            decimal_comma: false,
+            pause: None,
+            tolerate_redundant_resolves: false,
+            reserved_tx_ids: HashSet::new(),
+            collapse_repeated_errors: false,
+            max_transaction_amount: None,
+            debug_client: None,
+            snapshot_sink: None,
+            ignore_withdrawal_without_balance: false,
+            expected_schema: None,
+            atomic: false,
+            dispute_withdrawals: false,
+            deterministic_assignment: false,
+            max_workers: None,
+            clock: Arc::new(SystemClock),
+            reject_future_timestamps: false,
+            max_held_per_client: None,
+            deadline: None,
+            locked_disputes: LockedDisputePolicy::Processable,
+            rows_read: None,
+            binary_input: false,
+            allowed_types: TransactionKind::ALL.into_iter().collect(),
+            outcome_sink: None,
+            max_balance: None,
+            saturating_arithmetic: false,
+            trim: Trim::All,
+            adjustments: vec![],
+            on_negative_total: NegativeTotalPolicy::Allow,
+            checkpoint_every: None,
+            checkpoint_sink: None,
+            max_transactions_per_client: None,
+            partition_key: None,
+            track_operation_ids: false,
+            columns: None,
+            on_trailing_partial_row: TrailingRowPolicy::Parse,
+            audit_clients: HashSet::new(),
+            audit_trail: None,
+            aggregate_deposits_below: None,
+            final_statuses: None,
+            case_insensitive_headers: false,
+            dispute_lookback: None,
+            output_precision: None,
+            status_sink: None,
+            suspicious_pairs: None,
+            read_buffer_size: DEFAULT_READ_BUFFER_SIZE,
+            skipped: None,
+            include_phantom_clients: true,
+            reject_subprecision_amounts: false,
+            sort_errors: false,
+            overdraft_limit: None,
+            validators: HashMap::new(),
+            checksum: None,
+        }
+    }
+}
+
+/// Combinations of options that contradict each other.
+#[derive(Debug, Error, PartialEq)]
+pub enum OptionsError {
+    #[error("Atomic processing reads the whole input, which failing fast stops")]
+    AtomicFailFast,
+    #[error("Amounts with a decimal comma need a delimiter other than the comma")]
+    DecimalCommaDelimiter,
+    #[error("Checkpoints need both an interval above zero and a sink")]
+    IncompleteCheckpoints,
+    #[error("Limit {0} is not above zero")]
+    NonPositiveLimit(f32),
+    #[error("Amounts can't be reported with {0} decimals, as they are computed with {DECIMALS}")]
+    OutputPrecision(u32),
+    #[error("An overdraft lets totals go below zero, which clamping them hides")]
+    ClampedOverdraft,
+    #[error("A status sink gets the balances before an atomic run can discard them")]
+    AtomicStatusSink,
+}
+
+/// Builds `ProcessingOptions` one setting at a time, checking that they go
+/// together on `build`.
+#[derive(Clone, Debug, Default)]
+pub struct ProcessingOptionsBuilder {
+    options: ProcessingOptions,
+}
+
+impl ProcessingOptionsBuilder {
+    /// See `ProcessingOptions::strict`.
+    pub fn strict(mut self) -> ProcessingOptionsBuilder {
+        self.options.error_policy = ErrorPolicy::ContinueAll;
+        self.options.atomic = true;
+        self.options.warn_on_precision_loss = true;
+        self.options.reject_subprecision_amounts = true;
+        self.options.reject_future_timestamps = true;
+        self.options.locked_disputes = LockedDisputePolicy::Frozen;
+        self.options.on_negative_total = NegativeTotalPolicy::Error;
+        self
+    }
+
+    /// See `ProcessingOptions::lenient`.
+    pub fn lenient(mut self) -> ProcessingOptionsBuilder {
+        self.options.tolerate_redundant_resolves = true;
+        self.options.ignore_withdrawal_without_balance = true;
+        self.options.collapse_repeated_errors = true;
+        self.options.cap_dispute_holds = true;
+        self.options.on_negative_total = NegativeTotalPolicy::Clamp;
+        self
+    }
+
+    pub fn error_policy(mut self, error_policy: ErrorPolicy) -> ProcessingOptionsBuilder {
+        self.options.error_policy = error_policy;
+        self
+    }
+
+    pub fn atomic(mut self, atomic: bool) -> ProcessingOptionsBuilder {
+        self.options.atomic = atomic;
+        self
+    }
+
+    pub fn client_filter(mut self, client_filter: ClientFilter) -> ProcessingOptionsBuilder {
+        self.options.client_filter = Some(client_filter);
+        self
+    }
+
+    pub fn delimiter(mut self, delimiter: u8) -> ProcessingOptionsBuilder {
+        self.options.delimiter = delimiter;
+        self
+    }
+
+    pub fn decimal_comma(mut self, decimal_comma: bool) -> ProcessingOptionsBuilder {
+        self.options.decimal_comma = decimal_comma;
+        self
+    }
+
+    pub fn dispute_withdrawals(mut self, dispute_withdrawals: bool) -> ProcessingOptionsBuilder {
+        self.options.dispute_withdrawals = dispute_withdrawals;
+        self
+    }
+
+    pub fn locked_disputes(mut self, locked_disputes: LockedDisputePolicy) -> ProcessingOptionsBuilder {
+        self.options.locked_disputes = locked_disputes;
+        self
+    }
+
+    pub fn on_negative_total(mut self, on_negative_total: NegativeTotalPolicy) -> ProcessingOptionsBuilder {
+        self.options.on_negative_total = on_negative_total;
+        self
+    }
+
+    pub fn max_transaction_amount(mut self, limit: f32) -> ProcessingOptionsBuilder {
+        self.options.max_transaction_amount = Some(limit);
+        self
+    }
+
+    pub fn validator(mut self, kind: TransactionKind, validator: Validator) -> ProcessingOptionsBuilder {
+        self.options.validators.insert(kind, validator);
+        self
+    }
+
+    pub fn overdraft_limit(mut self, limit: f32) -> ProcessingOptionsBuilder {
+        self.options.overdraft_limit = Some(limit);
+        self
+    }
+
+    pub fn max_held_per_client(mut self, limit: f32) -> ProcessingOptionsBuilder {
+        self.options.max_held_per_client = Some(limit);
+        self
+    }
+
+    pub fn max_balance(mut self, limit: f32) -> ProcessingOptionsBuilder {
+        self.options.max_balance = Some(limit);
+        self
+    }
+
+    pub fn max_transactions_per_client(mut self, limit: u64) -> ProcessingOptionsBuilder {
+        self.options.max_transactions_per_client = Some(limit);
+        self
+    }
+
+    pub fn aggregate_deposits_below(mut self, limit: f32) -> ProcessingOptionsBuilder {
+        self.options.aggregate_deposits_below = Some(limit);
+        self
+    }
+
+    pub fn output_precision(mut self, decimals: u32) -> ProcessingOptionsBuilder {
+        self.options.output_precision = Some(decimals);
+        self
+    }
+
+    pub fn include_phantom_clients(mut self, include: bool) -> ProcessingOptionsBuilder {
+        self.options.include_phantom_clients = include;
+        self
+    }
+
+    pub fn max_workers(mut self, workers: usize) -> ProcessingOptionsBuilder {
+        self.options.max_workers = Some(workers);
+        self
+    }
+
+    pub fn status_sink(mut self, sink: StatusSink) -> ProcessingOptionsBuilder {
+        self.options.status_sink = Some(sink);
+        self
+    }
+
+    pub fn checkpoints(mut self, every: usize, sink: CheckpointSink) -> ProcessingOptionsBuilder {
+        self.options.checkpoint_every = Some(every);
+        self.options.checkpoint_sink = Some(sink);
+        self
+    }
+
+    pub fn build(self) -> Result<ProcessingOptions, OptionsError> {
+        let options = self.options;
+        if options.atomic && options.error_policy == ErrorPolicy::FailFast {
+            return Err(OptionsError::AtomicFailFast);
+        }
+        if options.decimal_comma && options.delimiter == b',' {
+            return Err(OptionsError::DecimalCommaDelimiter);
+        }
+        if options.checkpoint_every.is_some() != options.checkpoint_sink.is_some() || options.checkpoint_every == Some(0) {
+            return Err(OptionsError::IncompleteCheckpoints);
+        }
+        let limits = [options.max_transaction_amount, options.max_held_per_client, options.max_balance, options.aggregate_deposits_below, options.overdraft_limit];
+        if let Some(limit) = limits.into_iter().flatten().find(|limit| limit.is_nan() || *limit <= 0f32) {
+            return Err(OptionsError::NonPositiveLimit(limit));
+        }
+        if let Some(decimals) = options.output_precision.filter(|decimals| *decimals > DECIMALS) {
+            return Err(OptionsError::OutputPrecision(decimals));
+        }
+        if options.overdraft_limit.is_some() && options.on_negative_total == NegativeTotalPolicy::Clamp {
+            return Err(OptionsError::ClampedOverdraft);
+        }
+        if options.atomic && options.status_sink.is_some() {
+            return Err(OptionsError::AtomicStatusSink);
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CheckpointSink, ErrorPolicy, NegativeTotalPolicy, OptionsError, ProcessingOptions, ProcessingOptionsBuilder, StatusSink};
+
+    #[test]
+    fn test_builder_chains_presets_and_settings() {
+        let options = ProcessingOptions::builder().lenient().max_balance(100.0).delimiter(b';').decimal_comma(true).build().unwrap();
+        assert!(options.tolerate_redundant_resolves);
+        assert_eq!(options.max_balance, Some(100.0));
+        assert_eq!(options.delimiter, b';');
+        let options = ProcessingOptions::builder().lenient().strict().build().unwrap();
+        assert!(options.atomic);
+        assert_eq!(options.on_negative_total, NegativeTotalPolicy::Error);
+        assert!(ProcessingOptions::strict().atomic);
+        assert_eq!(ProcessingOptions::lenient().on_negative_total, NegativeTotalPolicy::Clamp);
+    }
+
+    #[test]
+    fn test_contradictory_options_are_rejected() {
+        let build = |builder: ProcessingOptionsBuilder| builder.build().err();
+        assert_eq!(build(ProcessingOptions::builder().strict().error_policy(ErrorPolicy::FailFast)), Some(OptionsError::AtomicFailFast));
+        assert_eq!(build(ProcessingOptions::builder().decimal_comma(true)), Some(OptionsError::DecimalCommaDelimiter));
+        assert_eq!(build(ProcessingOptions::builder().checkpoints(0, CheckpointSink::new(|_| {}))), Some(OptionsError::IncompleteCheckpoints));
+        assert_eq!(build(ProcessingOptions::builder().max_held_per_client(-1.0)), Some(OptionsError::NonPositiveLimit(-1.0)));
+        assert_eq!(build(ProcessingOptions::builder().output_precision(6)), Some(OptionsError::OutputPrecision(6)));
+        assert_eq!(build(ProcessingOptions::builder().lenient().overdraft_limit(5.0)), Some(OptionsError::ClampedOverdraft));
+        assert_eq!(build(ProcessingOptions::builder().strict().overdraft_limit(5.0)), None);
+        assert_eq!(build(ProcessingOptions::builder().strict().status_sink(StatusSink::new(|_| {}))), Some(OptionsError::AtomicStatusSink));
+        assert_eq!(build(ProcessingOptions::builder().strict()), None);
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use serde::Serialize;
+use crate::{ProcessingError, ProcessingOptions, Transaction, TransactionKind};
+
+/// What became of a row of the input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Applied,
+    Rejected,
+    /// Left alone on purpose, like the rows of filtered clients.
+    Ignored,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let outcome = match self {
+            Outcome::Applied => "applied",
+            Outcome::Rejected => "rejected",
+            Outcome::Ignored => "ignored",
+        };
+        f.write_str(outcome)
+    }
+}
+
+/// The outcome of a single row. Rows that could not be parsed only know
+/// what their error tells about them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransactionOutcome {
+    /// Row of the input, not counting the header.
+    pub row: usize,
+    pub tx: Option<u32>,
+    pub client: Option<u32>,
+    pub kind: Option<TransactionKind>,
+    pub outcome: Outcome,
+    pub reason: Option<String>,
+    /// Whether the transaction is one of `ProcessingOptions::adjustments`,
+    /// or was handed to `Engine::adjust`.
+    pub adjustment: bool,
+}
+
+impl TransactionOutcome {
+    pub(crate) fn unparsed(row: usize, error: &ProcessingError) -> TransactionOutcome {
+        TransactionOutcome {
+            row,
+            tx: error.tx(),
+            client: error.client(),
+            kind: None,
+            outcome: Outcome::Rejected,
+            reason: Some(error.to_string()),
+            adjustment: false,
+        }
+    }
+}
+
+/// Why a transaction was left out instead of being applied.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SkipReason {
+    /// Its client did not pass the client filter.
+    FilteredOut,
+    /// Its client went over `ProcessingOptions::max_transactions_per_client`.
+    RateLimited,
+    /// It reached the account of another client.
+    WrongClient,
+}
+
+/// The transactions left out of a run, when given as
+/// `ProcessingOptions::skipped`. Shared with the workers, so they are not
+/// in input order.
+#[derive(Clone, Debug, Default)]
+pub struct SkippedTransactions(Arc<Mutex<Vec<(Transaction, SkipReason)>>>);
+
+impl SkippedTransactions {
+    pub fn transactions(&self) -> Vec<(Transaction, SkipReason)> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub(crate) fn record(&self, transaction: Transaction, reason: SkipReason) {
+        self.0.lock().unwrap().push((transaction, reason));
+    }
+}
+
+/// Receives the outcome of every row when set as
+/// `ProcessingOptions::outcome_sink`. Workers call it as they go, so the
+/// outcomes do not arrive in input order.
+#[derive(Clone)]
+pub struct OutcomeSink(Arc<dyn Fn(TransactionOutcome) + Send + Sync>);
+
+impl OutcomeSink {
+    pub fn new<F: Fn(TransactionOutcome) + Send + Sync + 'static>(f: F) -> OutcomeSink {
+        OutcomeSink(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for OutcomeSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("OutcomeSink")
+    }
+}
+
+pub(crate) fn record_outcome(options: &ProcessingOptions, outcome: TransactionOutcome) {
+    if let Some(OutcomeSink(sink)) = &options.outcome_sink {
+        sink(outcome);
+    }
+}
+
+#[derive(Serialize)]
+struct TxLogRecord<'a> {
+    tx: Option<u32>,
+    client: Option<u32>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    outcome: String,
+    reason: Option<&'a str>,
+    source: &'static str,
+}
+
+/// Writes the outcomes as csv, in input order, with the columns
+/// `tx,client,type,outcome,reason,source`. The source is either `input` or
+/// `adjustment`.
+pub fn write_tx_log<W: Write>(outcomes: &[TransactionOutcome], writer: W) -> Result<(), ProcessingError> {
+    let mut sorted: Vec<&TransactionOutcome> = outcomes.iter().collect();
+    sorted.sort_by_key(|outcome| outcome.row);
+    let mut writer = csv::Writer::from_writer(writer);
+    for outcome in sorted {
+        writer.serialize(TxLogRecord {
+            tx: outcome.tx,
+            client: outcome.client,
+            kind: outcome.kind.map(|kind| kind.to_string()),
+            outcome: outcome.outcome.to_string(),
+            reason: outcome.reason.as_deref(),
+            source: if outcome.adjustment { "adjustment" } else { "input" },
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+    use crate::{execute_transactions_with_options, write_tx_log, ClientFilter, OutcomeSink, ProcessingOptions, Transaction};
+
+    #[test]
+    fn test_tx_log_has_a_row_per_input_row() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\nwithdrawal,1,2,5.0\ndeposit,2,3,1.0\nrefund,1,4,1.0\ndispute,1,1,\nwithdrawal,3,5,1.0";
+        let outcomes = Arc::new(Mutex::new(vec![]));
+        let sink_outcomes = outcomes.clone();
+        let options = ProcessingOptions {
+            outcome_sink: Some(OutcomeSink::new(move |outcome| sink_outcomes.lock().unwrap().push(outcome))),
+            client_filter: Some(ClientFilter::Deny(HashSet::from([2]))),
+            adjustments: vec![Transaction::deposit(1, 9, 2.0)],
+            ignore_withdrawal_without_balance: true,
+            ..Default::default()
+        };
+        execute_transactions_with_options(transactions.as_bytes(), 2, options);
+        let mut log = vec![];
+        write_tx_log(&outcomes.lock().unwrap(), &mut log).unwrap();
+        assert_eq!(String::from_utf8(log).unwrap(), "\
+tx,client,type,outcome,reason,source
+1,1,deposit,applied,,input
+2,1,withdrawal,rejected,\"Not enough founds to withdraw 5 during transaction 2, with available founds 1\",input
+3,2,deposit,ignored,filtered out,input
+,,,rejected,Transaction type refund is invalid,input
+1,1,dispute,applied,,input
+5,3,withdrawal,ignored,nothing deposited,input
+9,1,deposit,applied,,adjustment
+");
+    }
+}
@@ -0,0 +1,47 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Counts the rows after the header, so progress can be shown as a
+/// percentage. The reader is left where it was, ready for the real pass.
+pub fn count_rows<R: Read + Seek>(mut reader: R) -> io::Result<usize> {
+    let start = reader.stream_position()?;
+    let mut buffer = [0u8; 64 * 1024];
+    let mut lines = 0;
+    let mut last = b'\n';
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        lines += buffer[..read].iter().filter(|byte| **byte == b'\n').count();
+        last = buffer[read - 1];
+    }
+    // The last line does not always end with a newline.
+    if last != b'\n' {
+        lines += 1;
+    }
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(lines.saturating_sub(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+    use crate::count_rows;
+
+    #[test]
+    fn test_rows_are_counted_without_the_header() {
+        let mut input = Cursor::new("type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,2.0\nwithdrawal,1,3,0.5");
+        assert_eq!(count_rows(&mut input).unwrap(), 3);
+        let mut header = String::new();
+        input.read_to_string(&mut header).unwrap();
+        assert!(header.starts_with("type,client"));
+    }
+
+    #[test]
+    fn test_trailing_newline_is_not_a_row() {
+        assert_eq!(count_rows(Cursor::new("type,client,tx,amount\ndeposit,1,1,1.0\n")).unwrap(), 1);
+        assert_eq!(count_rows(Cursor::new("")).unwrap(), 0);
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::ops::Range;
+use crate::{ClientStatus, ClientStatusRecord, ProcessingError, ProcessingWarning, SkipReason, StatusTransition, Transaction, TransactionStatus};
+use crate::transaction::{from_minor_units, round, to_minor_units};
+
+/// Everything a run over a batch of transactions produced.
+#[derive(Debug)]
+pub struct ProcessingReport {
+    pub results: Vec<ClientStatus>,
+    pub errors: Vec<ProcessingError>,
+    pub warnings: Vec<ProcessingWarning>,
+    /// Number of transactions that were not dispatched because of the
+    /// client filter.
+    pub filtered_transactions: usize,
+    pub transaction_counts: TransactionCounts,
+    /// Whether reading stopped early because the deadline was reached.
+    pub timed_out: bool,
+    /// How many deposits, withdrawals and fees there were of every amount,
+    /// in minor units.
+    pub(crate) amounts: BTreeMap<i64, u64>,
+    /// Every change of status of the transactions of the audited clients.
+    pub transitions: BTreeMap<u32, Vec<StatusTransition>>,
+    /// The status every transaction ended in, by client and tx id, when
+    /// the run kept them.
+    pub(crate) final_statuses: BTreeMap<u32, BTreeMap<u32, TransactionStatus>>,
+    /// The transactions left out, and why, when the run kept them.
+    pub skipped: Vec<(Transaction, SkipReason)>,
+    /// Checksum of the input, when asked for. It is left empty when the run
+    /// stopped before reading the input to its end, by an error limit, a
+    /// timeout or an interrupt, as it would only cover part of it.
+    pub input_checksum: Option<u32>,
+}
+
+/// How many transactions of every type were dispatched.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TransactionCounts {
+    pub deposits: u64,
+    pub withdrawals: u64,
+    pub fees: u64,
+    pub disputes: u64,
+    pub resolves: u64,
+    pub chargebacks: u64,
+}
+
+/// What reading the input learned about it along the way.
+#[derive(Debug, Default)]
+pub(crate) struct InputStats {
+    pub(crate) filtered_transactions: usize,
+    pub(crate) timed_out: bool,
+    pub(crate) counts: TransactionCounts,
+    pub(crate) amounts: BTreeMap<i64, u64>,
+    pub(crate) checksum: Option<u32>,
+}
+
+impl InputStats {
+    pub(crate) fn record(&mut self, transaction: &Transaction) {
+        let amount = match transaction {
+            Transaction::Deposit { amount, .. } => {
+                self.counts.deposits += 1;
+                Some(amount)
+            }
+            Transaction::Withdrawal { amount, .. } => {
+                self.counts.withdrawals += 1;
+                Some(amount)
+            }
+            Transaction::Fee { amount, .. } => {
+                self.counts.fees += 1;
+                Some(amount)
+            }
+            Transaction::Dispute { .. } => {
+                self.counts.disputes += 1;
+                None
+            }
+            Transaction::Resolve { .. } | Transaction::ResolveAll { .. } => {
+                self.counts.resolves += 1;
+                None
+            }
+            Transaction::Chargeback { .. } | Transaction::ChargebackAll { .. } => {
+                self.counts.chargebacks += 1;
+                None
+            }
+        };
+        if let Some(amount) = amount {
+            *self.amounts.entry(to_minor_units(*amount)).or_default() += 1;
+        }
+    }
+}
+
+/// The funds of every client netted into a single record.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SettlementSummary {
+    pub total_available: f32,
+    pub total_held: f32,
+    pub total_total: f32,
+    pub locked_count: usize,
+    pub client_count: usize,
+}
+
+/// Half of the smallest unit amounts are rounded to, so amounts printed
+/// with a different number of decimals still match.
+pub const DEFAULT_EPSILON: f32 = 0.00005;
+
+/// How a report differs from another one, with clients ordered by id.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReportDiff {
+    /// Clients present in both reports with different balances, as the
+    /// status in this report followed by the one in the other.
+    pub changed: Vec<(ClientStatus, ClientStatus)>,
+    pub only_in_self: Vec<ClientStatus>,
+    pub only_in_other: Vec<ClientStatus>,
+    /// The error counts of both reports, when they differ.
+    pub error_counts: Option<(usize, usize)>,
+}
+
+impl ReportDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.only_in_self.is_empty() && self.only_in_other.is_empty() && self.error_counts.is_none()
+    }
+}
+
+impl ProcessingReport {
+    /// Counts the deposits, withdrawals and fees falling between every two
+    /// consecutive `buckets`, lower bound included. Amounts outside of the
+    /// buckets are left out.
+    pub fn amount_histogram(&self, buckets: &[f32]) -> Vec<(Range<f32>, u64)> {
+        buckets.windows(2)
+            .map(|bounds| {
+                let units = to_minor_units(bounds[0])..to_minor_units(bounds[1]);
+                // Buckets out of order are empty, rather than a panic.
+                let count = if units.start <= units.end {
+                    self.amounts.range(units.clone()).map(|(_, count)| count).sum()
+                } else {
+                    0
+                };
+                (from_minor_units(units.start)..from_minor_units(units.end), count)
+            })
+            .collect()
+    }
+
+    /// The client and tx id of every transaction that ended in `status`,
+    /// in that order. Empty unless the run was given
+    /// `ProcessingOptions::final_statuses`.
+    pub fn transactions_with_status(&self, status: TransactionStatus) -> Vec<(u32, u32)> {
+        self.final_statuses.iter()
+            .flat_map(|(client, statuses)| statuses.iter()
+                .filter(move |(_, s)| **s == status)
+                .map(move |(tx, _)| (*client, *tx)))
+            .collect()
+    }
+
+    pub fn diff(&self, other: &ProcessingReport) -> ReportDiff {
+        let mut diff = diff_results(&self.results, &other.results, |status, other| status == other);
+        if self.errors.len() != other.errors.len() {
+            diff.error_counts = Some((self.errors.len(), other.errors.len()));
+        }
+        diff
+    }
+
+    /// Compares the results against the `expected` ones, as read by
+    /// [`read_expected_results`]. Only the output columns are compared,
+    /// with amounts matching when they are less than `epsilon` apart.
+    pub fn diff_expected(&self, expected: &[ClientStatus], epsilon: f32) -> ReportDiff {
+        let close = |a: f32, b: f32| (a - b).abs() < epsilon;
+        diff_results(&self.results, expected, |status, expected| {
+            close(status.available, expected.available) && close(status.held, expected.held)
+                && close(status.total, expected.total) && status.locked == expected.locked
+        })
+    }
+
+    pub fn settlement_summary(&self) -> SettlementSummary {
+        let mut summary = self.results.iter().fold(SettlementSummary {
+            total_available: 0f32,
+            total_held: 0f32,
+            total_total: 0f32,
+            locked_count: 0,
+            client_count: 0,
+        }, |mut summary, status| {
+            summary.total_available += status.available;
+            summary.total_held += status.held;
+            summary.total_total += status.total;
+            summary.locked_count += status.locked as usize;
+            summary.client_count += 1;
+            summary
+        });
+        summary.total_available = round(summary.total_available);
+        summary.total_held = round(summary.total_held);
+        summary.total_total = round(summary.total_total);
+        summary
+    }
+}
+
+fn diff_results<F: Fn(&ClientStatus, &ClientStatus) -> bool>(
+    results: &[ClientStatus],
+    others: &[ClientStatus],
+    same: F,
+) -> ReportDiff {
+    let mut others: HashMap<u32, &ClientStatus> = others.iter().map(|status| (status.id, status)).collect();
+    let mut diff = ReportDiff::default();
+    for status in results {
+        match others.remove(&status.id) {
+            Some(other_status) if !same(status, other_status) => diff.changed.push((status.clone(), other_status.clone())),
+            Some(_) => {}
+            None => diff.only_in_self.push(status.clone()),
+        }
+    }
+    diff.only_in_other = others.into_values().cloned().collect();
+    diff.changed.sort_by_key(|(status, _)| status.id);
+    diff.only_in_self.sort_by_key(|status| status.id);
+    diff.only_in_other.sort_by_key(|status| status.id);
+    diff
+}
+
+/// Reads results in the output format, like a file of expected results.
+/// Only the output columns are known, so the rest is left empty.
+pub fn read_expected_results<R: Read>(reader: R) -> Result<Vec<ClientStatus>, ProcessingError> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+    reader.deserialize::<ClientStatusRecord>()
+        .map(|record| {
+            let record = record?;
+            Ok(ClientStatus {
+                id: record.client,
+                available: record.available,
+                held: record.held,
+                held_from_withdrawals: 0f32,
+                total: record.total,
+                locked: record.locked,
+                last_activity: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{execute_transactions_with_options, ProcessingOptions, TransactionStatus};
+    use crate::report::{read_expected_results, ReportDiff, SettlementSummary, TransactionCounts, DEFAULT_EPSILON};
+    use crate::ClientStatus;
+
+    const TRANSACTIONS: &str = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\ndeposit,1,3,2.0\nwithdrawal,1,4,1.5";
+
+    #[test]
+    fn test_settlement_summary_nets_all_clients() {
+        let transactions = "type, client,tx,amount\ndeposit, 1,1,1.0\ndeposit,2,2,2.0\ndeposit,1,3,2.0\nwithdrawal,1,4,1.5\ndispute,2,2,0\n";
+        let report = execute_transactions_with_options(transactions.as_bytes(), 1, ProcessingOptions::default());
+        assert_eq!(report.settlement_summary(), SettlementSummary {
+            total_available: 1.5,
+            total_held: 2.0,
+            total_total: 3.5,
+            locked_count: 0,
+            client_count: 2,
+        });
+    }
+
+    #[test]
+    fn test_report_has_no_diff_with_itself() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\nfoo,1,3,1.0";
+        let report = execute_transactions_with_options(transactions.as_bytes(), 1, ProcessingOptions::default());
+        assert!(report.diff(&report).is_empty());
+    }
+
+    #[test]
+    fn test_diff_lists_every_difference() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\ndeposit,3,3,3.0\nfoo,1,4,1.0";
+        let report = execute_transactions_with_options(transactions.as_bytes(), 1, ProcessingOptions::default());
+        let mut mutated = execute_transactions_with_options(transactions.as_bytes(), 1, ProcessingOptions::default());
+        mutated.results.retain(|status| status.id != 3);
+        mutated.results.push(ClientStatus { id: 4, available: 4.0, held: 0.0, held_from_withdrawals: 0.0, total: 4.0, locked: false, last_activity: None });
+        mutated.results.iter_mut().filter(|status| status.id == 1).for_each(|status| status.locked = true);
+        mutated.errors.clear();
+
+        assert_eq!(report.diff(&mutated), ReportDiff {
+            changed: vec![(
+                ClientStatus { id: 1, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: false, last_activity: None },
+                ClientStatus { id: 1, available: 1.0, held: 0.0, held_from_withdrawals: 0.0, total: 1.0, locked: true, last_activity: None },
+            )],
+            only_in_self: vec![ClientStatus { id: 3, available: 3.0, held: 0.0, held_from_withdrawals: 0.0, total: 3.0, locked: false, last_activity: None }],
+            only_in_other: vec![ClientStatus { id: 4, available: 4.0, held: 0.0, held_from_withdrawals: 0.0, total: 4.0, locked: false, last_activity: None }],
+            error_counts: Some((1, 0)),
+        });
+    }
+
+    #[test]
+    fn test_amounts_are_bucketed_and_types_counted() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,1,2,5.0\ndeposit,2,3,10.0\nwithdrawal,1,4,0.5\n\
+            fee,1,5,0.1\ndispute,2,3,0\nchargeback,2,3,0\ndeposit,3,6,1000.0";
+        let report = execute_transactions_with_options(transactions.as_bytes(), 1, ProcessingOptions::default());
+        assert_eq!(report.transaction_counts, TransactionCounts {
+            deposits: 4,
+            withdrawals: 1,
+            fees: 1,
+            disputes: 1,
+            resolves: 0,
+            chargebacks: 1,
+        });
+        assert_eq!(report.amount_histogram(&[0.0, 1.0, 10.0, 100.0]), vec![
+            (0.0..1.0, 2),
+            (1.0..10.0, 2),
+            (10.0..100.0, 1),
+        ]);
+    }
+
+    #[test]
+    fn test_matching_expectation_has_no_diff() {
+        let report = execute_transactions_with_options(TRANSACTIONS.as_bytes(), 1, ProcessingOptions::default());
+        let expected = read_expected_results("client,available,held,total,locked\n2,2,0,2,false\n1,1.50000,0.0,1.5,false\n".as_bytes()).unwrap();
+        assert!(report.diff_expected(&expected, DEFAULT_EPSILON).is_empty());
+    }
+
+    #[test]
+    fn test_mismatched_expectation_is_listed() {
+        let report = execute_transactions_with_options(TRANSACTIONS.as_bytes(), 1, ProcessingOptions::default());
+        let expected = read_expected_results("client,available,held,total,locked\n1,1.5001,0,1.5001,false\n3,0,0,0,false\n".as_bytes()).unwrap();
+        let diff = report.diff_expected(&expected, DEFAULT_EPSILON);
+        assert_eq!(diff.changed.iter().map(|(status, _)| status.id).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(diff.only_in_self.iter().map(|status| status.id).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(diff.only_in_other.iter().map(|status| status.id).collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn test_transactions_are_listed_by_final_status() {
+        let options = ProcessingOptions { final_statuses: Some(Default::default()), ..Default::default() };
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\ndeposit,2,2,2.0\ndeposit,2,3,1.0\ndispute,2,3,\nchargeback,2,3,\ndispute,1,1,\nchargeback,1,1,\ndeposit,3,4,1.0";
+        let report = execute_transactions_with_options(transactions.as_bytes(), 2, options);
+        assert_eq!(report.transactions_with_status(TransactionStatus::Chargeback), vec![(1, 1), (2, 3)]);
+        assert_eq!(report.transactions_with_status(TransactionStatus::Deposited), vec![(2, 2), (3, 4)]);
+        let report = execute_transactions_with_options(transactions.as_bytes(), 2, ProcessingOptions::default());
+        assert!(report.transactions_with_status(TransactionStatus::Chargeback).is_empty());
+    }
+
+    #[test]
+    fn test_buckets_out_of_order_are_empty() {
+        let report = execute_transactions_with_options(TRANSACTIONS.as_bytes(), 1, ProcessingOptions::default());
+        assert_eq!(report.amount_histogram(&[10.0, 1.0]), vec![(10.0..1.0, 0)]);
+    }
+}
@@ -0,0 +1,258 @@
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use crate::binary::BinaryReader;
+use crate::checksum::{Checksum, ChecksumReader};
+use crate::client_status::Message;
+use crate::report::InputStats;
+use crate::{read_source, read_transactions, ProcessingError, ProcessingOptions, ProcessingWarning, Transaction, CsvRows, Row};
+
+/// Anything transactions can be read from, one after the other, such as a
+/// queue or a database cursor. `None` means there are no more.
+///
+/// The transactions go through the same checks as the rows of a csv input,
+/// which also round their amounts, so amounts are best handed over as they
+/// were read rather than through constructors like [`Transaction::deposit`].
+pub trait TransactionSource {
+    fn next(&mut self) -> Option<Result<Transaction, ProcessingError>>;
+
+    /// Takes the warnings raised while reading the transaction `next`
+    /// returned last, which are added to the report. A
+    /// [`ProcessingWarning::Quarantined`] among them means that transaction
+    /// was assigned to the quarantine client.
+    fn take_warnings(&mut self) -> Vec<ProcessingWarning> {
+        Vec::new()
+    }
+
+    /// The timestamp of the transaction `next` returned last, if it had one.
+    fn timestamp(&self) -> Option<u64> {
+        None
+    }
+
+    /// The line the transaction `next` returned last was read from, if it
+    /// was read from lines.
+    fn line(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl<R: Read> TransactionSource for BinaryReader<R> {
+    fn next(&mut self) -> Option<Result<Transaction, ProcessingError>> {
+        self.next_record()
+    }
+}
+
+/// Reads the transactions of a csv input the way a run reading it directly
+/// does, with the default options unless others are given.
+pub struct CsvSource<R> {
+    rows: CsvRows<R>,
+    options: ProcessingOptions,
+    warnings: Mutex<Vec<ProcessingWarning>>,
+    timestamp: Option<u64>,
+    line: Option<u64>,
+}
+
+impl<R: Read> CsvSource<R> {
+    pub fn new(reader: R) -> Result<CsvSource<R>, ProcessingError> {
+        CsvSource::with_options(reader, ProcessingOptions::default())
+    }
+
+    /// Like [`CsvSource::new`], reading the input with the given options,
+    /// from its schema marker and columns down to its last row.
+    pub fn with_options(reader: R, options: ProcessingOptions) -> Result<CsvSource<R>, ProcessingError> {
+        let rows = CsvRows::new(reader, &options)?;
+        Ok(CsvSource { rows, options, warnings: Mutex::new(vec![]), timestamp: None, line: None })
+    }
+}
+
+impl<R: Read> TransactionSource for CsvSource<R> {
+    fn next(&mut self) -> Option<Result<Transaction, ProcessingError>> {
+        loop {
+            match self.rows.next_row(&self.options, &self.warnings) {
+                Row::Read { line, timestamp, transaction, .. } => {
+                    (self.line, self.timestamp) = (Some(line), timestamp);
+                    return Some(Ok(transaction));
+                }
+                Row::Failed(e) => return Some(Err(e)),
+                Row::Ignored => {}
+                Row::End => return None,
+            }
+        }
+    }
+
+    fn take_warnings(&mut self) -> Vec<ProcessingWarning> {
+        std::mem::take(self.warnings.get_mut().unwrap())
+    }
+
+    fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    fn line(&self) -> Option<u64> {
+        self.line
+    }
+}
+
+/// What a run reads from: a csv or binary input, or a source of its own.
+pub(crate) trait Input {
+    fn read_into<F: FnMut(Message)>(
+        self,
+        errors: &Arc<Mutex<Vec<ProcessingError>>>,
+        warnings: &Arc<Mutex<Vec<ProcessingWarning>>>,
+        options: &ProcessingOptions,
+        dispatch: F,
+    ) -> InputStats;
+}
+
+impl<R: Read> Input for R {
+    fn read_into<F: FnMut(Message)>(
+        self,
+        errors: &Arc<Mutex<Vec<ProcessingError>>>,
+        warnings: &Arc<Mutex<Vec<ProcessingWarning>>>,
+        options: &ProcessingOptions,
+        dispatch: F,
+    ) -> InputStats {
+        let Some(algorithm) = options.checksum else {
+            return read_transactions(self, errors, warnings, options, dispatch);
+        };
+        let mut checksum = Checksum::new(algorithm);
+        let mut stats = read_transactions(ChecksumReader::new(self, &mut checksum), errors, warnings, options, dispatch);
+        stats.checksum = checksum.input_value();
+        stats
+    }
+}
+
+pub(crate) struct FromSource<S>(pub(crate) S);
+
+impl<S: TransactionSource> Input for FromSource<S> {
+    fn read_into<F: FnMut(Message)>(
+        self,
+        errors: &Arc<Mutex<Vec<ProcessingError>>>,
+        warnings: &Arc<Mutex<Vec<ProcessingWarning>>>,
+        options: &ProcessingOptions,
+        dispatch: F,
+    ) -> InputStats {
+        if options.checksum.is_some() {
+            errors.lock().unwrap().push(ProcessingError::ChecksumUnsupported);
+            return InputStats::default();
+        }
+        read_source(self.0, errors, warnings, options, dispatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashSet, VecDeque};
+    use std::sync::Arc;
+    use crate::{
+        execute_source, execute_transactions_with_options, CsvSource, FixedClock, ProcessingError, ProcessingOptions, ProcessingReport,
+        ProcessingWarning, TrailingRowPolicy, Transaction, TransactionParseError, TransactionSource,
+    };
+    use crate::client_status::ClientStatusError;
+
+    /// Transactions handed over from memory, like a queue would.
+    struct Queue(VecDeque<Transaction>);
+
+    impl TransactionSource for Queue {
+        fn next(&mut self) -> Option<Result<Transaction, ProcessingError>> {
+            self.0.pop_front().map(Ok)
+        }
+    }
+
+    #[test]
+    fn test_transactions_can_come_from_any_source() {
+        let queue = Queue(VecDeque::from([
+            Transaction::deposit(1, 1, 2.0),
+            Transaction::deposit(2, 2, 1.0),
+            Transaction::withdrawal(1, 3, 0.5),
+            Transaction::withdrawal(2, 4, 3.0),
+        ]));
+        let mut report = execute_source(queue, 2, ProcessingOptions::default());
+        report.results.sort_by_key(|status| status.id);
+        let totals: Vec<f32> = report.results.iter().map(|status| status.total).collect();
+        assert_eq!(totals, vec![1.5, 1.0]);
+        assert!(matches!(report.errors[..], [ProcessingError::ClientAt { client: 2, row: 3, line: None, source: ClientStatusError::InsufficientFounds(..) }]));
+        assert_eq!(report.transaction_counts.withdrawals, 2);
+    }
+
+    #[test]
+    fn test_source_transactions_are_checked_like_csv_rows() {
+        let queue = Queue(VecDeque::from([
+            Transaction::Deposit { client: 1, tx: 1, amount: 1.23456, raw_amount: None },
+            Transaction::deposit(1, 2, 1.0),
+            Transaction::deposit(9, 3, 1.0),
+        ]));
+        let options = ProcessingOptions {
+            reserved_tx_ids: HashSet::from([2]),
+            quarantine_client: Some(9),
+            retain_raw_amounts: true,
+            warn_on_precision_loss: true,
+            ..Default::default()
+        };
+        let report = execute_source(queue, 1, options);
+        assert_eq!(report.results.iter().map(|status| status.total).collect::<Vec<f32>>(), vec![1.2346]);
+        assert_eq!(report.warnings, vec![ProcessingWarning::PrecisionTruncated { tx: 1, original: 1.23456, rounded: 1.2346 }]);
+        assert!(matches!(report.errors[..], [
+            ProcessingError::Parse(TransactionParseError::ReservedTxId(2)),
+            ProcessingError::QuarantineClientInUse(3, 9),
+        ]));
+    }
+
+    #[test]
+    fn test_subprecision_amounts_are_rejected_from_sources() {
+        let queue = Queue(VecDeque::from([
+            Transaction::Deposit { client: 1, tx: 1, amount: 0.00001, raw_amount: None },
+            Transaction::Withdrawal { client: 1, tx: 2, amount: 0.00004, raw_amount: None },
+        ]));
+        let options = ProcessingOptions { reject_subprecision_amounts: true, ..Default::default() };
+        let report = execute_source(queue, 1, options);
+        assert!(matches!(report.errors[..], [
+            ProcessingError::Client(ClientStatusError::SubPrecisionAmount(1)),
+            ProcessingError::Client(ClientStatusError::SubPrecisionAmount(2)),
+        ]));
+    }
+
+    #[test]
+    fn test_csv_source_reads_like_csv_input() {
+        let transactions = "type,client,tx,amount\ndeposit,1,1,1.0\nfoo,1,2,1.0\ndeposit,2,3,2.0";
+        let mut from_source = execute_source(CsvSource::new(transactions.as_bytes()).unwrap(), 2, ProcessingOptions::default());
+        let mut from_reader = execute_transactions_with_options(transactions.as_bytes(), 2, ProcessingOptions::default());
+        from_source.results.sort_by_key(|status| status.id);
+        from_reader.results.sort_by_key(|status| status.id);
+        assert_eq!(from_source.results, from_reader.results);
+        assert_eq!(from_source.errors.len(), 1);
+    }
+
+    #[test]
+    fn test_csv_source_reads_the_input_with_its_options() {
+        let transactions = "# schema: v2\nTYPE,Client,tx,amount,timestamp\ndeposit,1,1,2.0,100\ndeposit,1,2,1.0,9999\nwithdrawal,1,3,0.5,200\ndeposit,1,4,1.0,300";
+        let options = ProcessingOptions {
+            expected_schema: Some("v2".to_owned()),
+            case_insensitive_headers: true,
+            reject_future_timestamps: true,
+            clock: Arc::new(FixedClock(1000)),
+            on_trailing_partial_row: TrailingRowPolicy::Ignore,
+            ..Default::default()
+        };
+        let source = CsvSource::with_options(transactions.as_bytes(), options.clone()).unwrap();
+        let from_source = execute_source(source, 1, options.clone());
+        let from_reader = execute_transactions_with_options(transactions.as_bytes(), 1, options.clone());
+        assert_eq!(from_source.results, from_reader.results);
+        assert_eq!(from_source.results[0].total, 1.5);
+        assert_eq!(from_source.results[0].last_activity(), Some(200));
+        let errors = |report: &ProcessingReport| report.errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(errors(&from_source), errors(&from_reader));
+        assert_eq!(from_source.errors.len(), 1);
+        let other_schema = ProcessingOptions { expected_schema: Some("v3".to_owned()), ..options };
+        assert!(matches!(CsvSource::with_options(transactions.as_bytes(), other_schema), Err(ProcessingError::SchemaMismatch { .. })));
+    }
+
+    #[test]
+    fn test_csv_source_quarantines_like_csv_input() {
+        let transactions = "type,client,tx,amount\ndeposit,x,1,1.0\ndeposit,9,2,1.0";
+        let options = ProcessingOptions { quarantine_client: Some(9), ..Default::default() };
+        let report = execute_source(CsvSource::with_options(transactions.as_bytes(), options.clone()).unwrap(), 1, options);
+        assert_eq!(report.results.iter().map(|status| (status.id, status.total)).collect::<Vec<_>>(), vec![(9, 1.0)]);
+        assert_eq!(report.warnings, vec![ProcessingWarning::Quarantined { tx: 1, client: "x".to_owned() }]);
+        assert!(matches!(report.errors[..], [ProcessingError::QuarantineClientInUse(2, 9)]));
+    }
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use serde::Deserialize;
 use thiserror::Error;
 
@@ -5,22 +6,94 @@ use thiserror::Error;
 pub struct RawTransaction {
     #[serde(rename(serialize = "type", deserialize = "type"))]
     transaction_type: String,
-    client: u32,
-    tx: u32,
-    amount: f32,
+    pub(crate) client: u32,
+    /// Left empty by the operations on every dispute of a client.
+    pub(crate) tx: Option<u32>,
+    /// Left empty by disputes, resolves and chargebacks.
+    amount: Option<f32>,
+    #[serde(skip)]
+    pub(crate) raw_amount: Option<String>,
 }
 
-#[derive(Debug, PartialEq)]
+impl RawTransaction {
+    pub(crate) fn new(transaction_type: String, client: u32, tx: u32, amount: Option<f32>) -> RawTransaction {
+        RawTransaction { transaction_type, client, tx: Some(tx), amount, raw_amount: None }
+    }
+}
+
+/// The type of a transaction, without its data.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TransactionKind {
+    Deposit,
+    Withdrawal,
+    Fee,
+    Dispute,
+    Resolve,
+    Chargeback,
+    ResolveAll,
+    ChargebackAll,
+}
+
+impl TransactionKind {
+    pub const ALL: [TransactionKind; 8] = [
+        TransactionKind::Deposit,
+        TransactionKind::Withdrawal,
+        TransactionKind::Fee,
+        TransactionKind::Dispute,
+        TransactionKind::Resolve,
+        TransactionKind::Chargeback,
+        TransactionKind::ResolveAll,
+        TransactionKind::ChargebackAll,
+    ];
+
+    /// Whether it applies to every dispute of the client, without a
+    /// transaction id of its own.
+    pub fn is_bulk(&self) -> bool {
+        matches!(self, TransactionKind::ResolveAll | TransactionKind::ChargebackAll)
+    }
+}
+
+impl fmt::Display for TransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            TransactionKind::Deposit => "deposit",
+            TransactionKind::Withdrawal => "withdrawal",
+            TransactionKind::Fee => "fee",
+            TransactionKind::Dispute => "dispute",
+            TransactionKind::Resolve => "resolve",
+            TransactionKind::Chargeback => "chargeback",
+            TransactionKind::ResolveAll => "resolve_all",
+            TransactionKind::ChargebackAll => "chargeback_all",
+        };
+        f.write_str(kind)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Transaction {
     Deposit {
         client: u32,
         tx: u32,
         amount: f32,
+        /// The amount as it appeared in the input, when retained.
+        raw_amount: Option<String>,
     },
     Withdrawal {
         client: u32,
         tx: u32,
         amount: f32,
+        /// The amount as it appeared in the input, when retained.
+        raw_amount: Option<String>,
+    },
+    /// A fee charged to the client, like the one of a withdrawal. It has
+    /// its own transaction id, so it can be disputed apart from whatever
+    /// it was charged for.
+    Fee {
+        client: u32,
+        tx: u32,
+        amount: f32,
+        /// The amount as it appeared in the input, when retained.
+        raw_amount: Option<String>,
     },
     Dispute {
         client: u32,
@@ -34,14 +107,128 @@ pub enum Transaction {
         client: u32,
         tx: u32
     },
+    /// Resolves every transaction of the client currently on dispute.
+    ResolveAll {
+        client: u32,
+    },
+    /// Charges back every transaction of the client currently on dispute,
+    /// locking the account once all of them are.
+    ChargebackAll {
+        client: u32,
+    },
 }
 
 impl Transaction {
+    /// The amount is rounded like the ones read from the input, as the
+    /// balances are always compared against rounded amounts.
+    pub fn deposit(client: u32, tx: u32, amount: f32) -> Transaction {
+        Transaction::Deposit { client, tx, amount: round(amount), raw_amount: None }
+    }
+
+    pub fn withdrawal(client: u32, tx: u32, amount: f32) -> Transaction {
+        Transaction::Withdrawal { client, tx, amount: round(amount), raw_amount: None }
+    }
+
+    pub fn fee(client: u32, tx: u32, amount: f32) -> Transaction {
+        Transaction::Fee { client, tx, amount: round(amount), raw_amount: None }
+    }
+
+    pub fn dispute(client: u32, tx: u32) -> Transaction {
+        Transaction::Dispute { client, tx }
+    }
+
+    pub fn resolve(client: u32, tx: u32) -> Transaction {
+        Transaction::Resolve { client, tx }
+    }
+
+    pub fn chargeback(client: u32, tx: u32) -> Transaction {
+        Transaction::Chargeback { client, tx }
+    }
+
+    pub fn resolve_all(client: u32) -> Transaction {
+        Transaction::ResolveAll { client }
+    }
+
+    pub fn chargeback_all(client: u32) -> Transaction {
+        Transaction::ChargebackAll { client }
+    }
+
+    pub fn kind(&self) -> TransactionKind {
+        match self {
+            Transaction::Deposit { .. } => TransactionKind::Deposit,
+            Transaction::Withdrawal { .. } => TransactionKind::Withdrawal,
+            Transaction::Fee { .. } => TransactionKind::Fee,
+            Transaction::Dispute { .. } => TransactionKind::Dispute,
+            Transaction::Resolve { .. } => TransactionKind::Resolve,
+            Transaction::Chargeback { .. } => TransactionKind::Chargeback,
+            Transaction::ResolveAll { .. } => TransactionKind::ResolveAll,
+            Transaction::ChargebackAll { .. } => TransactionKind::ChargebackAll,
+        }
+    }
+
     pub fn get_client(&self) -> u32 {
         match &self {
-            Transaction::Deposit { client, .. } | Transaction::Withdrawal { client, ..} |
+            Transaction::Deposit { client, .. } | Transaction::Withdrawal { client, ..} | Transaction::Fee { client, .. } |
             Transaction::Dispute { client, .. } | Transaction::Resolve { client, .. } |
-            Transaction::Chargeback { client, .. } => *client,
+            Transaction::Chargeback { client, .. } | Transaction::ResolveAll { client } |
+            Transaction::ChargebackAll { client } => *client,
+        }
+    }
+
+    /// The amount of a deposit, withdrawal or fee, along with the amount
+    /// as it appeared in the input.
+    pub(crate) fn amount_mut(&mut self) -> Option<(&mut f32, &mut Option<String>)> {
+        match self {
+            Transaction::Deposit { amount, raw_amount, .. } | Transaction::Withdrawal { amount, raw_amount, .. } |
+            Transaction::Fee { amount, raw_amount, .. } => Some((amount, raw_amount)),
+            _ => None,
+        }
+    }
+
+    /// The bulk operations have no transaction id of their own, so they give 0.
+    pub fn get_tx(&self) -> u32 {
+        match &self {
+            Transaction::Deposit { tx, .. } | Transaction::Withdrawal { tx, ..} | Transaction::Fee { tx, .. } |
+            Transaction::Dispute { tx, .. } | Transaction::Resolve { tx, .. } |
+            Transaction::Chargeback { tx, .. } => *tx,
+            Transaction::ResolveAll { .. } | Transaction::ChargebackAll { .. } => 0,
+        }
+    }
+
+    /// Renders the transaction as a row with the same columns it is read
+    /// from. Transactions without an amount or a tx leave those columns empty.
+    pub fn to_record(&self) -> Vec<String> {
+        let tx = if self.kind().is_bulk() { String::new() } else { self.get_tx().to_string() };
+        let (transaction_type, amount) = match &self {
+            Transaction::Deposit { amount, .. } => ("deposit", amount.to_string()),
+            Transaction::Withdrawal { amount, .. } => ("withdrawal", amount.to_string()),
+            Transaction::Fee { amount, .. } => ("fee", amount.to_string()),
+            Transaction::Dispute { .. } => ("dispute", String::new()),
+            Transaction::Resolve { .. } => ("resolve", String::new()),
+            Transaction::Chargeback { .. } => ("chargeback", String::new()),
+            Transaction::ResolveAll { .. } => ("resolve_all", String::new()),
+            Transaction::ChargebackAll { .. } => ("chargeback_all", String::new()),
+        };
+        vec![
+            transaction_type.to_owned(),
+            self.get_client().to_string(),
+            tx,
+            amount,
+        ]
+    }
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transaction::Deposit { client, tx, amount, .. } => write!(f, "deposit(client={}, tx={}, amount={:?})", client, tx, amount),
+            Transaction::Withdrawal { client, tx, amount, .. } => write!(f, "withdrawal(client={}, tx={}, amount={:?})", client, tx, amount),
+            Transaction::Fee { client, tx, amount, .. } => write!(f, "fee(client={}, tx={}, amount={:?})", client, tx, amount),
+            Transaction::Dispute { client, tx } => write!(f, "dispute(client={}, tx={})", client, tx),
+            Transaction::Resolve { client, tx } => write!(f, "resolve(client={}, tx={})", client, tx),
+            Transaction::Chargeback { client, tx } => write!(f, "chargeback(client={}, tx={})", client, tx),
+            Transaction::ResolveAll { client } => write!(f, "resolve_all(client={})", client),
+            Transaction::ChargebackAll { client } => write!(f, "chargeback_all(client={})", client),
         }
     }
 }
@@ -50,41 +237,99 @@ impl Transaction {
 pub enum TransactionParseError {
     #[error("Transaction type {0} is invalid")]
     InvalidTransactionType(String),
+    #[error("Transaction ID {0} is reserved")]
+    ReservedTxId(u32),
+    #[error("Timestamp {0} is not a number of seconds since the Unix epoch")]
+    InvalidTimestamp(String),
+    #[error("Timestamp {0} is in the future")]
+    FutureTimestamp(u64),
+    #[error("Transaction of type {0} has an empty tx")]
+    MissingTx(String),
+    #[error("Transaction {0} has an empty amount")]
+    MissingAmount(u32),
+    #[error("Transactions of type {0} are not allowed")]
+    TypeNotAllowed(TransactionKind),
+    #[error("Row on line {line} has {got} columns instead of {expected}")]
+    TooFewColumns { expected: usize, got: usize, line: u64 },
 }
 
 const PRECISION: f32 = 10000f32;
 
+/// Number of decimals amounts are computed with.
+pub const DECIMALS: u32 = 4;
+
+/// The largest amount that can still be rounded to the precision, leaving
+/// room for adding the held and available funds together.
+pub(crate) const MAX_AMOUNT: f32 = f32::MAX / PRECISION / 4f32;
+
+/// Rounds to the precision amounts are computed with. Values too large to
+/// scale up have no decimals left to round anyway, so they are returned as
+/// they are, as are infinity and NaN.
 pub fn round(n: f32) -> f32 {
+    if n.is_nan() || n.abs() > f32::MAX / PRECISION {
+        return n;
+    }
     (n * PRECISION).round() / (PRECISION)
 }
 
+/// Rounds to `decimals` places, which can be fewer than amounts are
+/// computed with.
+pub(crate) fn round_to(n: f32, decimals: u32) -> f32 {
+    let scale = 10f64.powi(decimals as i32);
+    ((n as f64 * scale).round() / scale) as f32
+}
+
+/// Converts an amount into an integer count of the smallest unit
+/// representable with the configured precision.
+pub fn to_minor_units(n: f32) -> i64 {
+    (n as f64 * PRECISION as f64).round() as i64
+}
+
+pub fn from_minor_units(n: i64) -> f32 {
+    (n as f64 / PRECISION as f64) as f32
+}
+
+/// The amount is left as read, to be checked and rounded along with the
+/// transactions of every other input before they are dispatched.
 impl TryInto<Transaction> for RawTransaction {
     type Error = TransactionParseError;
 
     fn try_into(self) -> Result<Transaction, Self::Error> {
+        let tx = self.tx.ok_or_else(|| TransactionParseError::MissingTx(self.transaction_type.clone()));
+        let amount = self.amount.ok_or(TransactionParseError::MissingAmount(self.tx.unwrap_or_default()));
         match self.transaction_type.as_str() {
             "deposit" => Ok(Transaction::Deposit {
                 client: self.client,
-                tx: self.tx,
-                amount: round(self.amount),
+                tx: tx?,
+                amount: amount?,
+                raw_amount: self.raw_amount,
             }),
             "withdrawal" => Ok(Transaction::Withdrawal {
                 client: self.client,
-                tx: self.tx,
-                amount: round(self.amount),
+                tx: tx?,
+                amount: amount?,
+                raw_amount: self.raw_amount,
+            }),
+            "fee" => Ok(Transaction::Fee {
+                client: self.client,
+                tx: tx?,
+                amount: amount?,
+                raw_amount: self.raw_amount,
             }),
             "dispute" => Ok(Transaction::Dispute {
                 client: self.client,
-                tx: self.tx,
+                tx: tx?,
             }),
             "resolve" => Ok(Transaction::Resolve {
                 client: self.client,
-                tx: self.tx,
+                tx: tx?,
             }),
             "chargeback" => Ok(Transaction::Chargeback {
                 client: self.client,
-                tx: self.tx,
+                tx: tx?,
             }),
+            "resolve_all" => Ok(Transaction::ResolveAll { client: self.client }),
+            "chargeback_all" => Ok(Transaction::ChargebackAll { client: self.client }),
             s => Err(TransactionParseError::InvalidTransactionType(s.to_owned()))
         }
     }
@@ -97,26 +342,106 @@ pub enum TransactionStatus {
     FailedDeposit,
     FailedWithdrawal,
     OnDispute,
+    /// On dispute, but only part of the amount could be held.
+    PartialHold,
     Resolved,
     Chargeback,
 }
 
+impl TransactionStatus {
+    /// The status written as by `Display`.
+    pub(crate) fn from_name(name: &str) -> Option<TransactionStatus> {
+        let status = match name {
+            "withdrew" => TransactionStatus::Withdrew,
+            "deposited" => TransactionStatus::Deposited,
+            "failed deposit" => TransactionStatus::FailedDeposit,
+            "failed withdrawal" => TransactionStatus::FailedWithdrawal,
+            "on dispute" => TransactionStatus::OnDispute,
+            "partially held" => TransactionStatus::PartialHold,
+            "resolved" => TransactionStatus::Resolved,
+            "charged back" => TransactionStatus::Chargeback,
+            _ => return None,
+        };
+        Some(status)
+    }
+}
+
+impl fmt::Display for TransactionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            TransactionStatus::Withdrew => "withdrew",
+            TransactionStatus::Deposited => "deposited",
+            TransactionStatus::FailedDeposit => "failed deposit",
+            TransactionStatus::FailedWithdrawal => "failed withdrawal",
+            TransactionStatus::OnDispute => "on dispute",
+            TransactionStatus::PartialHold => "partially held",
+            TransactionStatus::Resolved => "resolved",
+            TransactionStatus::Chargeback => "charged back",
+        };
+        f.write_str(status)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::transaction::{RawTransaction, Transaction, TransactionParseError};
+    use crate::transaction::{round, RawTransaction, Transaction, TransactionParseError, TransactionStatus};
+
+    #[test]
+    fn test_round_extreme_values() {
+        assert_eq!(round(f32::MAX), f32::MAX);
+        assert_eq!(round(-f32::MAX), -f32::MAX);
+        assert_eq!(round(1e35), 1e35);
+        assert_eq!(round(1.23456), 1.2346);
+        assert!(round(f32::NAN).is_nan());
+        assert_eq!(round(f32::INFINITY), f32::INFINITY);
+    }
+
+    #[test]
+    fn test_transaction_display() {
+        let transactions = vec![
+            (Transaction::Deposit { client: 1, tx: 3, amount: 2.0, raw_amount: None }, "deposit(client=1, tx=3, amount=2.0)"),
+            (Transaction::Withdrawal { client: 1, tx: 4, amount: 1.5, raw_amount: None }, "withdrawal(client=1, tx=4, amount=1.5)"),
+            (Transaction::Fee { client: 1, tx: 5, amount: 0.5, raw_amount: None }, "fee(client=1, tx=5, amount=0.5)"),
+            (Transaction::Dispute { client: 1, tx: 3 }, "dispute(client=1, tx=3)"),
+            (Transaction::Resolve { client: 1, tx: 3 }, "resolve(client=1, tx=3)"),
+            (Transaction::Chargeback { client: 1, tx: 3 }, "chargeback(client=1, tx=3)"),
+        ];
+        for (transaction, expected) in transactions {
+            assert_eq!(transaction.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_transaction_status_display() {
+        let statuses = vec![
+            (TransactionStatus::Withdrew, "withdrew"),
+            (TransactionStatus::Deposited, "deposited"),
+            (TransactionStatus::FailedDeposit, "failed deposit"),
+            (TransactionStatus::FailedWithdrawal, "failed withdrawal"),
+            (TransactionStatus::OnDispute, "on dispute"),
+            (TransactionStatus::PartialHold, "partially held"),
+            (TransactionStatus::Resolved, "resolved"),
+            (TransactionStatus::Chargeback, "charged back"),
+        ];
+        for (status, expected) in statuses {
+            assert_eq!(status.to_string(), expected);
+        }
+    }
 
     #[test]
     fn test_deposit_deserialization() {
         let raw_transaction = RawTransaction {
             transaction_type: "deposit".to_owned(),
             client: 1,
-            tx: 42,
-            amount: 1.0,
+            tx: Some(42),
+            amount: Some(1.0),
+            raw_amount: None,
         };
         assert_eq!(raw_transaction.try_into(), Ok(Transaction::Deposit {
             client: 1,
             tx: 42,
             amount: 1.0,
+            raw_amount: None,
         }))
     }
 
@@ -125,23 +450,54 @@ mod tests {
         let raw_transaction = RawTransaction {
             transaction_type: "withdrawal".to_owned(),
             client: 1,
-            tx: 42,
-            amount: 1.0,
+            tx: Some(42),
+            amount: Some(1.0),
+            raw_amount: None,
         };
         assert_eq!(raw_transaction.try_into(), Ok(Transaction::Withdrawal {
             client: 1,
             tx: 42,
             amount: 1.0,
+            raw_amount: None,
         }))
     }
 
+    #[test]
+    fn test_raw_amount_is_carried_over() {
+        let raw_transaction = RawTransaction {
+            transaction_type: "deposit".to_owned(),
+            client: 1,
+            tx: Some(42),
+            amount: Some(1.123456),
+            raw_amount: Some("1.123456".to_owned()),
+        };
+        assert_eq!(raw_transaction.try_into(), Ok(Transaction::Deposit {
+            client: 1,
+            tx: 42,
+            amount: 1.123456,
+            raw_amount: Some("1.123456".to_owned()),
+        }))
+    }
+
+    #[test]
+    fn test_bulk_operations_need_no_tx() {
+        let raw_transaction = RawTransaction::new("resolve_all".to_owned(), 1, 0, None);
+        assert_eq!(raw_transaction.try_into(), Ok(Transaction::ResolveAll { client: 1 }));
+        let raw_transaction = RawTransaction { tx: None, ..RawTransaction::new("chargeback_all".to_owned(), 1, 0, None) };
+        assert_eq!(raw_transaction.try_into(), Ok(Transaction::ChargebackAll { client: 1 }));
+        let raw_transaction = RawTransaction { tx: None, ..RawTransaction::new("deposit".to_owned(), 1, 0, Some(1.0)) };
+        let parsed: Result<Transaction, _> = raw_transaction.try_into();
+        assert_eq!(parsed, Err(TransactionParseError::MissingTx("deposit".to_owned())));
+    }
+
     #[test]
     fn test_resolve_deserialization() {
         let raw_transaction = RawTransaction {
             transaction_type: "resolve".to_owned(),
             client: 1,
-            tx: 42,
-            amount: 1.0,
+            tx: Some(42),
+            amount: Some(1.0),
+            raw_amount: None,
         };
         assert_eq!(raw_transaction.try_into(), Ok(Transaction::Resolve {
             client: 1,
@@ -154,8 +510,9 @@ mod tests {
         let raw_transaction = RawTransaction {
             transaction_type: "chargeback".to_owned(),
             client: 1,
-            tx: 42,
-            amount: 1.0,
+            tx: Some(42),
+            amount: Some(1.0),
+            raw_amount: None,
         };
         assert_eq!(raw_transaction.try_into(), Ok(Transaction::Chargeback {
             client: 1,
@@ -168,8 +525,9 @@ mod tests {
         let raw_transaction = RawTransaction {
             transaction_type: "dispute".to_owned(),
             client: 1,
-            tx: 42,
-            amount: 1.0,
+            tx: Some(42),
+            amount: Some(1.0),
+            raw_amount: None,
         };
         assert_eq!(raw_transaction.try_into(), Ok(Transaction::Dispute {
             client: 1,
@@ -182,8 +540,9 @@ mod tests {
         let raw_transaction = RawTransaction {
             transaction_type: "WRONG".to_owned(),
             client: 1,
-            tx: 42,
-            amount: 1.0,
+            tx: Some(42),
+            amount: Some(1.0),
+            raw_amount: None,
         };
         let result: Result<Transaction, TransactionParseError> = raw_transaction.try_into();
         assert_eq!(result, Err(TransactionParseError::InvalidTransactionType(
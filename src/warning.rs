@@ -0,0 +1,42 @@
+use std::fmt;
+
+/// Something worth pointing out about the input that did not stop a
+/// transaction from being processed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProcessingWarning {
+    PrecisionTruncated { tx: u32, original: f32, rounded: f32 },
+    /// The client field could not be read, so the transaction went to the
+    /// quarantine client.
+    Quarantined { tx: u32, client: String },
+    /// The total of the client was negative and reported as zero, along with
+    /// the available funds it was taken off.
+    NegativeTotalClamped { client: u32, total: f32 },
+    /// The thread pool could not have as many threads as requested, so
+    /// fewer were used.
+    ThreadPoolReduced { requested: usize, used: usize },
+    /// The client kept depositing funds and withdrawing them right away,
+    /// as if trying stolen cards out.
+    SuspiciousActivity(u32),
+}
+
+impl fmt::Display for ProcessingWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessingWarning::PrecisionTruncated { tx, original, rounded } => {
+                write!(f, "Amount {} of transaction {} was rounded to {}", original, tx, rounded)
+            }
+            ProcessingWarning::Quarantined { tx, client } => {
+                write!(f, "Transaction {} was quarantined because of its client {:?}", tx, client)
+            }
+            ProcessingWarning::NegativeTotalClamped { client, total } => {
+                write!(f, "Total {} of client {} was reported as 0", total, client)
+            }
+            ProcessingWarning::ThreadPoolReduced { requested, used } => {
+                write!(f, "{} threads were requested but only {} were used", requested, used)
+            }
+            ProcessingWarning::SuspiciousActivity(client) => {
+                write!(f, "Client {} withdraws its deposits right away, over and over", client)
+            }
+        }
+    }
+}